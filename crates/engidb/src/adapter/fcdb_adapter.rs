@@ -0,0 +1,387 @@
+//! `GraphAdapter` on FCDB's content-addressed pack store.
+//!
+//! Everything the adapter stores lives in an `fcdb_cas::PackCAS`: append-only
+//! pack files of objects addressed by their BLAKE3 `fcdb_core::Cid`. The
+//! current state is held by an `InMemoryAdapter`. Every change is applied to
+//! it and then appended, with whether it succeeded, to a log of DAG-CBOR
+//! entries, each one a CAS object linking the entry before it. The `HEAD`
+//! file next to the packs names the latest entry.
+//!
+//! Every `CHECKPOINT_INTERVAL` changes the whole state is stored as well: each
+//! block, including the nodes, edge records, incidences and commit snapshots
+//! the state is made of, becomes a CAS object, and a checkpoint object holds
+//! an `Image` of the state along with the objects of its blocks. Opening a
+//! store loads the latest checkpoint and replays only the entries after it,
+//! checking every object against its CID and every change against the
+//! outcome it had. Entries and checkpoints carry `LOG_FORMAT`; stores written
+//! in another format are refused rather than misread.
+//!
+//! The published fcdb-cas 0.1.0 cannot read back the objects it stores, so
+//! the workspace patches it with the fix under `vendor/fcdb-cas`. The
+//! fcdb-graph and fcdb-concur crates are not used: `fcdb_graph::GraphDB`
+//! keeps its indexes in memory only and has no history, and fcdb-concur
+//! offers capability leases rather than transactions over a store, so the
+//! graph state, history and transactions are those of `InMemoryAdapter`.
+//!
+//! The sled-based adapter this one replaced kept node ids and adjacency under
+//! `fcdb_data` but no node content, so its stores cannot be migrated and
+//! opening a directory holding one fails.
+
+use super::GraphAdapter;
+use crate::adjacency::{Adjacency, Direction};
+use crate::memory::{Image, InMemoryAdapter};
+use crate::scan::{NodeFilter, NodePage};
+use crate::temporal::now;
+use crate::transact::TxOp;
+use crate::{Error, Result};
+use cid::Cid;
+use fcdb_cas::{PackBand, PackCAS};
+use kotoba_types::{Graph, Node};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+
+/// Format of the log entries and checkpoints written by this version.
+const LOG_FORMAT: u32 = 1;
+/// Number of log entries after which the state is checkpointed.
+const CHECKPOINT_INTERVAL: usize = 512;
+
+/// Object kind of log entries.
+const LOG_ENTRY: u8 = 0x10;
+/// Object kind of blocks.
+const BLOCK: u8 = 0x11;
+/// Object kind of checkpoints.
+const CHECKPOINT: u8 = 0x12;
+
+/// A change to the store, as recorded in the log.
+#[derive(Serialize, Deserialize)]
+enum Change {
+    AddVertex(Node),
+    AddEdge { source: u64, kind: String, target: u64 },
+    ImportGraph(Graph),
+    Transact(Vec<TxOp>),
+    Commit { branch: String, author: String, message: String, timestamp: u64 },
+    CreateBranch { name: String, from: String },
+    DeleteBranch { name: String },
+    ResetBranch { name: String, rev: String },
+    PutBlock { cid: Cid, object: fcdb_core::Cid },
+    /// The whole state at this point of the log, in a checkpoint object.
+    /// Replaying starts from the latest one.
+    Checkpoint(fcdb_core::Cid),
+}
+
+#[derive(Serialize, Deserialize)]
+struct LogEntry {
+    format: u32,
+    /// The entry before this one; `None` for the first entry.
+    prev: Option<fcdb_core::Cid>,
+    change: Change,
+    /// Whether the change succeeded. Failed changes are logged too, as they
+    /// can still use up ids.
+    applied: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    format: u32,
+    state: Image,
+    /// Every block of the state with the object holding it.
+    blocks: Vec<(Cid, fcdb_core::Cid)>,
+}
+
+/// The format field shared by log entries and checkpoints, read before the
+/// rest of an object.
+#[derive(Deserialize)]
+struct Format {
+    format: u32,
+}
+
+/// The pack store and the head of the log in it.
+struct Log {
+    cas: PackCAS,
+    runtime: Runtime,
+    path: PathBuf,
+    head: Option<fcdb_core::Cid>,
+    /// Objects already holding blocks of the state.
+    stored: HashMap<Cid, fcdb_core::Cid>,
+    /// Entries appended since the latest checkpoint.
+    since_checkpoint: usize,
+    /// Set once a change was applied but could not be logged, after which
+    /// the store no longer matches the state and takes no more changes.
+    behind: bool,
+}
+
+impl Log {
+    /// Appends an entry recording `change` and its outcome, and moves `HEAD`
+    /// to it.
+    fn append(&mut self, change: Change, applied: bool) -> Result<()> {
+        let entry = LogEntry { format: LOG_FORMAT, prev: self.head, change, applied };
+        let data = serde_ipld_dagcbor::to_vec(&entry).map_err(|e| Error::Serialization(e.to_string()))?;
+        let head = self.put(&data, LOG_ENTRY)?;
+        // Replaced in one step, so a crash leaves either the old or the new head.
+        let staged = self.path.join("HEAD.tmp");
+        std::fs::write(&staged, head.as_bytes())?;
+        std::fs::rename(&staged, self.path.join("HEAD"))?;
+        self.head = Some(head);
+        self.since_checkpoint += 1;
+        Ok(())
+    }
+
+    /// Stores the blocks of `state` that are not stored yet and a checkpoint
+    /// of it, and logs the checkpoint.
+    fn checkpoint(&mut self, state: &InMemoryAdapter) -> Result<()> {
+        let mut blocks = Vec::new();
+        for cid in state.block_cids() {
+            let object = match self.stored.get(&cid) {
+                Some(object) => *object,
+                None => {
+                    let data = state.get_block(&cid)?.ok_or_else(|| Error::NotFound(format!("block {}", cid)))?;
+                    let object = self.put(&data, BLOCK)?;
+                    self.stored.insert(cid, object);
+                    object
+                }
+            };
+            blocks.push((cid, object));
+        }
+        let checkpoint = Checkpoint { format: LOG_FORMAT, state: state.image(), blocks };
+        let data = serde_ipld_dagcbor::to_vec(&checkpoint).map_err(|e| Error::Serialization(e.to_string()))?;
+        let object = self.put(&data, CHECKPOINT)?;
+        self.append(Change::Checkpoint(object), true)?;
+        self.since_checkpoint = 0;
+        Ok(())
+    }
+
+    /// Stores an object and returns its CID.
+    fn put(&mut self, data: &[u8], kind: u8) -> Result<fcdb_core::Cid> {
+        let band = if data.len() < 4096 { PackBand::Small } else { PackBand::Blob };
+        Ok(self.runtime.block_on(self.cas.put(data, kind, band))?)
+    }
+
+    /// Reads an object the log refers to back, checked against its CID.
+    fn get(&self, object: &fcdb_core::Cid) -> Result<Vec<u8>> {
+        self.runtime.block_on(self.cas.get(object)).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => Error::Serialization(format!("FCDB object {} is missing", object)),
+            ErrorKind::InvalidData | ErrorKind::UnexpectedEof => {
+                Error::Serialization(format!("FCDB object {}: {}", object, e))
+            }
+            _ => Error::Io(e),
+        })
+    }
+
+    /// Reads a log entry or checkpoint, refusing other formats.
+    fn read<T: DeserializeOwned>(&self, object: &fcdb_core::Cid) -> Result<T> {
+        let data = self.get(object)?;
+        let decode_error = |e: serde_ipld_dagcbor::DecodeError<_>| Error::Serialization(format!("FCDB object {}: {}", object, e));
+        let Format { format } = serde_ipld_dagcbor::from_slice(&data).map_err(decode_error)?;
+        if format != LOG_FORMAT {
+            return Err(Error::Migration(format!(
+                "FCDB object {} has format {}, but only format {} can be read", object, format, LOG_FORMAT,
+            )));
+        }
+        serde_ipld_dagcbor::from_slice(&data).map_err(decode_error)
+    }
+
+    /// Loads a checkpoint and its blocks.
+    fn restore(&mut self, object: &fcdb_core::Cid) -> Result<InMemoryAdapter> {
+        let checkpoint: Checkpoint = self.read(object)?;
+        let mut blocks = HashMap::with_capacity(checkpoint.blocks.len());
+        for (cid, object) in checkpoint.blocks {
+            blocks.insert(cid, self.get(&object)?);
+            self.stored.insert(cid, object);
+        }
+        InMemoryAdapter::from_image(checkpoint.state, blocks)
+            .map_err(|e| Error::Serialization(format!("FCDB checkpoint {}: {}", object, e)))
+    }
+}
+
+/// Adapter keeping its data in an FCDB `PackCAS`.
+#[derive(Clone)]
+pub struct FcdbAdapter {
+    state: InMemoryAdapter,
+    log: Arc<Mutex<Log>>,
+}
+
+impl FcdbAdapter {
+    /// Opens or creates the store under `data_dir`, loading its latest
+    /// checkpoint and replaying the log after it.
+    pub fn new_sync(data_dir: PathBuf) -> Result<Self> {
+        let legacy = data_dir.join("fcdb_data");
+        if legacy.exists() {
+            return Err(Error::Migration(format!(
+                "{} is a store of the earlier sled-based FCDB adapter, which kept no node content to migrate; \
+                 rebuild the data into a new directory and remove it",
+                legacy.display(),
+            )));
+        }
+
+        let path = data_dir.join("fcdb_cas");
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        let cas = runtime.block_on(PackCAS::open(&path))?;
+        let head = match std::fs::read(path.join("HEAD")) {
+            Ok(bytes) => {
+                let bytes = bytes.try_into()
+                    .map_err(|_| Error::Serialization(format!("FCDB store {} has a malformed HEAD", path.display())))?;
+                Some(fcdb_core::Cid::from_bytes(bytes))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let mut log = Log { cas, runtime, path, head, stored: HashMap::new(), since_checkpoint: 0, behind: false };
+        let state = replay(&mut log)?;
+        Ok(FcdbAdapter { state, log: Arc::new(Mutex::new(log)) })
+    }
+
+    /// Applies a change to the current state, then logs it with its outcome.
+    fn logged<T>(&self, change: Change, apply: impl FnOnce(&InMemoryAdapter) -> Result<T>) -> Result<T> {
+        let mut log = self.log.lock().unwrap();
+        if log.behind {
+            return Err(Error::Transaction("FCDB log is behind the current state; reopen the store".to_string()));
+        }
+        if log.since_checkpoint >= CHECKPOINT_INTERVAL {
+            log.checkpoint(&self.state)?;
+        }
+        let result = apply(&self.state);
+        if let Err(e) = log.append(change, result.is_ok()) {
+            log.behind = true;
+            return Err(e);
+        }
+        result
+    }
+}
+
+/// Rebuilds the state from the latest checkpoint and the log entries after it.
+fn replay(log: &mut Log) -> Result<InMemoryAdapter> {
+    let mut state = InMemoryAdapter::new();
+    let mut entries = Vec::new();
+    let mut next = log.head;
+    while let Some(cid) = next {
+        let entry: LogEntry = log.read(&cid)?;
+        if let Change::Checkpoint(object) = entry.change {
+            state = log.restore(&object)?;
+            break;
+        }
+        next = entry.prev;
+        entries.push((cid, entry));
+    }
+    log.since_checkpoint = entries.len();
+
+    for (cid, entry) in entries.into_iter().rev() {
+        let result = match entry.change {
+            Change::PutBlock { cid, object } => {
+                log.stored.insert(cid, object);
+                state.put_block(&cid, &log.get(&object)?)
+            }
+            Change::AddVertex(node) => state.add_vertex(&node).map(drop),
+            Change::AddEdge { source, kind, target } => state.add_edge(source, &kind, target),
+            Change::ImportGraph(graph) => state.import_graph(&graph),
+            Change::Transact(ops) => state.transact(&ops).map(drop),
+            Change::Commit { branch, author, message, timestamp } => {
+                state.commit_at(&branch, author, message, timestamp).map(drop)
+            }
+            Change::CreateBranch { name, from } => state.create_branch(&name, &from).map(drop),
+            Change::DeleteBranch { name } => state.delete_branch(&name).map(drop),
+            Change::ResetBranch { name, rev } => state.reset_branch(&name, &rev).map(drop),
+            Change::Checkpoint(_) => unreachable!("replay stops at the latest checkpoint"),
+        };
+        // Changes that failed when they were made fail the same way again.
+        match (result, entry.applied) {
+            (Ok(()), true) | (Err(_), false) => {}
+            (Ok(()), false) => {
+                return Err(Error::Serialization(format!("FCDB log entry {} failed when made but replays", cid)));
+            }
+            (Err(e), true) => {
+                return Err(Error::Serialization(format!("FCDB log entry {} no longer replays: {}", cid, e)));
+            }
+        }
+    }
+    Ok(state)
+}
+
+impl GraphAdapter for FcdbAdapter {
+    fn add_vertex(&self, node: &Node) -> Result<u64> {
+        self.logged(Change::AddVertex(node.clone()), |state| state.add_vertex(node))
+    }
+
+    fn add_edge(&self, source_id: u64, edge_type: &str, target_id: u64) -> Result<()> {
+        let change = Change::AddEdge { source: source_id, kind: edge_type.to_string(), target: target_id };
+        self.logged(change, |state| state.add_edge(source_id, edge_type, target_id))
+    }
+
+    fn get_edges_from(&self, source_id: u64, edge_type: &str) -> Result<Vec<u64>> {
+        self.state.get_edges_from(source_id, edge_type)
+    }
+
+    fn import_graph(&self, graph: &Graph) -> Result<()> {
+        self.logged(Change::ImportGraph(graph.clone()), |state| state.import_graph(graph))
+    }
+
+    fn get_node(&self, id: &str) -> Result<Option<Node>> {
+        self.state.get_node(id)
+    }
+
+    fn vertex_id(&self, id: &str) -> Result<Option<u64>> {
+        self.state.vertex_id(id)
+    }
+
+    fn vertex_node(&self, vertex_id: u64) -> Result<Option<Node>> {
+        self.state.vertex_node(vertex_id)
+    }
+
+    fn scan_nodes(&self, filter: &NodeFilter) -> Result<NodePage> {
+        self.state.scan_nodes(filter)
+    }
+
+    fn edges_of(&self, vertex_id: u64, direction: Direction) -> Result<Vec<Adjacency>> {
+        self.state.edges_of(vertex_id, direction)
+    }
+
+    fn transact(&self, ops: &[TxOp]) -> Result<u64> {
+        self.logged(Change::Transact(ops.to_vec()), |state| state.transact(ops))
+    }
+
+    fn commit(&self, branch: &str, author: String, message: String) -> Result<Cid> {
+        let timestamp = now();
+        let change = Change::Commit { branch: branch.to_string(), author: author.clone(), message: message.clone(), timestamp };
+        self.logged(change, |state| state.commit_at(branch, author, message, timestamp))
+    }
+
+    fn resolve(&self, rev: &str) -> Result<Cid> {
+        self.state.resolve(rev)
+    }
+
+    fn list_branches(&self) -> Result<Vec<(String, Cid)>> {
+        self.state.list_branches()
+    }
+
+    fn create_branch(&self, name: &str, from: &str) -> Result<Cid> {
+        let change = Change::CreateBranch { name: name.to_string(), from: from.to_string() };
+        self.logged(change, |state| state.create_branch(name, from))
+    }
+
+    fn delete_branch(&self, name: &str) -> Result<Cid> {
+        self.logged(Change::DeleteBranch { name: name.to_string() }, |state| state.delete_branch(name))
+    }
+
+    fn reset_branch(&self, name: &str, rev: &str) -> Result<Cid> {
+        let change = Change::ResetBranch { name: name.to_string(), rev: rev.to_string() };
+        self.logged(change, |state| state.reset_branch(name, rev))
+    }
+
+    fn put_block(&self, cid: &Cid, data: &[u8]) -> Result<()> {
+        let object = {
+            let mut log = self.log.lock().unwrap();
+            let object = log.put(data, BLOCK)?;
+            log.stored.insert(*cid, object);
+            object
+        };
+        self.logged(Change::PutBlock { cid: *cid, object }, |state| state.put_block(cid, data))
+    }
+
+    fn get_block(&self, cid: &Cid) -> Result<Option<Vec<u8>>> {
+        self.state.get_block(cid)
+    }
+}
@@ -0,0 +1,195 @@
+//! Adjacency indexes for traversal.
+//!
+//! Every adjacency entry is written under three tuple keys: `(source, kind,
+//! target)` in the `edges` tree for outgoing lookups, `(target, kind, source)`
+//! in `edges_in` for incoming ones and `(kind, source, target)` in
+//! `edge_kinds` for scans by edge type. Entries derived from a stored edge
+//! append the edge id as a fourth element and hold the edge's layer as their
+//! value; entries added through `add_edge` have neither.
+
+use crate::key::{Key, KeyReader};
+use crate::{run_transaction, EngiDB, Error, Result, EDGES, EDGE_KINDS, EDGES_IN};
+use kotoba_types::Layer;
+use sled::transaction::{ConflictableTransactionResult, TransactionalTree};
+
+/// Direction of an adjacency lookup relative to a vertex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Outgoing,
+    Incoming,
+    Both,
+}
+
+/// An entry of the adjacency indexes.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Adjacency {
+    pub source: u64,
+    pub kind: String,
+    pub target: u64,
+    /// Id of the stored edge this entry comes from.
+    pub edge: Option<String>,
+    /// Layer of the stored edge this entry comes from.
+    pub layer: Option<Layer>,
+}
+
+impl Adjacency {
+    /// Keys of the entry in `edges`, `edges_in` and `edge_kinds`.
+    pub(crate) fn keys(&self) -> [Vec<u8>; 3] {
+        let with_edge = |key: Key| match &self.edge {
+            Some(edge) => key.str(edge).into_bytes(),
+            None => key.into_bytes(),
+        };
+        [
+            with_edge(Key::new().uint(self.source).str(&self.kind).uint(self.target)),
+            with_edge(Key::new().uint(self.target).str(&self.kind).uint(self.source)),
+            with_edge(Key::new().str(&self.kind).uint(self.source).uint(self.target)),
+        ]
+    }
+
+    fn value(&self) -> &'static [u8] {
+        self.layer.map(|layer| layer.as_str().as_bytes()).unwrap_or_default()
+    }
+
+    /// Decodes an entry of the `edges` tree.
+    pub(crate) fn from_outgoing(key: &[u8], value: &[u8]) -> Result<Self> {
+        let mut key = KeyReader::new(key);
+        let (source, kind, target) = (key.uint()?, key.str()?, key.uint()?);
+        Self::decode(source, kind, target, key, value)
+    }
+
+    fn from_incoming(key: &[u8], value: &[u8]) -> Result<Self> {
+        let mut key = KeyReader::new(key);
+        let (target, kind, source) = (key.uint()?, key.str()?, key.uint()?);
+        Self::decode(source, kind, target, key, value)
+    }
+
+    fn from_kind(key: &[u8], value: &[u8]) -> Result<Self> {
+        let mut key = KeyReader::new(key);
+        let (kind, source, target) = (key.str()?, key.uint()?, key.uint()?);
+        Self::decode(source, kind, target, key, value)
+    }
+
+    fn decode(source: u64, kind: &str, target: u64, mut rest: KeyReader, value: &[u8]) -> Result<Self> {
+        let edge = if rest.is_empty() { None } else { Some(rest.str()?.to_string()) };
+        Ok(Adjacency {
+            source,
+            kind: kind.to_string(),
+            target,
+            edge,
+            layer: Layer::from_str(std::str::from_utf8(value)?),
+        })
+    }
+}
+
+/// The adjacency trees inside a sled transaction.
+pub(crate) struct AdjacencyTrees<'t> {
+    pub edges: &'t TransactionalTree,
+    pub edges_in: &'t TransactionalTree,
+    pub edge_kinds: &'t TransactionalTree,
+}
+
+impl AdjacencyTrees<'_> {
+    pub fn insert(&self, entry: &Adjacency) -> ConflictableTransactionResult<(), Error> {
+        let [outgoing, incoming, kind] = entry.keys();
+        self.edges.insert(outgoing, entry.value())?;
+        self.edges_in.insert(incoming, entry.value())?;
+        self.edge_kinds.insert(kind, entry.value())?;
+        Ok(())
+    }
+
+    pub fn remove(&self, entry: &Adjacency) -> ConflictableTransactionResult<(), Error> {
+        let [outgoing, incoming, kind] = entry.keys();
+        self.edges.remove(outgoing)?;
+        self.edges_in.remove(incoming)?;
+        self.edge_kinds.remove(kind)?;
+        Ok(())
+    }
+}
+
+impl EngiDB {
+    /// Gets all source vertex IDs for a given target vertex and edge type, in ascending order.
+    pub fn get_edges_to(&self, target_id: u64, edge_type: &str) -> Result<Vec<u64>> {
+        let prefix = Key::new().uint(target_id).str(edge_type);
+        let mut sources = Vec::new();
+        for result in self.tree(EDGES_IN)?.scan_prefix(prefix) {
+            let (key, value) = result?;
+            sources.push(Adjacency::from_incoming(&key, &value)?.source);
+        }
+        sources.sort_unstable();
+        sources.dedup();
+        Ok(sources)
+    }
+
+    /// All adjacency entries of a vertex in the given direction.
+    pub fn edges_of(&self, vertex_id: u64, direction: Direction) -> Result<Vec<Adjacency>> {
+        let prefix = Key::new().uint(vertex_id);
+        let mut entries = Vec::new();
+        if direction != Direction::Incoming {
+            for result in self.tree(EDGES)?.scan_prefix(&prefix) {
+                let (key, value) = result?;
+                entries.push(Adjacency::from_outgoing(&key, &value)?);
+            }
+        }
+        if direction != Direction::Outgoing {
+            for result in self.tree(EDGES_IN)?.scan_prefix(&prefix) {
+                let (key, value) = result?;
+                entries.push(Adjacency::from_incoming(&key, &value)?);
+            }
+        }
+        // Self-loops are found in both directions.
+        entries.sort();
+        entries.dedup();
+        Ok(entries)
+    }
+
+    /// All adjacency entries of an edge type.
+    pub fn edges_of_kind(&self, edge_type: &str) -> Result<Vec<Adjacency>> {
+        let mut entries = Vec::new();
+        for result in self.tree(EDGE_KINDS)?.scan_prefix(Key::new().str(edge_type)) {
+            let (key, value) = result?;
+            entries.push(Adjacency::from_kind(&key, &value)?);
+        }
+        Ok(entries)
+    }
+
+    /// Vertices one edge of `layer` away from a vertex, in ascending order.
+    ///
+    /// Entries added through `add_edge` have no layer and are never followed.
+    pub fn neighbors_in_layer(&self, vertex_id: u64, direction: Direction, layer: Layer) -> Result<Vec<u64>> {
+        let mut neighbors: Vec<u64> = self.edges_of(vertex_id, direction)?
+            .into_iter()
+            .filter(|entry| entry.layer == Some(layer))
+            .map(|entry| if entry.source == vertex_id { entry.target } else { entry.source })
+            .collect();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        Ok(neighbors)
+    }
+
+    /// Inserts or removes adjacency entries outside a transaction, one batch per tree.
+    pub(crate) fn write_adjacency<'a>(&self, entries: impl IntoIterator<Item = (&'a Adjacency, bool)>) -> Result<()> {
+        let mut batches: [sled::Batch; 3] = Default::default();
+        for (entry, insert) in entries {
+            for (batch, key) in batches.iter_mut().zip(entry.keys()) {
+                if insert {
+                    batch.insert(key, entry.value());
+                } else {
+                    batch.remove(key);
+                }
+            }
+        }
+        let [edges, edges_in, edge_kinds] = batches;
+        self.tree(EDGES)?.apply_batch(edges)?;
+        self.tree(EDGES_IN)?.apply_batch(edges_in)?;
+        self.tree(EDGE_KINDS)?.apply_batch(edge_kinds)?;
+        Ok(())
+    }
+
+    /// Inserts a single adjacency entry into all three trees atomically.
+    pub(crate) fn insert_adjacency(&self, entry: &Adjacency) -> Result<()> {
+        let trees = (&self.tree(EDGES)?, &self.tree(EDGES_IN)?, &self.tree(EDGE_KINDS)?);
+        run_transaction(trees, |(edges, edges_in, edge_kinds)| {
+            AdjacencyTrees { edges, edges_in, edge_kinds }.insert(entry)
+        })
+    }
+}
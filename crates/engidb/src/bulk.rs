@@ -0,0 +1,250 @@
+//! Bulk loading of large graphs.
+//!
+//! `EngiDB::bulk_import_graph` is the fast path for graphs with hundreds of
+//! thousands of nodes. Nodes are encoded, hashed and diffed against the fact
+//! log in parallel, and every tree is then written with large sled batches,
+//! with keys sorted so each batch is a single ordered run.
+//!
+//! Unlike `import_graph` the load is not one transaction: each batch is atomic
+//! on its own, and trees are written node blocks first, then facts, then the
+//! entity, property and kind indexes, then edge and incidence records and finally
+//! adjacency. Every step is idempotent, so a load interrupted by a crash is
+//! completed by running it again. Indexes declared while a bulk load runs may
+//! miss the nodes it writes.
+
+use crate::adjacency::Adjacency;
+use crate::entity::EntityRecord;
+use crate::index::index_changes;
+use crate::scan::kind_changes;
+use crate::temporal::{datom_key, now, Datom};
+use crate::{
+    edge_endpoints, EngiDB, Error, Result, CID_TO_VERTEX, DATOMS, ENTITIES, IPLD_BLOCKS, NODE_KINDS, PROPERTY_INDEX,
+    TRANSACTIONS, VERTICES,
+};
+use cid::Cid;
+use kotoba_types::{Graph, Node};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// Number of keys written per sled batch.
+const BATCH_SIZE: usize = 10_000;
+
+/// Stage of a bulk import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportPhase {
+    /// Encoding nodes, computing CIDs and diffing their facts.
+    Encode,
+    /// Writing node blocks.
+    Blocks,
+    /// Writing datoms to the fact log.
+    Facts,
+    /// Pointing entities at their new content and updating property and kind indexes.
+    Entities,
+    /// Writing edge and incidence blocks with their index entries.
+    EdgeRecords,
+    /// Writing adjacency entries and their indexes.
+    Edges,
+}
+
+/// Progress report passed to the `bulk_import_graph` callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImportProgress {
+    pub phase: ImportPhase,
+    /// Items of this phase written so far.
+    pub done: usize,
+    /// Items this phase will write in total.
+    pub total: usize,
+}
+
+/// A node prepared for writing.
+struct EncodedNode<'g> {
+    node: &'g Node,
+    cid: Cid,
+    data: Vec<u8>,
+    record: Option<EntityRecord>,
+    datoms: Vec<Datom>,
+}
+
+impl EngiDB {
+    /// Imports a large graph using parallel encoding and batched writes.
+    ///
+    /// The result is the same as `import_graph`; `progress` is called as each
+    /// phase advances. See the module documentation for crash behaviour.
+    pub fn bulk_import_graph(&self, graph: &Graph, mut progress: impl FnMut(ImportProgress)) -> Result<()> {
+        let tx = self.allocate_tx()?;
+        let valid_from = now();
+
+        // 1. Encode nodes in parallel; the last node with a given id wins
+        let mut latest: HashMap<&str, &Node> = HashMap::with_capacity(graph.node.len());
+        for node in &graph.node {
+            latest.insert(node.id.as_str(), node);
+        }
+        let nodes: Vec<&Node> = latest.into_values().collect();
+        progress(ImportProgress { phase: ImportPhase::Encode, done: 0, total: nodes.len() });
+        let mut encoded = nodes
+            .par_iter()
+            .map(|node| self.encode_node(node, tx.tx, valid_from))
+            .collect::<Result<Vec<_>>>()?;
+        progress(ImportProgress { phase: ImportPhase::Encode, done: nodes.len(), total: nodes.len() });
+
+        // 2. Blocks
+        let mut blocks: Vec<(Vec<u8>, &[u8])> = encoded.iter()
+            .filter(|n| n.record.as_ref().and_then(|r| r.cid) != Some(n.cid))
+            .map(|n| (n.cid.to_bytes(), n.data.as_slice()))
+            .collect();
+        blocks.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let blocks_tree = self.tree(IPLD_BLOCKS)?;
+        write_chunks(ImportPhase::Blocks, &blocks, &mut progress, |chunk| {
+            let mut batch = sled::Batch::default();
+            for (key, data) in chunk {
+                batch.insert(key.as_slice(), *data);
+            }
+            Ok(blocks_tree.apply_batch(batch)?)
+        })?;
+        drop(blocks);
+
+        // 3. Facts, logged under a single transaction id
+        let mut datoms = encoded.par_iter_mut()
+            .flat_map(|n| std::mem::take(&mut n.datoms))
+            .map(|datom| {
+                let data = serde_ipld_dagcbor::to_vec(&datom).map_err(|e| Error::Serialization(e.to_string()))?;
+                Ok((datom_key(&datom), data))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        datoms.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let datoms_tree = self.tree(DATOMS)?;
+        write_chunks(ImportPhase::Facts, &datoms, &mut progress, |chunk| {
+            let mut batch = sled::Batch::default();
+            for (key, data) in chunk {
+                batch.insert(key.as_slice(), data.as_slice());
+            }
+            Ok(datoms_tree.apply_batch(batch)?)
+        })?;
+        if !datoms.is_empty() {
+            let record = serde_ipld_dagcbor::to_vec(&tx).map_err(|e| Error::Serialization(e.to_string()))?;
+            self.tree(TRANSACTIONS)?.insert(tx.tx.to_be_bytes(), record)?;
+        }
+        drop(datoms);
+
+        // 4. Entity index, with vertex ids reserved in one block for new entities
+        let new_entities = encoded.iter().filter(|n| n.record.is_none()).count() as u64;
+        let mut next_id = if new_entities > 0 { self.allocate_vertex_ids(new_entities)? } else { 0 };
+        let mut vertex_ids: HashMap<&str, u64> = HashMap::with_capacity(encoded.len());
+        let mut updates = Vec::new();
+        for node in &mut encoded {
+            let mut record = node.record.take().unwrap_or_else(|| {
+                next_id += 1;
+                EntityRecord { vertex: next_id - 1, cid: None, history: Vec::new() }
+            });
+            vertex_ids.insert(node.node.id.as_str(), record.vertex);
+            if record.cid != Some(node.cid) {
+                let old = record.cid.replace(node.cid);
+                record.history.push(node.cid);
+                updates.push((node.node, old, node.cid, record));
+            }
+        }
+        updates.par_sort_unstable_by_key(|(_, _, _, record)| record.vertex);
+        let vertices_tree = self.tree(VERTICES)?;
+        let cid_to_vertex_tree = self.tree(CID_TO_VERTEX)?;
+        let entities_tree = self.tree(ENTITIES)?;
+        let index_tree = self.tree(PROPERTY_INDEX)?;
+        let kinds_tree = self.tree(NODE_KINDS)?;
+        let index_definitions = self.list_indexes()?;
+        write_chunks(ImportPhase::Entities, &updates, &mut progress, |chunk| {
+            let mut vertices = sled::Batch::default();
+            let mut cid_to_vertex = sled::Batch::default();
+            let mut entities = sled::Batch::default();
+            let mut indexes = sled::Batch::default();
+            let mut kinds = sled::Batch::default();
+            for (node, old, cid, record) in chunk {
+                let id_bytes = record.vertex.to_be_bytes();
+                if let Some(old) = old {
+                    cid_to_vertex.remove(old.to_bytes());
+                }
+                vertices.insert(&id_bytes, cid.to_bytes());
+                cid_to_vertex.insert(cid.to_bytes(), &id_bytes);
+                entities.insert(node.id.as_bytes(), record.to_bytes()?);
+                let old_node: Option<Node> = match old {
+                    Some(old) => self.get_dag(old)?,
+                    None => None,
+                };
+                for (batch, changes) in [
+                    (&mut indexes, index_changes(&index_definitions, old_node.as_ref(), Some(node))),
+                    (&mut kinds, kind_changes(old_node.as_ref(), Some(node))),
+                ] {
+                    for (key, insert) in changes {
+                        if insert {
+                            batch.insert(key, node.id.as_bytes());
+                        } else {
+                            batch.remove(key);
+                        }
+                    }
+                }
+            }
+            vertices_tree.apply_batch(vertices)?;
+            cid_to_vertex_tree.apply_batch(cid_to_vertex)?;
+            // Entity records go last: a rerun diffs against them.
+            index_tree.apply_batch(indexes)?;
+            kinds_tree.apply_batch(kinds)?;
+            entities_tree.apply_batch(entities)?;
+            Ok(())
+        })?;
+
+        // 5. Edge and incidence records
+        let edge_writes = self.stage_graph_edges(graph)?;
+        let total = edge_writes.len();
+        progress(ImportProgress { phase: ImportPhase::EdgeRecords, done: 0, total });
+        edge_writes.apply_batches(self)?;
+        progress(ImportProgress { phase: ImportPhase::EdgeRecords, done: total, total });
+        drop(edge_writes);
+
+        // 6. Adjacency, sorted by source so outgoing keys are written in order
+        let endpoints = edge_endpoints(&graph.edge, &graph.incidence);
+        vertex_ids.extend(self.stored_endpoints(graph, &endpoints)?);
+        let mut edges: Vec<Adjacency> = endpoints.into_par_iter()
+            .filter_map(|(edge, source, target)| Some(Adjacency {
+                source: *vertex_ids.get(source)?,
+                kind: edge.kind.clone(),
+                target: *vertex_ids.get(target)?,
+                edge: Some(edge.id.clone()),
+                layer: Some(edge.layer),
+            }))
+            .collect();
+        edges.par_sort_unstable();
+        edges.dedup();
+        write_chunks(ImportPhase::Edges, &edges, &mut progress, |chunk| {
+            self.write_adjacency(chunk.iter().map(|entry| (entry, true)))
+        })?;
+
+        Ok(())
+    }
+
+    /// Serializes a node and collects the facts it changes.
+    fn encode_node<'g>(&self, node: &'g Node, tx: u64, valid_from: u64) -> Result<EncodedNode<'g>> {
+        let data = serde_ipld_dagcbor::to_vec(node).map_err(|e| Error::Serialization(e.to_string()))?;
+        let cid = self.calculate_cid(&data)?;
+        let datoms = self.attribute_changes(node, valid_from)?
+            .into_iter()
+            .map(|(attribute, value, added)| Datom { entity: node.id.clone(), attribute, value, tx, valid_from, added })
+            .collect();
+        Ok(EncodedNode { node, cid, data, record: self.entity_record(&node.id)?, datoms })
+    }
+}
+
+/// Hands `items` to `write` in chunks of `BATCH_SIZE`, reporting progress after each one.
+fn write_chunks<T>(
+    phase: ImportPhase,
+    items: &[T],
+    progress: &mut impl FnMut(ImportProgress),
+    mut write: impl FnMut(&[T]) -> Result<()>,
+) -> Result<()> {
+    let total = items.len();
+    progress(ImportProgress { phase, done: 0, total });
+    let mut done = 0;
+    for chunk in items.chunks(BATCH_SIZE) {
+        write(chunk)?;
+        done += chunk.len();
+        progress(ImportProgress { phase, done, total });
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+//! CARv1 export and import of commit histories.
+//!
+//! A CAR file is a DAG-CBOR header naming its root CIDs followed by one
+//! section per block: a varint length, the CID and the block bytes. An
+//! export holds a commit and everything reachable from it: its ancestors,
+//! their transactions and snapshots, and the node, edge and incidence blocks
+//! of every snapshot. An import checks every block against its CID before
+//! anything is written, so a damaged file leaves the database untouched.
+
+use crate::{
+    calculate_cid, Commit, EngiDB, Error, Result, Snapshot, Transaction, BRANCHES, COMMITS, IMPORT_ROOTS, IPLD_BLOCKS,
+    PRUNED_COMMITS,
+};
+use cid::Cid;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Read, Write};
+
+/// Header of a CARv1 file.
+#[derive(Serialize, Deserialize)]
+struct CarHeader {
+    roots: Vec<Cid>,
+    version: u64,
+}
+
+/// Outcome of `EngiDB::import_car`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CarImport {
+    pub roots: Vec<Cid>,
+    /// Blocks read from the file.
+    pub blocks: usize,
+    /// Commits among them, now resolvable by CID.
+    pub commits: usize,
+}
+
+fn write_varint<W: Write>(out: &mut W, mut value: u64) -> Result<()> {
+    let mut buf = Vec::with_capacity(10);
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+    out.write_all(&buf)?;
+    Ok(())
+}
+
+/// Reads an unsigned LEB128 varint; `None` at a clean end of input.
+fn read_varint<R: Read>(input: &mut R) -> Result<Option<u64>> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let mut byte = [0u8];
+        if input.read(&mut byte)? == 0 {
+            return match i {
+                0 => Ok(None),
+                _ => Err(Error::Serialization("truncated varint in CAR file".to_string())),
+            };
+        }
+        value |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(Error::Serialization("varint in CAR file is too long".to_string()))
+}
+
+/// Reads one length-prefixed frame; `None` at a clean end of input.
+fn read_frame<R: Read>(input: &mut R) -> Result<Option<Vec<u8>>> {
+    let Some(len) = read_varint(input)? else { return Ok(None) };
+    let mut frame = Vec::new();
+    input.take(len).read_to_end(&mut frame)?;
+    if frame.len() as u64 != len {
+        return Err(Error::Serialization("truncated section in CAR file".to_string()));
+    }
+    Ok(Some(frame))
+}
+
+fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+    serde_ipld_dagcbor::from_slice(data).map_err(|e| Error::Serialization(e.to_string()))
+}
+
+impl EngiDB {
+    /// Writes `commit` and every block reachable from it to `out` as a CARv1 file.
+    ///
+    /// History cut off by `gc` ends the export at the last retained commit.
+    /// Returns the number of blocks written.
+    pub fn export_car<W: Write>(&self, commit: &Cid, mut out: W) -> Result<usize> {
+        self.get_commit(commit)?;
+        let header = serde_ipld_dagcbor::to_vec(&CarHeader { roots: vec![*commit], version: 1 })
+            .map_err(|e| Error::Serialization(e.to_string()))?;
+        write_varint(&mut out, header.len() as u64)?;
+        out.write_all(&header)?;
+
+        let mut written = HashSet::new();
+        let mut queue = VecDeque::from([*commit]);
+        while let Some(commit_cid) = queue.pop_front() {
+            // Parents collected by `gc` end the history.
+            if written.contains(&commit_cid) || self.get_block(&commit_cid)?.is_none() {
+                continue;
+            }
+            let Some(commit) = self.copy_block::<Commit, W>(&commit_cid, &mut written, &mut out)? else { continue };
+            queue.extend(commit.parents.iter().copied());
+            let Some(transaction) = self.copy_block::<Transaction, W>(&commit.transaction_cid, &mut written, &mut out)? else { continue };
+            let Some(root) = transaction.root else { continue };
+            let Some(snapshot) = self.copy_block::<Snapshot, W>(&root, &mut written, &mut out)? else { continue };
+            for cid in snapshot.vertices.iter().chain(&snapshot.hyperedges).chain(&snapshot.incidences) {
+                self.copy_block::<serde::de::IgnoredAny, W>(cid, &mut written, &mut out)?;
+            }
+        }
+        out.flush()?;
+        Ok(written.len())
+    }
+
+    /// Writes a block not written yet as a CAR section and decodes it.
+    fn copy_block<T: DeserializeOwned, W: Write>(&self, cid: &Cid, written: &mut HashSet<Cid>, out: &mut W) -> Result<Option<T>> {
+        if !written.insert(*cid) {
+            return Ok(None);
+        }
+        let data = self.get_block(cid)?.ok_or_else(|| Error::NotFound(format!("block {}", cid)))?;
+        let cid_bytes = cid.to_bytes();
+        write_varint(out, (cid_bytes.len() + data.len()) as u64)?;
+        out.write_all(&cid_bytes)?;
+        out.write_all(&data)?;
+        decode(&data).map(Some)
+    }
+
+    /// Reads a CARv1 file into the block store.
+    ///
+    /// Every block must hash to its CID. Commits reachable from the roots are
+    /// indexed like locally made ones, and parents missing from both the file
+    /// and the store are recorded as pruned, as `gc` does. With `branch`, the
+    /// file must have a single root commit, which becomes the head of the new
+    /// branch; without one, the roots are recorded so that `gc` keeps what
+    /// they reference.
+    pub fn import_car<R: Read>(&self, mut input: R, branch: Option<&str>) -> Result<CarImport> {
+        let header = read_frame(&mut input)?
+            .ok_or_else(|| Error::Serialization("empty CAR file".to_string()))?;
+        let header: CarHeader = serde_ipld_dagcbor::from_slice(&header)
+            .map_err(|e| Error::Serialization(format!("invalid CAR header: {}", e)))?;
+        if header.version != 1 {
+            return Err(Error::InvalidArgument(format!("unsupported CAR version {}", header.version)));
+        }
+        let branches = self.tree(BRANCHES)?;
+        if let Some(name) = branch {
+            if header.roots.len() != 1 {
+                return Err(Error::InvalidArgument(format!("a branch needs a single root, the file has {}", header.roots.len())));
+            }
+            if branches.contains_key(name.as_bytes())? {
+                return Err(Error::AlreadyExists(format!("branch '{}'", name)));
+            }
+        }
+
+        let mut blocks = HashMap::new();
+        let mut report = CarImport { roots: header.roots, ..CarImport::default() };
+        while let Some(section) = read_frame(&mut input)? {
+            let cid = Cid::read_bytes(section.as_slice())
+                .map_err(|e| Error::Serialization(format!("invalid CID in CAR file: {}", e)))?;
+            let data = section[cid.encoded_len()..].to_vec();
+            let actual = calculate_cid(&data);
+            if actual != cid {
+                return Err(Error::InvalidArgument(format!("CAR block {} hashes to {}", cid, actual)));
+            }
+            report.blocks += 1;
+            blocks.insert(cid, data);
+        }
+
+        // Index the imported history, reading blocks from the file first.
+        let read = |cid: &Cid| -> Result<Option<Vec<u8>>> {
+            match blocks.get(cid) {
+                Some(data) => Ok(Some(data.clone())),
+                None => self.get_block(cid),
+            }
+        };
+        let mut commits = sled::Batch::default();
+        let mut pruned = sled::Batch::default();
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<Cid> = report.roots.iter().copied().collect();
+        while let Some(commit_cid) = queue.pop_front() {
+            if !seen.insert(commit_cid) {
+                continue;
+            }
+            let Some(data) = read(&commit_cid)? else {
+                // Roots are not history; a missing parent was cut off at the source.
+                if !report.roots.contains(&commit_cid) {
+                    pruned.insert(commit_cid.to_bytes(), &[]);
+                }
+                continue;
+            };
+            let Ok(commit) = serde_ipld_dagcbor::from_slice::<Commit>(&data) else { continue };
+            let data = read(&commit.transaction_cid)?
+                .ok_or_else(|| Error::NotFound(format!("transaction {}", commit.transaction_cid)))?;
+            let transaction: Transaction = decode(&data)?;
+            if let Some(root) = transaction.root {
+                commits.insert(commit_cid.to_bytes(), root.to_bytes());
+            }
+            report.commits += 1;
+            queue.extend(commit.parents);
+        }
+        if branch.is_some() && report.commits == 0 {
+            return Err(Error::InvalidArgument(format!("CAR root {} is not a commit", report.roots[0])));
+        }
+
+        let mut batch = sled::Batch::default();
+        for (cid, data) in &blocks {
+            batch.insert(cid.to_bytes(), data.as_slice());
+        }
+        // Blocks go first, so no commit index entry points at a missing block.
+        self.tree(IPLD_BLOCKS)?.apply_batch(batch)?;
+        self.tree(COMMITS)?.apply_batch(commits)?;
+        self.tree(PRUNED_COMMITS)?.apply_batch(pruned)?;
+        match branch {
+            Some(name) => {
+                branches
+                    .compare_and_swap(name.as_bytes(), None as Option<&[u8]>, Some(report.roots[0].to_bytes()))?
+                    .map_err(|_| Error::AlreadyExists(format!("branch '{}'", name)))?;
+            }
+            None => {
+                let mut roots = sled::Batch::default();
+                for root in &report.roots {
+                    roots.insert(root.to_bytes(), &[]);
+                }
+                self.tree(IMPORT_ROOTS)?.apply_batch(roots)?;
+            }
+        }
+        Ok(report)
+    }
+}
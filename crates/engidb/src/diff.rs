@@ -0,0 +1,234 @@
+//! Structural diff between two graphs.
+//!
+//! Elements are matched by identity (node and edge ids, incidence
+//! `edge/node/role[/pos]`) rather than by position, so reordering arrays in
+//! the source JSON does not show up as a change.
+
+use crate::merge::incidence_key;
+use crate::{EngiDB, Result};
+use indexmap::IndexMap;
+use kotoba_types::{Graph, Layer};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+/// How an element differs between the old and the new graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// Part of an element that changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "field", content = "name", rename_all = "lowercase")]
+pub enum DiffField {
+    Kind,
+    Layer,
+    Property(String),
+}
+
+/// Old and new value of a changed field; `None` means absent.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    #[serde(flatten)]
+    pub field: DiffField,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// Change to a single node, edge or incidence.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ElementChange {
+    pub id: String,
+    pub change: Change,
+    /// The element as a whole for additions and removals.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Value>,
+    /// Field-level changes for modified elements.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldChange>,
+}
+
+/// Edge and incidence changes within one layer.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LayerDiff {
+    pub edges: Vec<ElementChange>,
+    pub incidences: Vec<ElementChange>,
+}
+
+/// Structural difference between two graphs, with edges and incidences
+/// grouped by the layer of their edge.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct GraphDiff {
+    pub nodes: Vec<ElementChange>,
+    pub layers: BTreeMap<Layer, LayerDiff>,
+}
+
+impl GraphDiff {
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.layers.is_empty()
+    }
+}
+
+impl EngiDB {
+    /// Diffs the graphs recorded by two revisions.
+    pub fn diff(&self, old_rev: &str, new_rev: &str) -> Result<GraphDiff> {
+        Ok(diff_graphs(&self.checkout(old_rev)?, &self.checkout(new_rev)?))
+    }
+}
+
+/// Computes the structural difference from `old` to `new`.
+pub fn diff_graphs(old: &Graph, new: &Graph) -> GraphDiff {
+    let mut diff = GraphDiff {
+        nodes: diff_elements(
+            old.node.iter().map(|n| (n.id.clone(), n)),
+            new.node.iter().map(|n| (n.id.clone(), n)),
+            |old, new, fields| {
+                diff_field(fields, DiffField::Kind, &old.kind, &new.kind);
+                diff_properties(fields, &old.properties, &new.properties);
+            },
+        ),
+        layers: BTreeMap::new(),
+    };
+
+    let edges = diff_elements(
+        old.edge.iter().map(|e| (e.id.clone(), e)),
+        new.edge.iter().map(|e| (e.id.clone(), e)),
+        |old, new, fields| {
+            diff_field(fields, DiffField::Kind, &old.kind, &new.kind);
+            diff_field(fields, DiffField::Layer, &old.layer, &new.layer);
+            diff_properties(fields, &old.properties, &new.properties);
+        },
+    );
+    let incidences = diff_elements(
+        old.incidence.iter().map(|i| (incidence_key(i), i)),
+        new.incidence.iter().map(|i| (incidence_key(i), i)),
+        |old, new, fields| diff_properties(fields, &old.properties, &new.properties),
+    );
+
+    // New edges override old ones, so an edge whose layer changed is
+    // grouped under the layer it ended up in.
+    let layers: HashMap<&str, Layer> = old.edge.iter().chain(new.edge.iter())
+        .map(|e| (e.id.as_str(), e.layer))
+        .collect();
+    let incidence_edges: HashMap<String, &str> = old.incidence.iter().chain(new.incidence.iter())
+        .map(|i| (incidence_key(i), i.edge.as_str()))
+        .collect();
+
+    for change in edges {
+        let layer = layers.get(change.id.as_str()).copied().unwrap_or(Layer::Data);
+        diff.layers.entry(layer).or_default().edges.push(change);
+    }
+    for change in incidences {
+        let layer = incidence_edges.get(&change.id)
+            .and_then(|edge| layers.get(edge))
+            .copied()
+            .unwrap_or(Layer::Data);
+        diff.layers.entry(layer).or_default().incidences.push(change);
+    }
+
+    diff
+}
+
+fn diff_elements<'a, T: Serialize + PartialEq + 'a>(
+    old: impl Iterator<Item = (String, &'a T)>,
+    new: impl Iterator<Item = (String, &'a T)>,
+    diff_fields: impl Fn(&T, &T, &mut Vec<FieldChange>),
+) -> Vec<ElementChange> {
+    let old: BTreeMap<String, &T> = old.collect();
+    let new: BTreeMap<String, &T> = new.collect();
+    let ids: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let to_value = |v: &T| serde_json::to_value(v).ok();
+
+    let mut changes = Vec::new();
+    for id in ids {
+        let change = match (old.get(id), new.get(id)) {
+            (Some(o), Some(n)) if o == n => continue,
+            (Some(o), Some(n)) => {
+                let mut fields = Vec::new();
+                diff_fields(o, n, &mut fields);
+                ElementChange { id: id.clone(), change: Change::Changed, old: None, new: None, fields }
+            }
+            (Some(o), None) => ElementChange { id: id.clone(), change: Change::Removed, old: to_value(o), new: None, fields: Vec::new() },
+            (None, Some(n)) => ElementChange { id: id.clone(), change: Change::Added, old: None, new: to_value(n), fields: Vec::new() },
+            (None, None) => continue,
+        };
+        changes.push(change);
+    }
+    changes
+}
+
+fn diff_field<T: Serialize + PartialEq>(fields: &mut Vec<FieldChange>, field: DiffField, old: &T, new: &T) {
+    if old != new {
+        fields.push(FieldChange {
+            field,
+            old: serde_json::to_value(old).ok(),
+            new: serde_json::to_value(new).ok(),
+        });
+    }
+}
+
+fn diff_properties(fields: &mut Vec<FieldChange>, old: &IndexMap<String, Value>, new: &IndexMap<String, Value>) {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    for key in keys {
+        let (o, n) = (old.get(key), new.get(key));
+        if o != n {
+            fields.push(FieldChange {
+                field: DiffField::Property(key.clone()),
+                old: o.cloned(),
+                new: n.cloned(),
+            });
+        }
+    }
+}
+
+impl fmt::Display for GraphDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No differences.");
+        }
+        if !self.nodes.is_empty() {
+            writeln!(f, "nodes:")?;
+            write_changes(f, "node", &self.nodes)?;
+        }
+        for (layer, layer_diff) in &self.layers {
+            writeln!(f, "layer {}:", layer.as_str())?;
+            write_changes(f, "edge", &layer_diff.edges)?;
+            write_changes(f, "incidence", &layer_diff.incidences)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_changes(f: &mut fmt::Formatter<'_>, label: &str, changes: &[ElementChange]) -> fmt::Result {
+    let show = |value: &Option<Value>| value.as_ref().map_or("∅".to_string(), Value::to_string);
+    for change in changes {
+        let marker = match change.change {
+            Change::Added => '+',
+            Change::Removed => '-',
+            Change::Changed => '~',
+        };
+        let kind = change.new.as_ref().or(change.old.as_ref())
+            .and_then(|v| v.get("type"))
+            .and_then(Value::as_str);
+        match kind {
+            Some(kind) => writeln!(f, "  {} {} {} ({})", marker, label, change.id, kind)?,
+            None => writeln!(f, "  {} {} {}", marker, label, change.id)?,
+        }
+        for field in &change.fields {
+            let name = match &field.field {
+                DiffField::Kind => "type",
+                DiffField::Layer => "layer",
+                DiffField::Property(name) => name,
+            };
+            writeln!(f, "      {}: {} -> {}", name, show(&field.old), show(&field.new))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,268 @@
+//! Stable entity identity for EngiDB.
+//!
+//! A node's identity is its `Node::id`, not the CID of its content. The
+//! `entities` tree maps each id to an `EntityRecord` holding the vertex id the
+//! entity keeps for its whole life, the CID of its current content and every
+//! CID it has had. Vertex ids come from a persistent counter, so they are
+//! never reused, even after deletions.
+
+use crate::{
+    vertex_id_from_bytes, EngiDB, Error, Result, CID_TO_VERTEX, ENTITIES, IPLD_BLOCKS, META, NODE_KINDS, PROPERTY_INDEX,
+    RENAMED_ENTITIES, VERTICES,
+};
+use cid::Cid;
+use kotoba_types::Node;
+use serde::{Deserialize, Serialize};
+use sled::transaction::{ConflictableTransactionError, ConflictableTransactionResult, TransactionalTree};
+use std::collections::btree_map::{BTreeMap, Entry};
+
+/// Key in the `meta` tree holding the next unallocated vertex id.
+const NEXT_VERTEX_ID: &[u8] = b"next_vertex_id";
+
+/// Index entry of an entity.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EntityRecord {
+    /// Vertex id of the entity; stays the same across updates.
+    pub vertex: u64,
+    /// CID of the current content, `None` once the entity was deleted.
+    pub cid: Option<Cid>,
+    /// Every CID the entity has had, oldest first. Blocks of earlier CIDs
+    /// may have been removed by garbage collection.
+    pub history: Vec<Cid>,
+}
+
+impl EntityRecord {
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        serde_ipld_dagcbor::from_slice(bytes).map_err(|e| Error::Serialization(e.to_string()))
+    }
+
+    pub(crate) fn to_bytes(&self) -> Result<Vec<u8>> {
+        serde_ipld_dagcbor::to_vec(self).map_err(|e| Error::Serialization(e.to_string()))
+    }
+}
+
+/// Outcome of `EngiDB::backfill_entities`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Backfill {
+    /// Number of entities created.
+    pub entities: usize,
+    /// Nodes renamed because a newer node had the same id, as `(old id, new id)`.
+    pub renamed: Vec<(String, String)>,
+}
+
+/// The trees an entity write touches, inside a sled transaction.
+///
+/// `blocks` must already hold the content an entity is pointed at, so the
+/// property indexes in `indexes` and the kind index in `kinds` can be
+/// updated from it.
+pub(crate) struct EntityTrees<'t> {
+    pub vertices: &'t TransactionalTree,
+    pub cid_to_vertex: &'t TransactionalTree,
+    pub entities: &'t TransactionalTree,
+    pub blocks: &'t TransactionalTree,
+    pub indexes: &'t TransactionalTree,
+    pub kinds: &'t TransactionalTree,
+}
+
+impl EntityTrees<'_> {
+    /// Reads an entity record within the transaction.
+    pub fn get(&self, entity: &str) -> ConflictableTransactionResult<Option<EntityRecord>, Error> {
+        match self.entities.get(entity.as_bytes())? {
+            Some(bytes) => Ok(Some(EntityRecord::from_bytes(&bytes).map_err(ConflictableTransactionError::Abort)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Points an entity at new content, or deletes it when `cid` is `None`.
+    ///
+    /// A new entity gets `vertex`; an existing one keeps its vertex id.
+    /// Returns the vertex id the entity ends up with.
+    pub fn set(&self, entity: &str, vertex: u64, cid: Option<Cid>) -> ConflictableTransactionResult<u64, Error> {
+        let mut record = self.get(entity)?
+            .unwrap_or(EntityRecord { vertex, cid: None, history: Vec::new() });
+        if record.cid == cid {
+            return Ok(record.vertex);
+        }
+
+        crate::index::reindex(self.blocks, self.indexes, self.kinds, entity, record.cid, cid)?;
+        let id_bytes = record.vertex.to_be_bytes();
+        if let Some(old) = record.cid {
+            self.cid_to_vertex.remove(old.to_bytes())?;
+        }
+        match cid {
+            Some(cid) => {
+                self.vertices.insert(&id_bytes, cid.to_bytes())?;
+                self.cid_to_vertex.insert(cid.to_bytes(), &id_bytes)?;
+                record.history.push(cid);
+            }
+            None => {
+                self.vertices.remove(&id_bytes)?;
+            }
+        }
+        record.cid = cid;
+        self.entities.insert(entity.as_bytes(), record.to_bytes().map_err(ConflictableTransactionError::Abort)?)?;
+        Ok(record.vertex)
+    }
+}
+
+impl EngiDB {
+    /// Looks up the index entry of an entity, including deleted ones.
+    pub fn entity_record(&self, entity: &str) -> Result<Option<EntityRecord>> {
+        match self.tree(ENTITIES)?.get(entity.as_bytes())? {
+            Some(bytes) => Ok(Some(EntityRecord::from_bytes(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Current content of a live entity.
+    pub fn get_node(&self, entity: &str) -> Result<Option<Node>> {
+        match self.entity_record(entity)?.and_then(|record| record.cid) {
+            Some(cid) => self.get_dag(&cid),
+            None => Ok(None),
+        }
+    }
+
+    /// Entities `backfill_entities` renamed because they shared the id
+    /// `entity` with another node, so their original id still leads to them.
+    pub fn renamed_entities(&self, entity: &str) -> Result<Vec<String>> {
+        match self.tree(RENAMED_ENTITIES)?.get(entity.as_bytes())? {
+            Some(bytes) => serde_ipld_dagcbor::from_slice(&bytes).map_err(|e| Error::Serialization(e.to_string())),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Vertex id of a live entity.
+    pub fn entity_vertex(&self, entity: &str) -> Result<Option<u64>> {
+        Ok(self.entity_record(entity)?.filter(|r| r.cid.is_some()).map(|r| r.vertex))
+    }
+
+    /// Current content of the live entity with a vertex id.
+    pub fn vertex_node(&self, vertex: u64) -> Result<Option<Node>> {
+        match self.tree(VERTICES)?.get(vertex.to_be_bytes())? {
+            Some(cid_bytes) => self.get_dag(&crate::cid_from_bytes(&cid_bytes)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Gives an entity to every stored node that has none.
+    ///
+    /// Databases written before entities existed only map vertex ids to node
+    /// blocks, which leaves their nodes out of entity lookups, scans and the
+    /// kind and property indexes. Each such node becomes the entity named by
+    /// its `Node::id` and keeps its vertex id. Those databases could also hold
+    /// different nodes with the same id; all but the one with the highest
+    /// vertex id, or the one already backing the entity, are renamed to
+    /// `<id>~<vertex id>` so that every node stays reachable, and the new ids
+    /// are recorded under the old one for `renamed_entities`. Opening a
+    /// database runs this once as a format migration; running it again finds
+    /// nothing to do.
+    pub fn backfill_entities(&self) -> Result<Backfill> {
+        let mut nodes: BTreeMap<String, Vec<(u64, Cid, Node)>> = BTreeMap::new();
+        for result in self.tree(VERTICES)?.iter() {
+            let (id_bytes, cid_bytes) = result?;
+            let cid = crate::cid_from_bytes(&cid_bytes)?;
+            let node: Node = self.get_dag(&cid)?
+                .ok_or_else(|| Error::NotFound(format!("vertex block {}", cid)))?;
+            nodes.entry(node.id.clone()).or_default().push((vertex_id_from_bytes(&id_bytes)?, cid, node));
+        }
+
+        let mut report = Backfill::default();
+        let mut missing = Vec::new();
+        let mut renamed = Vec::new();
+        for (id, mut vertices) in nodes {
+            let new_entity = match self.entity_record(&id)?.and_then(|record| record.cid.map(|_| record.vertex)) {
+                Some(vertex) => {
+                    vertices.retain(|(v, _, _)| *v != vertex);
+                    None
+                }
+                None => vertices.pop().map(|(vertex, cid, _)| (vertex, cid)),
+            };
+            for (vertex, old_cid, mut node) in vertices {
+                node.id = format!("{}~{}", node.id, vertex);
+                let data = serde_ipld_dagcbor::to_vec(&node).map_err(|e| Error::Serialization(e.to_string()))?;
+                let cid = crate::calculate_cid(&data);
+                report.renamed.push((id.clone(), node.id.clone()));
+                renamed.push((node.id, vertex, old_cid, cid, data));
+            }
+            if let Some((vertex, cid)) = new_entity {
+                missing.push((id, vertex, cid));
+            }
+        }
+        report.entities = missing.len() + renamed.len();
+        let mut aliases: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for (old, new) in &report.renamed {
+            let ids = match aliases.entry(old) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(self.renamed_entities(old)?),
+            };
+            ids.push(new.clone());
+        }
+        let aliases = aliases.into_iter()
+            .map(|(old, new)| Ok((old, serde_ipld_dagcbor::to_vec(&new).map_err(|e| Error::Serialization(e.to_string()))?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let trees = (
+            &self.tree(VERTICES)?,
+            &self.tree(CID_TO_VERTEX)?,
+            &self.tree(ENTITIES)?,
+            &self.tree(IPLD_BLOCKS)?,
+            &self.tree(PROPERTY_INDEX)?,
+            &self.tree(NODE_KINDS)?,
+            &self.tree(RENAMED_ENTITIES)?,
+        );
+        crate::run_transaction(trees, |(vertices, cid_to_vertex, entities, blocks, indexes, kinds, renamed_entities)| {
+            let entity_trees = EntityTrees { vertices, cid_to_vertex, entities, blocks, indexes, kinds };
+            for (id, vertex, cid) in &missing {
+                entity_trees.set(id, *vertex, Some(*cid))?;
+            }
+            for (id, vertex, old_cid, cid, data) in &renamed {
+                blocks.insert(cid.to_bytes(), data.as_slice())?;
+                cid_to_vertex.remove(old_cid.to_bytes())?;
+                entity_trees.set(id, *vertex, Some(*cid))?;
+            }
+            for (old, new) in &aliases {
+                renamed_entities.insert(old.as_bytes(), new.as_slice())?;
+            }
+            Ok(())
+        })?;
+        Ok(report)
+    }
+
+    /// Reserves `count` consecutive vertex ids and returns the first one.
+    ///
+    /// The counter is advanced with a compare-and-swap loop, so concurrent
+    /// writers never receive the same id.
+    pub(crate) fn allocate_vertex_ids(&self, count: u64) -> Result<u64> {
+        // Databases created before the counter existed start past their highest vertex id.
+        let floor = match self.tree(VERTICES)?.last()? {
+            Some((key, _)) => vertex_id_from_bytes(&key)? + 1,
+            None => 1,
+        };
+        let start = |old: Option<&[u8]>| {
+            old.and_then(|bytes| vertex_id_from_bytes(bytes).ok()).unwrap_or(floor).max(floor)
+        };
+        let previous = self.tree(META)?.fetch_and_update(NEXT_VERTEX_ID, |old| {
+            Some((start(old) + count).to_be_bytes().to_vec())
+        })?;
+        Ok(start(previous.as_deref()))
+    }
+
+    /// Stores the content of an entity and points the entity index at it.
+    pub(crate) fn put_entity(&self, entity: &str, cid: Cid) -> Result<u64> {
+        let vertex = match self.entity_record(entity)? {
+            Some(record) => record.vertex,
+            None => self.allocate_vertex_ids(1)?,
+        };
+        let trees = (
+            &self.tree(VERTICES)?,
+            &self.tree(CID_TO_VERTEX)?,
+            &self.tree(ENTITIES)?,
+            &self.tree(IPLD_BLOCKS)?,
+            &self.tree(PROPERTY_INDEX)?,
+            &self.tree(NODE_KINDS)?,
+        );
+        crate::run_transaction(trees, |(vertices, cid_to_vertex, entities, blocks, indexes, kinds)| {
+            EntityTrees { vertices, cid_to_vertex, entities, blocks, indexes, kinds }.set(entity, vertex, Some(cid))
+        })
+    }
+}
@@ -0,0 +1,177 @@
+//! Garbage collection of unreachable IPLD blocks.
+//!
+//! Writes never delete blocks: every vertex edit stores a new block and the
+//! old one stays behind. `EngiDB::gc` marks the blocks still referenced, that
+//! is the current node content, edge and incidence records and everything
+//! recorded by the commits reachable from branch heads or from the roots of
+//! CAR files imported without a branch, and sweeps the rest of `ipld_blocks`. The CIDs of removed commits are kept in
+//! `pruned_commits`, so that `verify` can tell truncated history from lost
+//! blocks.
+//!
+//! Blocks are listed before marking starts, so blocks written during a
+//! collection survive it. A write that re-references an existing unreachable
+//! block while the collection runs may still lose it; run the collection
+//! while the database is not being written to.
+
+use crate::{
+    cid_from_bytes, Commit, EngiDB, Error, Result, Snapshot, Transaction, BRANCHES, COMMITS, HYPEREDGES, IMPORT_ROOTS,
+    INCIDENCES, IPLD_BLOCKS, PRUNED_COMMITS, VERTICES,
+};
+use cid::Cid;
+use std::collections::{HashSet, VecDeque};
+
+/// Number of keys removed per sled batch.
+const BATCH_SIZE: usize = 10_000;
+
+/// How `EngiDB::gc` runs.
+#[derive(Debug, Clone, Default)]
+pub struct GcOptions {
+    /// Only report what would be collected.
+    pub dry_run: bool,
+    /// Keep only this many of the most recent commits of each branch, in
+    /// `log` order; all history is kept when unset. At least the branch head
+    /// must be kept.
+    pub retain_commits: Option<usize>,
+}
+
+/// Outcome of a collection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Blocks in the store when the collection started.
+    pub blocks: usize,
+    /// Unreachable blocks, removed unless the run was a dry run.
+    pub collected: usize,
+    /// Total size of the unreachable blocks.
+    pub collected_bytes: u64,
+    /// Unreachable commits among them.
+    pub collected_commits: usize,
+}
+
+impl EngiDB {
+    /// Removes the blocks that are no longer reachable.
+    pub fn gc(&self, options: &GcOptions) -> Result<GcReport> {
+        if options.retain_commits == Some(0) {
+            return Err(Error::InvalidArgument("gc must retain at least the head commit of each branch".to_string()));
+        }
+        let blocks_tree = self.tree(IPLD_BLOCKS)?;
+        let mut blocks = Vec::new();
+        for result in blocks_tree.iter() {
+            let (key, data) = result?;
+            blocks.push((key, data.len() as u64));
+        }
+
+        let reachable = self.mark(options.retain_commits)?;
+        let commits_tree = self.tree(COMMITS)?;
+        let mut report = GcReport { blocks: blocks.len(), ..GcReport::default() };
+        let mut garbage = Vec::new();
+        let mut pruned = Vec::new();
+        for (key, size) in blocks {
+            if reachable.contains(&cid_from_bytes(&key)?) {
+                continue;
+            }
+            report.collected += 1;
+            report.collected_bytes += size;
+            if commits_tree.contains_key(&key)? {
+                report.collected_commits += 1;
+                pruned.push(key.clone());
+            }
+            garbage.push(key);
+        }
+        if options.dry_run {
+            return Ok(report);
+        }
+
+        // Recorded before anything is removed, so `verify` never sees an
+        // unexplained hole in the history.
+        let pruned_tree = self.tree(PRUNED_COMMITS)?;
+        for chunk in pruned.chunks(BATCH_SIZE) {
+            let mut batch = sled::Batch::default();
+            for key in chunk {
+                batch.insert(key, &[]);
+            }
+            pruned_tree.apply_batch(batch)?;
+        }
+        for chunk in garbage.chunks(BATCH_SIZE) {
+            let mut blocks = sled::Batch::default();
+            let mut commits = sled::Batch::default();
+            for key in chunk {
+                blocks.remove(key);
+                commits.remove(key);
+            }
+            // Commit index entries go first, so no entry outlives its block.
+            commits_tree.apply_batch(commits)?;
+            blocks_tree.apply_batch(blocks)?;
+        }
+        Ok(report)
+    }
+
+    /// CIDs of every block referenced by the live graph, a retained commit
+    /// or an import root.
+    fn mark(&self, retain_commits: Option<usize>) -> Result<HashSet<Cid>> {
+        let mut reachable = HashSet::new();
+        for tree in [VERTICES, HYPEREDGES, INCIDENCES] {
+            for value in self.tree(tree)?.iter().values() {
+                reachable.insert(cid_from_bytes(&value?)?);
+            }
+        }
+
+        let mut heads = Vec::new();
+        for value in self.tree(BRANCHES)?.iter().values() {
+            heads.push(cid_from_bytes(&value?)?);
+        }
+        // Import roots that are commits count as heads; others are kept with
+        // the graph state they describe, if they are snapshots.
+        for key in self.tree(IMPORT_ROOTS)?.iter().keys() {
+            let root = cid_from_bytes(&key?)?;
+            let Some(data) = self.get_block(&root)? else { continue };
+            if serde_ipld_dagcbor::from_slice::<Commit>(&data).is_ok() {
+                heads.push(root);
+            } else if reachable.insert(root) {
+                if let Ok(snapshot) = serde_ipld_dagcbor::from_slice::<Snapshot>(&data) {
+                    mark_snapshot(snapshot, &mut reachable);
+                }
+            }
+        }
+
+        for head in heads {
+            // Each head counts its own window, even through shared history.
+            let mut visited = HashSet::new();
+            let mut retained = 0;
+            let mut queue = VecDeque::from([head]);
+            while let Some(commit_cid) = queue.pop_front() {
+                if retain_commits.is_some_and(|limit| retained >= limit) {
+                    break;
+                }
+                if !visited.insert(commit_cid) {
+                    continue;
+                }
+                // Parents collected by an earlier run end the history.
+                let Some(commit) = self.get_dag::<Commit>(&commit_cid)? else { continue };
+                retained += 1;
+                reachable.insert(commit_cid);
+                queue.extend(commit.parents.iter().copied());
+                self.mark_transaction(&commit.transaction_cid, &mut reachable)?;
+            }
+        }
+        Ok(reachable)
+    }
+
+    fn mark_transaction(&self, transaction_cid: &Cid, reachable: &mut HashSet<Cid>) -> Result<()> {
+        reachable.insert(*transaction_cid);
+        // Commits written before snapshots existed reference no graph state.
+        let Some(root) = self.get_dag::<Transaction>(transaction_cid)?.and_then(|t| t.root) else { return Ok(()) };
+        if !reachable.insert(root) {
+            return Ok(());
+        }
+        if let Some(snapshot) = self.get_dag::<Snapshot>(&root)? {
+            mark_snapshot(snapshot, reachable);
+        }
+        Ok(())
+    }
+}
+
+fn mark_snapshot(snapshot: Snapshot, reachable: &mut HashSet<Cid>) {
+    reachable.extend(snapshot.vertices);
+    reachable.extend(snapshot.hyperedges);
+    reachable.extend(snapshot.incidences);
+}
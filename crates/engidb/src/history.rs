@@ -0,0 +1,251 @@
+//! Commit history access for EngiDB: resolving revisions and reading the
+//! graph state recorded by a commit.
+
+use crate::{cid_from_bytes, edge_endpoints, Commit, EngiDB, Error, Result, Snapshot, Transaction, BRANCHES, COMMITS};
+use cid::Cid;
+use indexmap::IndexMap;
+use kotoba_types::{Edge, Graph, Incidence, Layer, Node};
+use serde::de::DeserializeOwned;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+impl EngiDB {
+    /// Resolves a revision (branch name or commit CID) to a commit CID.
+    pub fn resolve(&self, rev: &str) -> Result<Cid> {
+        let branches_tree = self.tree(BRANCHES)?;
+        if let Some(head) = branches_tree.get(rev.as_bytes())? {
+            return cid_from_bytes(&head);
+        }
+        Cid::try_from(rev).map_err(|_| Error::NotFound(format!("revision '{}'", rev)))
+    }
+
+    /// Reads a commit object.
+    pub fn get_commit(&self, commit_cid: &Cid) -> Result<Commit> {
+        self.get_dag(commit_cid)?
+            .ok_or_else(|| Error::NotFound(format!("commit {}", commit_cid)))
+    }
+
+    /// Reads the transaction object referenced by a commit.
+    pub fn get_transaction(&self, commit: &Commit) -> Result<Transaction> {
+        self.get_dag(&commit.transaction_cid)?
+            .ok_or_else(|| Error::NotFound(format!("transaction {}", commit.transaction_cid)))
+    }
+
+    /// Reads the snapshot recorded by a commit.
+    ///
+    /// A commit written before commits recorded their graph state has none
+    /// and fails with `NotFound`.
+    pub fn get_snapshot(&self, commit_cid: &Cid) -> Result<Snapshot> {
+        let transaction = self.get_transaction(&self.get_commit(commit_cid)?)?;
+        let root = transaction.root.ok_or_else(|| no_snapshot(commit_cid))?;
+        self.read_snapshot(&root)
+    }
+
+    /// Resolves a revision to the root CID of its snapshot.
+    ///
+    /// Besides branch names and commit CIDs, a snapshot root CID resolves to
+    /// itself. A commit without a recorded snapshot fails with `NotFound`.
+    pub fn resolve_root(&self, rev: &str) -> Result<Cid> {
+        let cid = self.resolve(rev)?;
+        if let Some(root) = self.tree(COMMITS)?.get(cid.to_bytes())? {
+            return cid_from_bytes(&root);
+        }
+        match self.get_dag::<Commit>(&cid) {
+            Ok(Some(commit)) => self.get_transaction(&commit)?.root.ok_or_else(|| no_snapshot(&cid)),
+            _ => Ok(cid),
+        }
+    }
+
+    /// Reads back the graph whose snapshot is stored at `root`.
+    ///
+    /// Importing a graph, committing it and exporting the commit's root gives
+    /// back the same nodes, edges and incidences, in no particular order.
+    pub fn export_graph(&self, root: &Cid) -> Result<Graph> {
+        self.graph_from_snapshot(&self.read_snapshot(root)?)
+    }
+
+    fn read_snapshot(&self, root: &Cid) -> Result<Snapshot> {
+        self.get_dag(root)?.ok_or_else(|| Error::NotFound(format!("snapshot {}", root)))
+    }
+
+    /// Lists the commits reachable from a revision, newest first.
+    ///
+    /// Parents are visited breadth-first, so the first-parent chain comes
+    /// before commits reached only through merges. History ends at parents
+    /// removed by garbage collection.
+    pub fn log(&self, rev: &str) -> Result<Vec<(Cid, Commit)>> {
+        walk_log(self.resolve(rev)?, |cid| self.get_block(cid))
+    }
+
+    /// Lists all branches with their head commits, sorted by name.
+    pub fn list_branches(&self) -> Result<Vec<(String, Cid)>> {
+        let branches_tree = self.tree(BRANCHES)?;
+        let mut branches = Vec::new();
+        for result in branches_tree.iter() {
+            let (name, head) = result?;
+            branches.push((std::str::from_utf8(&name)?.to_string(), cid_from_bytes(&head)?));
+        }
+        Ok(branches)
+    }
+
+    /// Creates a new branch pointing at the commit a revision resolves to.
+    pub fn create_branch(&self, name: &str, from: &str) -> Result<Cid> {
+        let head = self.resolve(from)?;
+        self.get_commit(&head)?;
+
+        let branches_tree = self.tree(BRANCHES)?;
+        branches_tree
+            .compare_and_swap(name.as_bytes(), None as Option<&[u8]>, Some(head.to_bytes()))?
+            .map_err(|_| Error::AlreadyExists(format!("branch '{}'", name)))?;
+        Ok(head)
+    }
+
+    /// Deletes a branch and returns the commit it pointed at.
+    pub fn delete_branch(&self, name: &str) -> Result<Cid> {
+        let branches_tree = self.tree(BRANCHES)?;
+        let head = branches_tree.remove(name.as_bytes())?
+            .ok_or_else(|| Error::NotFound(format!("branch '{}'", name)))?;
+        cid_from_bytes(&head)
+    }
+
+    /// Moves an existing branch to the commit a revision resolves to.
+    pub fn reset_branch(&self, name: &str, rev: &str) -> Result<Cid> {
+        let branches_tree = self.tree(BRANCHES)?;
+        if !branches_tree.contains_key(name.as_bytes())? {
+            return Err(Error::NotFound(format!("branch '{}'", name)));
+        }
+
+        let head = self.resolve(rev)?;
+        self.get_commit(&head)?;
+        branches_tree.insert(name.as_bytes(), head.to_bytes())?;
+        Ok(head)
+    }
+
+    /// Materialises the graph as it existed at a revision (branch head or commit CID).
+    pub fn checkout(&self, rev: &str) -> Result<Graph> {
+        let commit_cid = self.resolve(rev)?;
+        self.graph_from_snapshot(&self.get_snapshot(&commit_cid)?)
+    }
+
+    /// Builds a `Graph` from the blocks referenced by a snapshot.
+    ///
+    /// Edges and incidences come from their stored blocks. Adjacency entries
+    /// not backed by an edge block (snapshots written before edges were stored,
+    /// or edges added through `add_edge`) become data-layer edges whose id is
+    /// derived from their endpoints.
+    pub fn graph_from_snapshot(&self, snapshot: &Snapshot) -> Result<Graph> {
+        snapshot_graph(snapshot, |cid| self.get_block(cid))
+    }
+}
+
+/// Reads and decodes a DAG-CBOR block through `get_block`.
+pub(crate) fn read_dag<T, F>(get_block: &F, cid: &Cid) -> Result<Option<T>>
+where
+    T: DeserializeOwned,
+    F: Fn(&Cid) -> Result<Option<Vec<u8>>>,
+{
+    match get_block(cid)? {
+        Some(data) => Ok(Some(serde_ipld_dagcbor::from_slice(&data).map_err(|e| Error::Serialization(e.to_string()))?)),
+        None => Ok(None),
+    }
+}
+
+/// Walks the history from `head` as `EngiDB::log` does, reading blocks through `get_block`.
+pub(crate) fn walk_log<F>(head: Cid, get_block: F) -> Result<Vec<(Cid, Commit)>>
+where
+    F: Fn(&Cid) -> Result<Option<Vec<u8>>>,
+{
+    let mut entries = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([head]);
+
+    while let Some(commit_cid) = queue.pop_front() {
+        if !seen.insert(commit_cid) {
+            continue;
+        }
+        let commit = match read_dag::<Commit, _>(&get_block, &commit_cid)? {
+            Some(commit) => commit,
+            None if commit_cid == head => return Err(Error::NotFound(format!("commit {}", commit_cid))),
+            None => continue,
+        };
+        queue.extend(commit.parents.iter().copied());
+        entries.push((commit_cid, commit));
+    }
+
+    Ok(entries)
+}
+
+/// Error for a commit written before commits recorded their graph state.
+fn no_snapshot(commit_cid: &Cid) -> Error {
+    Error::NotFound(format!("snapshot of commit {}, written before commits recorded one", commit_cid))
+}
+
+/// Reads the graph recorded by a commit through `get_block`.
+pub(crate) fn commit_graph<F>(commit_cid: &Cid, get_block: F) -> Result<Graph>
+where
+    F: Fn(&Cid) -> Result<Option<Vec<u8>>>,
+{
+    let commit: Commit = read_dag(&get_block, commit_cid)?
+        .ok_or_else(|| Error::NotFound(format!("commit {}", commit_cid)))?;
+    let transaction: Transaction = read_dag(&get_block, &commit.transaction_cid)?
+        .ok_or_else(|| Error::NotFound(format!("transaction {}", commit.transaction_cid)))?;
+    let root = transaction.root.ok_or_else(|| no_snapshot(commit_cid))?;
+    let snapshot: Snapshot = read_dag(&get_block, &root)?
+        .ok_or_else(|| Error::NotFound(format!("snapshot {}", root)))?;
+    snapshot_graph(&snapshot, get_block)
+}
+
+/// Builds a `Graph` from a snapshot as `EngiDB::graph_from_snapshot` does,
+/// reading blocks through `get_block`.
+pub(crate) fn snapshot_graph<F>(snapshot: &Snapshot, get_block: F) -> Result<Graph>
+where
+    F: Fn(&Cid) -> Result<Option<Vec<u8>>>,
+{
+    let mut node = Vec::with_capacity(snapshot.vertices.len());
+    let mut node_ids = HashMap::new();
+    for cid in &snapshot.vertices {
+        let vertex: Node = read_dag(&get_block, cid)?
+            .ok_or_else(|| Error::NotFound(format!("vertex block {}", cid)))?;
+        node_ids.insert(*cid, vertex.id.clone());
+        node.push(vertex);
+    }
+
+    let mut edge = Vec::with_capacity(snapshot.hyperedges.len());
+    for cid in &snapshot.hyperedges {
+        edge.push(read_dag::<Edge, _>(&get_block, cid)?.ok_or_else(|| Error::NotFound(format!("edge block {}", cid)))?);
+    }
+    let mut incidence = Vec::with_capacity(snapshot.incidences.len());
+    for cid in &snapshot.incidences {
+        incidence.push(read_dag::<Incidence, _>(&get_block, cid)?.ok_or_else(|| Error::NotFound(format!("incidence block {}", cid)))?);
+    }
+
+    let stored: HashSet<(String, String, String)> = edge_endpoints(&edge, &incidence)
+        .into_iter()
+        .map(|(edge, source, target)| (source.to_string(), edge.kind.clone(), target.to_string()))
+        .collect();
+    for snapshot_edge in &snapshot.edges {
+        let (Some(source), Some(target)) = (node_ids.get(&snapshot_edge.source), node_ids.get(&snapshot_edge.target)) else {
+            return Err(Error::NotFound(format!("edge endpoint for '{}'", snapshot_edge.kind)));
+        };
+        if stored.contains(&(source.clone(), snapshot_edge.kind.clone(), target.clone())) {
+            continue;
+        }
+        let id = format!("{}:{}:{}", source, snapshot_edge.kind, target);
+        for (role, node) in [("source", source), ("target", target)] {
+            incidence.push(Incidence {
+                node: node.clone(),
+                edge: id.clone(),
+                role: role.to_string(),
+                pos: None,
+                properties: IndexMap::new(),
+            });
+        }
+        edge.push(Edge {
+            id,
+            layer: Layer::Data,
+            kind: snapshot_edge.kind.clone(),
+            properties: IndexMap::new(),
+        });
+    }
+
+    Ok(Graph { node, edge, incidence })
+}
@@ -0,0 +1,293 @@
+//! Lossless storage of edges and incidences.
+//!
+//! Every `Edge` and `Incidence` is stored as its own DAG-CBOR block. The
+//! `hyperedges` tree maps edge ids to the CID of their block, and the
+//! `incidences` tree maps the tuple key `(edge, node, role[, pos])` to
+//! incidence CIDs so that all incidences of an edge share a key prefix. The
+//! `node_incidences` tree holds the same keys with the node in front, so the
+//! edges touching a node share a key prefix as well. The adjacency derived
+//! from them is kept in the indexes of the `adjacency` module, where every
+//! edge owns its entries.
+
+use crate::adjacency::{Adjacency, AdjacencyTrees};
+use crate::key::{Key, KeyReader};
+use crate::{cid_from_bytes, edge_endpoints, EngiDB, Error, Result, HYPEREDGES, INCIDENCES, IPLD_BLOCKS, NODE_INCIDENCES};
+use cid::Cid;
+use kotoba_types::{Edge, Graph, Incidence};
+use sled::transaction::{ConflictableTransactionError, ConflictableTransactionResult, TransactionalTree};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// Common key prefix of the incidences of an edge.
+pub(crate) fn incidence_prefix(edge: &str) -> Key {
+    Key::new().str(edge)
+}
+
+/// Key of an incidence in the `incidences` tree.
+pub(crate) fn incidence_key(incidence: &Incidence) -> Vec<u8> {
+    let key = incidence_prefix(&incidence.edge).str(&incidence.node).str(&incidence.role);
+    match incidence.pos {
+        Some(pos) => key.uint(pos as u64).into_bytes(),
+        None => key.into_bytes(),
+    }
+}
+
+/// Key of an incidence in the `node_incidences` tree, from its key in the
+/// `incidences` tree: the node moves in front of the edge.
+pub(crate) fn node_incidence_key(key: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = KeyReader::new(key);
+    let (edge, node) = (reader.str()?, reader.str()?);
+    let mut by_node = Key::new().str(node).str(edge).into_bytes();
+    by_node.extend_from_slice(reader.rest());
+    Ok(by_node)
+}
+
+/// Edge and incidence changes staged for a sled transaction.
+#[derive(Default)]
+pub(crate) struct EdgeWrites {
+    blocks: Vec<(Cid, Vec<u8>)>,
+    hyperedges: Vec<(String, Option<Cid>)>,
+    incidences: Vec<(Vec<u8>, Option<Cid>)>,
+    adjacency: Vec<(Adjacency, bool)>,
+}
+
+impl EdgeWrites {
+    /// Stores an edge, replacing any edge with the same id.
+    pub fn put_edge(&mut self, edge: &Edge) -> Result<Cid> {
+        let cid = self.put_block(edge)?;
+        self.hyperedges.push((edge.id.clone(), Some(cid)));
+        Ok(cid)
+    }
+
+    /// Stores an incidence, replacing any incidence with the same key.
+    pub fn put_incidence(&mut self, incidence: &Incidence) -> Result<Cid> {
+        let cid = self.put_block(incidence)?;
+        self.incidences.push((incidence_key(incidence), Some(cid)));
+        Ok(cid)
+    }
+
+    pub fn remove_edge(&mut self, id: &str) {
+        self.hyperedges.push((id.to_string(), None));
+    }
+
+    pub fn remove_incidence(&mut self, key: Vec<u8>) {
+        self.incidences.push((key, None));
+    }
+
+    pub fn put_adjacency(&mut self, entry: Adjacency) {
+        self.adjacency.push((entry, true));
+    }
+
+    pub fn remove_adjacency(&mut self, entry: Adjacency) {
+        self.adjacency.push((entry, false));
+    }
+
+    fn extend(&mut self, other: EdgeWrites) {
+        self.blocks.extend(other.blocks);
+        self.hyperedges.extend(other.hyperedges);
+        self.incidences.extend(other.incidences);
+        self.adjacency.extend(other.adjacency);
+    }
+
+    /// Number of staged writes.
+    pub fn len(&self) -> usize {
+        self.blocks.len() + self.hyperedges.len() + self.incidences.len() + self.adjacency.len()
+    }
+
+    /// Writes the staged changes outside a transaction, one batch per tree.
+    pub fn apply_batches(&self, db: &EngiDB) -> Result<()> {
+        let mut batches: [sled::Batch; 4] = Default::default();
+        for (cid, data) in &self.blocks {
+            batches[0].insert(cid.to_bytes(), data.as_slice());
+        }
+        for (id, cid) in &self.hyperedges {
+            match cid {
+                Some(cid) => batches[1].insert(id.as_bytes(), cid.to_bytes()),
+                None => batches[1].remove(id.as_bytes()),
+            }
+        }
+        for (key, cid) in &self.incidences {
+            let by_node = node_incidence_key(key)?;
+            match cid {
+                Some(cid) => {
+                    batches[2].insert(key.as_slice(), cid.to_bytes());
+                    batches[3].insert(by_node, &[]);
+                }
+                None => {
+                    batches[2].remove(key.as_slice());
+                    batches[3].remove(by_node);
+                }
+            }
+        }
+        let [b0, b1, b2, b3] = batches;
+        db.tree(IPLD_BLOCKS)?.apply_batch(b0)?;
+        db.tree(HYPEREDGES)?.apply_batch(b1)?;
+        db.tree(INCIDENCES)?.apply_batch(b2)?;
+        db.tree(NODE_INCIDENCES)?.apply_batch(b3)?;
+        db.write_adjacency(self.adjacency.iter().map(|(entry, insert)| (entry, *insert)))
+    }
+
+    fn put_block<T: serde::Serialize>(&mut self, value: &T) -> Result<Cid> {
+        let data = serde_ipld_dagcbor::to_vec(value).map_err(|e| Error::Serialization(e.to_string()))?;
+        let cid = crate::calculate_cid(&data);
+        self.blocks.push((cid, data));
+        Ok(cid)
+    }
+
+    /// Applies the staged changes in order, so later changes win.
+    pub fn apply(
+        &self,
+        blocks: &TransactionalTree,
+        hyperedges: &TransactionalTree,
+        incidences: &TransactionalTree,
+        node_incidences: &TransactionalTree,
+        adjacency: &AdjacencyTrees,
+    ) -> ConflictableTransactionResult<(), Error> {
+        for (cid, data) in &self.blocks {
+            blocks.insert(cid.to_bytes(), data.as_slice())?;
+        }
+        for (id, cid) in &self.hyperedges {
+            match cid {
+                Some(cid) => hyperedges.insert(id.as_bytes(), cid.to_bytes())?,
+                None => hyperedges.remove(id.as_bytes())?,
+            };
+        }
+        for (key, cid) in &self.incidences {
+            let by_node = node_incidence_key(key).map_err(ConflictableTransactionError::Abort)?;
+            match cid {
+                Some(cid) => {
+                    incidences.insert(key.as_slice(), cid.to_bytes())?;
+                    node_incidences.insert(by_node, &[])?;
+                }
+                None => {
+                    incidences.remove(key.as_slice())?;
+                    node_incidences.remove(by_node)?;
+                }
+            };
+        }
+        for (entry, insert) in &self.adjacency {
+            if *insert {
+                adjacency.insert(entry)?;
+            } else {
+                adjacency.remove(entry)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EngiDB {
+    /// Reads the stored edge with the given id.
+    pub fn get_edge(&self, id: &str) -> Result<Option<Edge>> {
+        match self.tree(HYPEREDGES)?.get(id.as_bytes())? {
+            Some(cid) => self.get_dag(&cid_from_bytes(&cid)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the stored incidences of an edge.
+    pub fn get_incidences(&self, edge: &str) -> Result<Vec<Incidence>> {
+        let mut incidences = Vec::new();
+        for result in self.tree(INCIDENCES)?.scan_prefix(incidence_prefix(edge)) {
+            let (_, cid) = result?;
+            let cid = cid_from_bytes(&cid)?;
+            incidences.push(self.get_dag(&cid)?.ok_or_else(|| Error::NotFound(format!("incidence block {}", cid)))?);
+        }
+        Ok(incidences)
+    }
+
+    /// Stages the edges and incidences of `graph`, encoding them in parallel.
+    ///
+    /// Each edge in the graph is stored together with exactly the incidences
+    /// the graph gives it; incidences it had before but no longer has are
+    /// removed.
+    pub(crate) fn stage_graph_edges(&self, graph: &Graph) -> Result<EdgeWrites> {
+        let mut new_keys: HashMap<&str, HashSet<Vec<u8>>> = HashMap::new();
+        for incidence in &graph.incidence {
+            new_keys.entry(incidence.edge.as_str()).or_default().insert(incidence_key(incidence));
+        }
+        let incidences_tree = self.tree(INCIDENCES)?;
+
+        let incidences = graph.incidence.par_iter().map(|incidence| {
+            let mut writes = EdgeWrites::default();
+            writes.put_incidence(incidence)?;
+            Ok(writes)
+        });
+        let edges = graph.edge.par_iter().map(|edge| {
+            let mut writes = EdgeWrites::default();
+            self.stage_adjacency_removal(&edge.id, &mut writes)?;
+            writes.put_edge(edge)?;
+            let keep = new_keys.get(edge.id.as_str());
+            for result in incidences_tree.scan_prefix(incidence_prefix(&edge.id)) {
+                let (key, _) = result?;
+                if !keep.is_some_and(|keep| keep.contains(key.as_ref())) {
+                    writes.remove_incidence(key.to_vec());
+                }
+            }
+            Ok(writes)
+        });
+        incidences.chain(edges).try_reduce(EdgeWrites::default, |mut all, writes| {
+            all.extend(writes);
+            Ok(all)
+        })
+    }
+
+    /// Stages the removal of an edge and all its incidences.
+    pub(crate) fn stage_edge_removal(&self, id: &str, writes: &mut EdgeWrites) -> Result<()> {
+        self.stage_adjacency_removal(id, writes)?;
+        writes.remove_edge(id);
+        for result in self.tree(INCIDENCES)?.scan_prefix(incidence_prefix(id)) {
+            let (key, _) = result?;
+            writes.remove_incidence(key.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Stages the removal of the adjacency entries a stored edge contributes.
+    fn stage_adjacency_removal(&self, id: &str, writes: &mut EdgeWrites) -> Result<()> {
+        let Some(edge) = self.get_edge(id)? else { return Ok(()) };
+        let incidences = self.get_incidences(id)?;
+        for (edge, source, target) in edge_endpoints(std::slice::from_ref(&edge), &incidences) {
+            if let (Some(source), Some(target)) = (self.entity_vertex(source)?, self.entity_vertex(target)?) {
+                writes.remove_adjacency(Adjacency {
+                    source,
+                    kind: edge.kind.clone(),
+                    target,
+                    edge: Some(edge.id.clone()),
+                    layer: Some(edge.layer),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Ids of the stored edges with an incidence on `node`.
+    pub(crate) fn edges_touching(&self, node: &str) -> Result<Vec<String>> {
+        let mut edges = Vec::new();
+        for key in self.tree(NODE_INCIDENCES)?.scan_prefix(Key::new().str(node)).keys() {
+            let key = key?;
+            let mut key = KeyReader::new(&key);
+            key.str()?;
+            let edge = key.str()?;
+            if edges.last().map(String::as_str) != Some(edge) {
+                edges.push(edge.to_string());
+            }
+        }
+        Ok(edges)
+    }
+
+    /// Sorted CIDs of all stored edge blocks and all stored incidence blocks.
+    pub(crate) fn edge_record_cids(&self) -> Result<(Vec<Cid>, Vec<Cid>)> {
+        Ok((self.tree_cids(HYPEREDGES)?, self.tree_cids(INCIDENCES)?))
+    }
+
+    fn tree_cids(&self, tree: &str) -> Result<Vec<Cid>> {
+        let mut cids = Vec::new();
+        for result in self.tree(tree)?.iter() {
+            let (_, cid) = result?;
+            cids.push(cid_from_bytes(&cid)?);
+        }
+        cids.sort();
+        Ok(cids)
+    }
+}
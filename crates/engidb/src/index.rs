@@ -0,0 +1,253 @@
+//! Declarative secondary indexes on node properties.
+//!
+//! An `IndexDef` names a node kind and one or more of its properties. Every
+//! node of that kind gets one entry in the `property_index` tree, under the
+//! tuple key `(name, values.., entity)` with the values encoded so that byte
+//! order matches value order, which makes both equality and range lookups
+//! prefix scans. A
+//! node missing an indexed property is indexed with `null` for it, so an
+//! index covers every node of its kind.
+//!
+//! The list of definitions is kept in the same tree under a reserved key and
+//! read inside every write transaction, so entries stay in sync with each
+//! vertex write.
+
+use crate::{run_transaction, EngiDB, Error, Result, ENTITIES, IPLD_BLOCKS, PROPERTY_INDEX};
+use crate::entity::EntityRecord;
+use crate::key::{successor, Key};
+use crate::scan::kind_changes;
+use cid::Cid;
+use kotoba_types::Node;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sled::transaction::{ConflictableTransactionError, ConflictableTransactionResult, TransactionalTree};
+use std::collections::HashSet;
+use std::ops::{Bound, RangeBounds};
+
+/// Key holding the index definitions; entry keys start with the index name.
+pub(crate) const DEFINITIONS: &[u8] = &[0];
+
+/// Number of entities indexed per transaction when an index is created.
+const BUILD_BATCH: usize = 1_000;
+
+/// Declaration of a property index.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexDef {
+    pub name: String,
+    /// Node kind the index covers.
+    pub kind: String,
+    /// Indexed properties, most significant first.
+    pub properties: Vec<String>,
+}
+
+impl IndexDef {
+    /// Key of the entry a node has in this index, if it is of the indexed kind.
+    fn entry(&self, node: &Node) -> Option<Vec<u8>> {
+        if node.kind != self.kind {
+            return None;
+        }
+        let mut key = Key::new().str(&self.name);
+        for property in &self.properties {
+            key = key.value(node.properties.get(property).unwrap_or(&Value::Null));
+        }
+        Some(key.str(&node.id).into_bytes())
+    }
+}
+
+fn decode_definitions(bytes: Option<&[u8]>) -> Result<Vec<IndexDef>> {
+    match bytes {
+        Some(bytes) => serde_ipld_dagcbor::from_slice(bytes).map_err(|e| Error::Serialization(e.to_string())),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn encode_definitions(definitions: &[IndexDef]) -> Result<Vec<u8>> {
+    serde_ipld_dagcbor::to_vec(definitions).map_err(|e| Error::Serialization(e.to_string()))
+}
+
+/// Index entries to remove and insert when an entity's content changes.
+pub(crate) fn index_changes(definitions: &[IndexDef], old: Option<&Node>, new: Option<&Node>) -> Vec<(Vec<u8>, bool)> {
+    let entries = |node: Option<&Node>| -> HashSet<Vec<u8>> {
+        node.map(|node| definitions.iter().filter_map(|d| d.entry(node)).collect()).unwrap_or_default()
+    };
+    let (old, new) = (entries(old), entries(new));
+    let mut changes: Vec<_> = old.difference(&new).map(|key| (key.clone(), false)).collect();
+    changes.extend(new.difference(&old).map(|key| (key.clone(), true)));
+    changes
+}
+
+/// Updates the property and kind index entries of `entity` as its content
+/// moves from `old` to `new`.
+///
+/// Both blocks are read through the transaction, so `new` may have been
+/// written earlier in it.
+pub(crate) fn reindex(
+    blocks: &TransactionalTree,
+    indexes: &TransactionalTree,
+    kinds: &TransactionalTree,
+    entity: &str,
+    old: Option<Cid>,
+    new: Option<Cid>,
+) -> ConflictableTransactionResult<(), Error> {
+    let definitions = decode_definitions(indexes.get(DEFINITIONS)?.as_deref()).map_err(ConflictableTransactionError::Abort)?;
+    let load = |cid: Option<Cid>| -> ConflictableTransactionResult<Option<Node>, Error> {
+        let Some(cid) = cid else { return Ok(None) };
+        let data = blocks.get(cid.to_bytes())?
+            .ok_or_else(|| ConflictableTransactionError::Abort(Error::NotFound(format!("block {} of entity '{}'", cid, entity))))?;
+        let node = serde_ipld_dagcbor::from_slice(&data)
+            .map_err(|e| ConflictableTransactionError::Abort(Error::Serialization(e.to_string())))?;
+        Ok(Some(node))
+    };
+    let (old, new) = (load(old)?, load(new)?);
+    for (tree, changes) in [
+        (indexes, index_changes(&definitions, old.as_ref(), new.as_ref())),
+        (kinds, kind_changes(old.as_ref(), new.as_ref())),
+    ] {
+        for (key, insert) in changes {
+            if insert {
+                tree.insert(key, entity.as_bytes())?;
+            } else {
+                tree.remove(key)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl EngiDB {
+    /// Declares a property index and indexes the nodes already stored.
+    ///
+    /// Declaring an index identical to an existing one does nothing; reusing
+    /// the name of a different index fails with `Error::AlreadyExists`.
+    pub fn create_index(&self, definition: IndexDef) -> Result<()> {
+        if definition.name.is_empty() {
+            return Err(Error::InvalidArgument("empty index name".to_string()));
+        }
+        let indexes = self.tree(PROPERTY_INDEX)?;
+        let created = run_transaction(&indexes, |indexes| {
+            let mut definitions = decode_definitions(indexes.get(DEFINITIONS)?.as_deref())
+                .map_err(ConflictableTransactionError::Abort)?;
+            match definitions.iter().find(|d| d.name == definition.name) {
+                Some(existing) if *existing == definition => return Ok(false),
+                Some(_) => {
+                    return Err(ConflictableTransactionError::Abort(Error::AlreadyExists(format!("index '{}'", definition.name))));
+                }
+                None => definitions.push(definition.clone()),
+            }
+            indexes.insert(DEFINITIONS, encode_definitions(&definitions).map_err(ConflictableTransactionError::Abort)?)?;
+            Ok(true)
+        })?;
+        if !created {
+            return Ok(());
+        }
+
+        // Writes from here on maintain the index themselves; index what is
+        // already stored in small transactions, reading each entity afresh.
+        let trees = (&self.tree(ENTITIES)?, &self.tree(IPLD_BLOCKS)?, &indexes);
+        let mut entities = self.tree(ENTITIES)?.iter().keys().peekable();
+        while entities.peek().is_some() {
+            let batch = entities.by_ref().take(BUILD_BATCH).collect::<std::result::Result<Vec<_>, _>>()?;
+            run_transaction(trees, |(entities, blocks, indexes)| {
+                for entity in &batch {
+                    let Some(bytes) = entities.get(entity)? else { continue };
+                    let record = EntityRecord::from_bytes(&bytes).map_err(ConflictableTransactionError::Abort)?;
+                    let Some(cid) = record.cid else { continue };
+                    let Some(data) = blocks.get(cid.to_bytes())? else { continue };
+                    let node: Node = serde_ipld_dagcbor::from_slice(&data)
+                        .map_err(|e| ConflictableTransactionError::Abort(Error::Serialization(e.to_string())))?;
+                    if let Some(key) = definition.entry(&node) {
+                        indexes.insert(key, node.id.as_bytes())?;
+                    }
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Removes a property index and its entries.
+    pub fn drop_index(&self, name: &str) -> Result<()> {
+        let indexes = self.tree(PROPERTY_INDEX)?;
+        run_transaction(&indexes, |indexes| {
+            let mut definitions = decode_definitions(indexes.get(DEFINITIONS)?.as_deref())
+                .map_err(ConflictableTransactionError::Abort)?;
+            let count = definitions.len();
+            definitions.retain(|d| d.name != name);
+            if definitions.len() == count {
+                return Err(ConflictableTransactionError::Abort(Error::NotFound(format!("index '{}'", name))));
+            }
+            indexes.insert(DEFINITIONS, encode_definitions(&definitions).map_err(ConflictableTransactionError::Abort)?)?;
+            Ok(())
+        })?;
+
+        let mut batch = sled::Batch::default();
+        for key in indexes.scan_prefix(Key::new().str(name)).keys() {
+            batch.remove(key?);
+        }
+        indexes.apply_batch(batch)?;
+        Ok(())
+    }
+
+    /// Lists the declared property indexes.
+    pub fn list_indexes(&self) -> Result<Vec<IndexDef>> {
+        decode_definitions(self.tree(PROPERTY_INDEX)?.get(DEFINITIONS)?.as_deref())
+    }
+
+    /// Nodes whose leading indexed properties equal `values`, in index order.
+    ///
+    /// `values` may be shorter than the index's property list, in which case
+    /// only that many properties are compared.
+    pub fn lookup_index(&self, name: &str, values: &[Value]) -> Result<Vec<Node>> {
+        let prefix = self.index_prefix(name, values, 0)?.into_bytes();
+        let end = successor(prefix.clone());
+        self.index_nodes(prefix, end)
+    }
+
+    /// Nodes whose leading indexed properties equal `prefix` and whose next
+    /// property lies in `range`, in index order.
+    pub fn range_index(&self, name: &str, prefix: &[Value], range: impl RangeBounds<Value>) -> Result<Vec<Node>> {
+        let base = self.index_prefix(name, prefix, 1)?;
+        let with = |value: &Value| base.clone().value(value).into_bytes();
+        let start = match range.start_bound() {
+            Bound::Included(value) => with(value),
+            Bound::Excluded(value) => successor(with(value)),
+            Bound::Unbounded => base.clone().into_bytes(),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(value) => successor(with(value)),
+            Bound::Excluded(value) => with(value),
+            Bound::Unbounded => successor(base.into_bytes()),
+        };
+        self.index_nodes(start, end)
+    }
+
+    /// Key prefix for `values`, leaving room for `more` further properties.
+    fn index_prefix(&self, name: &str, values: &[Value], more: usize) -> Result<Key> {
+        let definition = self.list_indexes()?
+            .into_iter()
+            .find(|d| d.name == name)
+            .ok_or_else(|| Error::NotFound(format!("index '{}'", name)))?;
+        if values.len() + more > definition.properties.len() {
+            return Err(Error::InvalidArgument(format!(
+                "index '{}' covers {} properties", name, definition.properties.len(),
+            )));
+        }
+        Ok(values.iter().fold(Key::new().str(name), |key, value| key.value(value)))
+    }
+
+    fn index_nodes(&self, start: Vec<u8>, end: Vec<u8>) -> Result<Vec<Node>> {
+        let mut nodes = Vec::new();
+        if start >= end {
+            return Ok(nodes);
+        }
+        for result in self.tree(PROPERTY_INDEX)?.range(start..end) {
+            let (_, entity) = result?;
+            let entity = std::str::from_utf8(&entity)?;
+            let cid = self.entity_record(entity)?
+                .and_then(|r| r.cid)
+                .ok_or_else(|| Error::NotFound(format!("indexed entity '{}'", entity)))?;
+            nodes.push(self.get_dag(&cid)?.ok_or_else(|| Error::NotFound(format!("vertex block {}", cid)))?);
+        }
+        Ok(nodes)
+    }
+}
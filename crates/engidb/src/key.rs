@@ -0,0 +1,420 @@
+//! Tuple key codec for composite keys.
+//!
+//! A key is a sequence of tagged elements. Integers are a tag and eight
+//! big-endian bytes, so they sort numerically. Strings are a tag, a
+//! big-endian `u32` length and the raw bytes, so any byte may appear in them.
+//! Every element is self-delimiting, which makes the encoding of the leading
+//! elements of a key a prefix of the key itself: prefix scans select whole
+//! components and never match a component that merely starts the same way.
+//!
+//! JSON values in property index keys have an encoding of their own that
+//! sorts by value rather than by length, so that range lookups are prefix
+//! and range scans too. Integers keep their exact value, however large.
+//!
+//! Databases written before the codec joined key components with `:` or NUL
+//! bytes; the first format migration rewrites them.
+
+use crate::adjacency::Adjacency;
+use crate::{run_transaction, EngiDB, Error, Result, EDGES, EDGES_IN, EDGE_KINDS, META};
+use kotoba_types::Layer;
+use serde_json::Value;
+use sled::transaction::{ConflictableTransactionResult, TransactionalTree};
+
+const UINT: u8 = 0x10;
+const STR: u8 = 0x11;
+
+/// Builder for a composite key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Key(Vec<u8>);
+
+impl Key {
+    pub fn new() -> Self {
+        Key::default()
+    }
+
+    pub fn uint(mut self, value: u64) -> Self {
+        self.0.push(UINT);
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn str(mut self, value: &str) -> Self {
+        let len = u32::try_from(value.len()).expect("key components are shorter than 4 GiB");
+        self.0.push(STR);
+        self.0.extend_from_slice(&len.to_be_bytes());
+        self.0.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    /// Appends the order-preserving encoding of a JSON value.
+    ///
+    /// Values sort by type first (null, false, true, numbers, strings, arrays,
+    /// objects) and then by value. Numbers sort by their exact value: the
+    /// nearest `f64` comes first, so integers and floats interleave, followed
+    /// by the distance of an integer from it, so integers beyond 2^53 stay
+    /// distinct. Strings are escaped and terminated so that no encoding is a
+    /// prefix of another.
+    pub fn value(mut self, value: &Value) -> Self {
+        let out = &mut self.0;
+        match value {
+            Value::Null => out.push(1),
+            Value::Bool(false) => out.push(2),
+            Value::Bool(true) => out.push(3),
+            Value::Number(n) => {
+                out.push(4);
+                let (float, offset) = split_number(n);
+                let bits = float.to_bits();
+                let bits = if bits >> 63 == 1 { !bits } else { bits | 1 << 63 };
+                out.extend_from_slice(&bits.to_be_bytes());
+                out.extend_from_slice(&(offset as u16 ^ 1 << 15).to_be_bytes());
+            }
+            Value::String(s) => {
+                out.push(5);
+                escape(s.as_bytes(), out);
+            }
+            Value::Array(_) => {
+                out.push(6);
+                escape(value.to_string().as_bytes(), out);
+            }
+            Value::Object(_) => {
+                out.push(7);
+                escape(value.to_string().as_bytes(), out);
+            }
+        }
+        self
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl AsRef<[u8]> for Key {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Key> for sled::IVec {
+    fn from(key: Key) -> Self {
+        key.0.into()
+    }
+}
+
+/// A number as the nearest `f64` and, for integers, their offset from it.
+///
+/// The pairs order numbers by exact value: integers that round to the same
+/// float differ in the offset, which is at most half a unit in the last place.
+pub(crate) fn split_number(n: &serde_json::Number) -> (f64, i16) {
+    // Adding 0.0 turns -0.0 into 0.0.
+    let float = n.as_f64().unwrap_or(0.0) + 0.0;
+    let exact = n.as_i64().map(i128::from).or_else(|| n.as_u64().map(i128::from));
+    let offset = exact.map_or(0, |exact| exact - float as i128);
+    (float, i16::try_from(offset).expect("integers lie within 2048 of the nearest float"))
+}
+
+fn escape(bytes: &[u8], out: &mut Vec<u8>) {
+    for &b in bytes {
+        out.push(b);
+        if b == 0 {
+            out.push(0xff);
+        }
+    }
+    out.extend_from_slice(&[0, 0]);
+}
+
+/// Reads the elements of a composite key in order.
+pub(crate) struct KeyReader<'k> {
+    key: &'k [u8],
+    rest: &'k [u8],
+}
+
+impl<'k> KeyReader<'k> {
+    pub fn new(key: &'k [u8]) -> Self {
+        KeyReader { key, rest: key }
+    }
+
+    pub fn uint(&mut self) -> Result<u64> {
+        let bytes = self.element(UINT, 8)?;
+        Ok(u64::from_be_bytes(bytes.try_into().expect("element has eight bytes")))
+    }
+
+    pub fn str(&mut self) -> Result<&'k str> {
+        let len = self.element(STR, 4)?;
+        let len = u32::from_be_bytes(len.try_into().expect("length has four bytes")) as usize;
+        let bytes = self.take(len)?;
+        Ok(std::str::from_utf8(bytes)?)
+    }
+
+    /// The elements not read yet, still encoded.
+    pub fn rest(&self) -> &'k [u8] {
+        self.rest
+    }
+
+    /// Whether every element has been read.
+    pub fn is_empty(&self) -> bool {
+        self.rest.is_empty()
+    }
+
+    fn element(&mut self, tag: u8, len: usize) -> Result<&'k [u8]> {
+        if self.take(1)? != [tag] {
+            return Err(self.malformed());
+        }
+        self.take(len)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'k [u8]> {
+        if self.rest.len() < len {
+            return Err(self.malformed());
+        }
+        let (head, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Ok(head)
+    }
+
+    fn malformed(&self) -> Error {
+        Error::Serialization(format!("malformed key {:02x?}", self.key))
+    }
+}
+
+/// Smallest key greater than every key starting with `key`.
+pub(crate) fn successor(mut key: Vec<u8>) -> Vec<u8> {
+    while key.last() == Some(&0xff) {
+        key.pop();
+    }
+    if let Some(last) = key.last_mut() {
+        *last += 1;
+    }
+    key
+}
+
+/// Key in the `meta` tree marking a database whose composite keys use the codec.
+const TUPLE_KEYS: &[u8] = b"tuple_keys";
+
+/// Removals and insertions for one tree.
+#[derive(Default)]
+struct Rewrite {
+    remove: Vec<sled::IVec>,
+    insert: Vec<(Vec<u8>, sled::IVec)>,
+}
+
+impl Rewrite {
+    /// Schedules every key of `tree` for removal.
+    fn clear(tree: &sled::Tree) -> Result<Self> {
+        let mut rewrite = Rewrite::default();
+        for key in tree.iter().keys() {
+            rewrite.remove.push(key?);
+        }
+        Ok(rewrite)
+    }
+
+    fn apply(&self, tree: &TransactionalTree) -> ConflictableTransactionResult<(), Error> {
+        for key in &self.remove {
+            tree.remove(key)?;
+        }
+        for (key, value) in &self.insert {
+            tree.insert(key.as_slice(), value)?;
+        }
+        Ok(())
+    }
+}
+
+impl EngiDB {
+    /// Rewrites adjacency keys from the separator-joined format to the tuple codec.
+    ///
+    /// Everything is written in a single transaction together with a marker,
+    /// so the step is a no-op when it runs again before the format version is
+    /// recorded. A key that cannot be decoded fails the step before anything
+    /// is written.
+    pub(crate) fn migrate_legacy_keys(&self) -> Result<()> {
+        let meta = self.tree(META)?;
+        if meta.contains_key(TUPLE_KEYS)? {
+            return Ok(());
+        }
+        let (edges, edges_in, edge_kinds) =
+            (self.tree(EDGES)?, self.tree(EDGES_IN)?, self.tree(EDGE_KINDS)?);
+
+        let mut adjacency = [Rewrite::clear(&edges)?, Rewrite::clear(&edges_in)?, Rewrite::clear(&edge_kinds)?];
+        let mut undecodable = Vec::new();
+        for result in edges.iter() {
+            let (key, value) = result?;
+            let Some(entry) = self.legacy_adjacency(&key, &value)? else {
+                undecodable.push(String::from_utf8_lossy(&key).into_owned());
+                continue;
+            };
+            let value = sled::IVec::from(entry.layer.map(|layer| layer.as_str()).unwrap_or_default());
+            for (rewrite, key) in adjacency.iter_mut().zip(entry.keys()) {
+                rewrite.insert.push((key, value.clone()));
+            }
+        }
+        if let Some(example) = undecodable.first() {
+            return Err(Error::Migration(format!(
+                "{} adjacency keys cannot be decoded, such as '{}'; nothing was migrated",
+                undecodable.len(), example,
+            )));
+        }
+
+        let trees = (&edges, &edges_in, &edge_kinds, &meta);
+        let [edges_rw, edges_in_rw, edge_kinds_rw] = &adjacency;
+        run_transaction(trees, |(edges, edges_in, edge_kinds, meta)| {
+            edges_rw.apply(edges)?;
+            edges_in_rw.apply(edges_in)?;
+            edge_kinds_rw.apply(edge_kinds)?;
+            meta.insert(TUPLE_KEYS, &[1])?;
+            Ok(())
+        })
+    }
+
+    /// Decodes a `source:kind:target[:edge]` adjacency key.
+    ///
+    /// Kinds and edge ids may contain `:` themselves. Entries added without
+    /// an edge have an empty value, so their target follows the last
+    /// separator. Entries of a stored edge hold its layer; of the ways to split
+    /// off their target and edge id, the one naming a stored edge of that kind
+    /// is taken. Keys that do not decode to exactly one entry yield `None`.
+    fn legacy_adjacency(&self, key: &[u8], value: &[u8]) -> Result<Option<Adjacency>> {
+        let (Ok(key), Ok(value)) = (std::str::from_utf8(key), std::str::from_utf8(value)) else {
+            return Ok(None);
+        };
+        let Some((source, rest)) = key.split_once(':') else { return Ok(None) };
+        let Ok(source) = source.parse() else { return Ok(None) };
+        // Splits `kind:target` at its last separator.
+        let kind_and_target = |head: &str| -> Option<(String, u64)> {
+            let (kind, target) = head.rsplit_once(':')?;
+            Some((kind.to_string(), target.parse().ok()?))
+        };
+
+        if value.is_empty() {
+            return Ok(kind_and_target(rest).map(|(kind, target)| Adjacency { source, kind, target, edge: None, layer: None }));
+        }
+        let mut decoded = None;
+        for (at, _) in rest.match_indices(':') {
+            let (edge, Some((kind, target))) = (&rest[at + 1..], kind_and_target(&rest[..at])) else { continue };
+            let Some(stored) = self.get_edge(edge)?.filter(|stored| stored.kind == kind) else { continue };
+            if decoded.is_some() {
+                return Ok(None);
+            }
+            let layer = Layer::from_str(value).or(Some(stored.layer));
+            decoded = Some(Adjacency { source, kind, target, edge: Some(edge.to_string()), layer });
+        }
+        Ok(decoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn encoded(value: &Value) -> Vec<u8> {
+        Key::new().value(value).into_bytes()
+    }
+
+    /// Asserts that the encodings of `values` sort strictly in the given order.
+    fn assert_sorted(values: &[Value]) {
+        for pair in values.windows(2) {
+            assert!(encoded(&pair[0]) < encoded(&pair[1]), "{} sorts before {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn integers_sort_by_the_big_endian_value() {
+        let keys: Vec<_> = [0, 1, 255, 256, u64::MAX].iter().map(|n| Key::new().uint(*n).into_bytes()).collect();
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn strings_are_whole_components() {
+        let key = |s: &str| Key::new().str(s).into_bytes();
+        assert!(!key("ab").starts_with(&key("a")));
+        assert!(Key::new().str("a").str("b").into_bytes().starts_with(&key("a")));
+        assert_ne!(Key::new().str("a:b").into_bytes(), Key::new().str("a").str("b").into_bytes());
+    }
+
+    #[test]
+    fn values_sort_by_type_then_value() {
+        assert_sorted(&[
+            json!(null),
+            json!(false),
+            json!(true),
+            json!(-1),
+            json!(0),
+            json!(""),
+            json!("a"),
+            json!([]),
+            json!({}),
+        ]);
+    }
+
+    #[test]
+    fn numbers_sort_by_their_exact_value() {
+        let two_53 = 1u64 << 53;
+        assert_sorted(&[
+            json!(f64::MIN),
+            json!(i64::MIN),
+            json!(i64::MIN + 1),
+            json!(-(two_53 as i64) - 1),
+            json!(-(two_53 as i64)),
+            json!(-1.5),
+            json!(-1),
+            json!(0),
+            json!(0.5),
+            json!(1),
+            json!(1.5),
+            json!(two_53 - 1),
+            json!(two_53),
+            json!(two_53 + 1),
+            json!(two_53 + 2),
+            json!(i64::MAX - 1),
+            json!(i64::MAX),
+            json!(i64::MAX as u64 + 1),
+            json!(u64::MAX - 1),
+            json!(u64::MAX),
+            json!(1e300),
+            json!(f64::MAX),
+        ]);
+    }
+
+    #[test]
+    fn equal_numbers_encode_equally() {
+        assert_eq!(encoded(&json!(1)), encoded(&json!(1.0)));
+        assert_eq!(encoded(&json!(-0.0)), encoded(&json!(0)));
+        assert_eq!(encoded(&json!(1u64 << 60)), encoded(&json!((1u64 << 60) as f64)));
+        assert_eq!(encoded(&json!(-5i64)), encoded(&json!(-5.0)));
+    }
+
+    #[test]
+    fn strings_sort_bytewise_and_never_prefix_each_other() {
+        assert_sorted(&[json!(""), json!("\0"), json!("\0\0"), json!("a"), json!("a\0"), json!("a\u{1}"), json!("ab"), json!("b")]);
+        assert!(!encoded(&json!("ab")).starts_with(&encoded(&json!("a"))));
+        // Keys continue after a value without changing its order.
+        let key = |s: &str, id: &str| Key::new().value(&json!(s)).str(id).into_bytes();
+        assert!(key("a", "zzz") < key("ab", ""));
+    }
+
+    #[test]
+    fn readers_decode_what_keys_encode() {
+        let key = Key::new().str("node").uint(7).str("").into_bytes();
+        let mut reader = KeyReader::new(&key);
+        assert_eq!(reader.str().unwrap(), "node");
+        assert_eq!(reader.uint().unwrap(), 7);
+        assert!(!reader.is_empty());
+        assert_eq!(reader.str().unwrap(), "");
+        assert!(reader.is_empty());
+
+        let mut reader = KeyReader::new(&key);
+        assert!(matches!(reader.uint(), Err(Error::Serialization(_))));
+        let mut truncated = KeyReader::new(&key[..key.len() - 6]);
+        truncated.str().unwrap();
+        assert!(matches!(truncated.uint(), Err(Error::Serialization(_))));
+    }
+
+    #[test]
+    fn successors_bound_every_extension() {
+        let prefix = Key::new().str("a").into_bytes();
+        let end = successor(prefix.clone());
+        assert!(Key::new().str("a").value(&json!(u64::MAX)).into_bytes() < end);
+        assert!(Key::new().str("b").into_bytes() >= end);
+        assert_eq!(successor(vec![1, 0xff, 0xff]), vec![2]);
+    }
+}
@@ -2,16 +2,40 @@
 //! Pure Rust implementation using sled (no native dependencies).
 //! Merkle DAG note: Keep storage/process node boundaries minimal for stability.
 
-use kotoba_types::{Node, Graph};
+use kotoba_types::{Edge, Graph, Incidence, Node};
 use cid::Cid;
 use multihash::Multihash;
-use sled::{Db, Tree};
 use std::path::Path;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use sha2::{Digest, Sha256};
 
 pub mod adapter;
+pub mod adjacency;
+pub mod bulk;
+pub mod car;
+pub mod diff;
+pub mod entity;
+pub mod gc;
+pub mod history;
+pub mod hyperedge;
+pub mod index;
+mod key;
+pub mod memory;
+pub mod merge;
+pub mod migrate;
+pub mod namespace;
+pub mod scan;
+pub mod temporal;
+pub mod transact;
+pub mod verify;
+
+use adjacency::{Adjacency, AdjacencyTrees};
+use entity::EntityTrees;
+use hyperedge::EdgeWrites;
+use sled::transaction::{ConflictableTransactionResult, TransactionalTree};
+use key::Key;
+use temporal::Datom;
 
 #[cfg(feature = "fcdb")]
 pub use adapter::fcdb_adapter::FcdbAdapter;
@@ -26,6 +50,20 @@ pub enum Error {
     Serialization(String),
     #[error("UTF-8 error: {0}")]
     Utf8(#[from] std::str::Utf8Error),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Already exists: {0}")]
+    AlreadyExists(String),
+    #[error("Transaction error: {0}")]
+    Transaction(String),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("Migration error: {0}")]
+    Migration(String),
+    #[error("Unsupported by this backend: {0}")]
+    Unsupported(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 // Tree names for different data layers
@@ -33,16 +71,61 @@ const IPLD_BLOCKS: &str = "ipld_blocks";
 const VERTICES: &str = "vertices";
 const CID_TO_VERTEX: &str = "cid_to_vertex";
 const EDGES: &str = "edges";
+const EDGES_IN: &str = "edges_in";
+const EDGE_KINDS: &str = "edge_kinds";
 const COMMITS: &str = "commits";
 const TRANSACTIONS: &str = "transactions";
 const BRANCHES: &str = "branches";
+const DATOMS: &str = "datoms";
+const ENTITIES: &str = "entities";
+/// Entities the backfill split off an id shared by several legacy nodes, under that id.
+const RENAMED_ENTITIES: &str = "renamed_entities";
+const META: &str = "meta";
+const HYPEREDGES: &str = "hyperedges";
+const INCIDENCES: &str = "incidences";
+/// Incidence keys with the node in front, so the edges touching a node are found by a prefix scan.
+const NODE_INCIDENCES: &str = "node_incidences";
+const PROPERTY_INDEX: &str = "property_index";
+const NODE_KINDS: &str = "node_kinds";
+/// Commits removed by `gc`, so that history ending at them is not reported as damage.
+const PRUNED_COMMITS: &str = "pruned_commits";
+/// Roots of CAR files imported without a branch, which `gc` keeps like branch heads.
+const IMPORT_ROOTS: &str = "import_roots";
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Transaction {
     pub timestamp: u64,
-    // For now, we'll keep it simple. We can add more details later.
-    // pub added_vertices: Vec<u64>,
-    // pub added_edges: Vec<(u64, String, u64)>,
+    /// CID of the `Snapshot` describing the graph state at commit time.
+    ///
+    /// `None` for commits written before commits recorded their graph state.
+    #[serde(default)]
+    pub root: Option<Cid>,
+}
+
+/// Content-addressed graph state referenced by a commit.
+///
+/// Vertices and edges are identified by content (vertex CIDs) rather than by
+/// local vertex ids, and both sets are kept sorted, so two databases holding
+/// the same graph produce the same snapshot CID.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub vertices: Vec<Cid>,
+    /// `(source, kind, target)` adjacency between vertices.
+    pub edges: Vec<SnapshotEdge>,
+    /// Edge blocks, recording each edge's id, layer and properties.
+    #[serde(default)]
+    pub hyperedges: Vec<Cid>,
+    /// Incidence blocks linking the edges to their nodes.
+    #[serde(default)]
+    pub incidences: Vec<Cid>,
+}
+
+/// Edge entry of a `Snapshot`, keyed by the CIDs of its endpoints.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SnapshotEdge {
+    pub source: Cid,
+    pub kind: String,
+    pub target: Cid,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -55,28 +138,39 @@ pub struct Commit {
 
 
 /// EngiDB main database structure.
+///
+/// A handle works on one graph of the database: the default graph, or a
+/// named graph obtained through `EngiDB::namespace`.
 #[derive(Clone)]
 pub struct EngiDB {
     db: sled::Db,
+    namespace: Option<String>,
 }
 
 impl EngiDB {
-    /// Opens a database at the specified path.
+    /// Opens a database at the specified path, migrating it to the current format.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let db = sled::open(path)?;
-        Ok(EngiDB { db })
+        let db = EngiDB { db: sled::open(path)?, namespace: None };
+        migrate::migrate(&db.db, &db, &migrate::ENGIDB_LAYOUT, &migrate::MigrateOptions::default())?;
+        Ok(db)
+    }
+
+    /// Flushes all pending writes to disk.
+    pub fn flush(&self) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
     }
 
     /// Puts an IPLD block into the store.
     pub fn put_block(&self, cid: &Cid, data: &[u8]) -> Result<()> {
-        let tree = self.db.open_tree(IPLD_BLOCKS)?;
+        let tree = self.tree(IPLD_BLOCKS)?;
         tree.insert(cid.to_bytes(), data)?;
         Ok(())
     }
 
     /// Gets an IPLD block from the store.
     pub fn get_block(&self, cid: &Cid) -> Result<Option<Vec<u8>>> {
-        let tree = self.db.open_tree(IPLD_BLOCKS)?;
+        let tree = self.tree(IPLD_BLOCKS)?;
         let result = tree.get(cid.to_bytes())?
             .map(|v| v.to_vec());
         Ok(result)
@@ -84,86 +178,162 @@ impl EngiDB {
 
     /// Adds an edge between two vertices.
     pub fn add_edge(&self, source_id: u64, edge_type: &str, target_id: u64) -> Result<()> {
-        let tree = self.db.open_tree(EDGES)?;
-        let key = format!("{}:{}:{}", source_id, edge_type, target_id);
+        let entry = Adjacency { source: source_id, kind: edge_type.to_string(), target: target_id, edge: None, layer: None };
 
         // Check if edge already exists
-        if tree.contains_key(key.as_bytes())? {
+        let [key, _, _] = entry.keys();
+        if self.tree(EDGES)?.contains_key(key)? {
             return Ok(());
         }
 
-        // Add the edge (empty value)
-        tree.insert(key.as_bytes(), &[])?;
-        Ok(())
+        self.insert_adjacency(&entry)
     }
 
-    /// Gets all target vertex IDs for a given source vertex and edge type.
+    /// Gets all target vertex IDs for a given source vertex and edge type, in ascending order.
     pub fn get_edges_from(&self, source_id: u64, edge_type: &str) -> Result<Vec<u64>> {
-        let tree = self.db.open_tree(EDGES)?;
-        let prefix = format!("{}:{}:", source_id, edge_type);
+        let tree = self.tree(EDGES)?;
+        let prefix = Key::new().uint(source_id).str(edge_type);
         let mut targets = Vec::new();
 
-        for result in tree.scan_prefix(prefix.as_bytes()) {
-            let (key, _) = result?;
-            let key_str = std::str::from_utf8(&key)?;
-            if let Some(target_part) = key_str.split(':').nth(2) {
-                if let Ok(target_id) = target_part.parse::<u64>() {
-                    targets.push(target_id);
-                }
-            }
+        for result in tree.scan_prefix(prefix) {
+            let (key, value) = result?;
+            targets.push(Adjacency::from_outgoing(&key, &value)?.target);
         }
+        // Parallel edges between the same vertices each have their own key.
+        targets.sort_unstable();
+        targets.dedup();
 
         Ok(targets)
     }
 
     /// Imports a `kotoba` Graph into the database.
-    /// This method is transactional.
+    ///
+    /// The import is applied as a single sled transaction across all trees it
+    /// touches, so after an error or a crash the database holds either the
+    /// whole graph or none of it.
     pub fn import_graph(&self, graph: &Graph) -> Result<()> {
-        let mut node_id_map = HashMap::new();
-        for node in &graph.node {
-            let vertex_id = self.add_vertex(node)?;
-            node_id_map.insert(node.id.clone(), vertex_id);
-        }
+        let import = self.stage_import(graph)?;
+        self.write_graph(|trees| import.apply(trees))
+    }
 
-        let mut edge_sources: HashMap<&str, &str> = HashMap::new();
-        let mut edge_targets: HashMap<&str, &str> = HashMap::new();
+    /// Works out the writes of `import_graph` without making them.
+    pub(crate) fn stage_import(&self, graph: &Graph) -> Result<StagedImport> {
+        let record = self.allocate_tx()?;
+        let valid_from = temporal::now();
 
-        for i in &graph.incidence {
-            if i.role == "source" {
-                edge_sources.insert(&i.edge, &i.node);
-            } else if i.role == "target" {
-                edge_targets.insert(&i.edge, &i.node);
+        // 1. Serialize nodes and work out the facts to record for them
+        let mut nodes = Vec::with_capacity(graph.node.len());
+        let mut datoms = Vec::new();
+        for node in &graph.node {
+            let data = serde_ipld_dagcbor::to_vec(node).map_err(|e| Error::Serialization(e.to_string()))?;
+            let cid = self.calculate_cid(&data)?;
+            for (attribute, value, added) in self.attribute_changes(node, valid_from)? {
+                let datom = Datom { entity: node.id.clone(), attribute, value, tx: record.tx, valid_from, added };
+                let datom_data = serde_ipld_dagcbor::to_vec(&datom).map_err(|e| Error::Serialization(e.to_string()))?;
+                datoms.push((temporal::datom_key(&datom), datom_data));
             }
+            let vertex = self.entity_record(&node.id)?.map(|r| r.vertex);
+            nodes.push((node.id.clone(), vertex, cid, data));
         }
-        
-        for edge in &graph.edge {
-            if let (Some(source_node_id), Some(target_node_id)) = (edge_sources.get(edge.id.as_str()), edge_targets.get(edge.id.as_str())) {
-                if let (Some(source_vertex_id), Some(target_vertex_id)) = (node_id_map.get(*source_node_id), node_id_map.get(*target_node_id)) {
-                    self.add_edge(*source_vertex_id, &edge.kind, *target_vertex_id)?;
+
+        // 2. Reserve vertex ids for entities seen for the first time
+        let new_entities = nodes.iter().filter(|(_, vertex, _, _)| vertex.is_none()).count() as u64;
+        let mut next_id = if new_entities > 0 { self.allocate_vertex_ids(new_entities)? } else { 0 };
+        let nodes = nodes.into_iter().map(|(id, vertex, cid, data)| {
+            let vertex = vertex.unwrap_or_else(|| {
+                next_id += 1;
+                next_id - 1
+            });
+            (id, vertex, cid, data)
+        }).collect();
+
+        // 3. Stage edge and incidence records, and resolve the adjacency they imply
+        let edges = self.stage_graph_edges(graph)?;
+        let endpoints = edge_endpoints(&graph.edge, &graph.incidence);
+        let stored = self.stored_endpoints(graph, &endpoints)?
+            .into_iter()
+            .map(|(id, vertex)| (id.to_string(), vertex))
+            .collect();
+        let endpoints = endpoints.into_iter()
+            .map(|(edge, source, target)| (edge.clone(), source.to_string(), target.to_string()))
+            .collect();
+
+        let record_data = serde_ipld_dagcbor::to_vec(&record).map_err(|e| Error::Serialization(e.to_string()))?;
+        Ok(StagedImport { tx: record.tx, record_data, nodes, datoms, edges, endpoints, stored })
+    }
+
+    /// Runs `write` as one sled transaction over every tree a graph write touches.
+    pub(crate) fn write_graph<A>(&self, write: impl Fn(&GraphTrees) -> ConflictableTransactionResult<A, Error>) -> Result<A> {
+        let trees = (
+            &self.tree(IPLD_BLOCKS)?,
+            &self.tree(VERTICES)?,
+            &self.tree(CID_TO_VERTEX)?,
+            &self.tree(ENTITIES)?,
+            &self.tree(EDGES)?,
+            &self.tree(EDGES_IN)?,
+            &self.tree(EDGE_KINDS)?,
+            &self.tree(HYPEREDGES)?,
+            &self.tree(INCIDENCES)?,
+            &self.tree(NODE_INCIDENCES)?,
+            &self.tree(PROPERTY_INDEX)?,
+            &self.tree(NODE_KINDS)?,
+            &self.tree(DATOMS)?,
+            &self.tree(TRANSACTIONS)?,
+        );
+        run_transaction(trees, |(blocks, vertices, cid_to_vertex, entities, edges, edges_in, edge_kinds, hyperedges, incidences, node_incidences, indexes, kinds, datoms, transactions)| {
+            write(&GraphTrees {
+                blocks, vertices, cid_to_vertex, entities, edges, edges_in, edge_kinds,
+                hyperedges, incidences, node_incidences, indexes, kinds, datoms, transactions,
+            })
+        })
+    }
+
+    /// Vertex ids of the stored nodes that edges of `graph` lead to but `graph` does not hold.
+    pub(crate) fn stored_endpoints<'g>(&self, graph: &Graph, endpoints: &[(&Edge, &'g str, &'g str)]) -> Result<HashMap<&'g str, u64>> {
+        let in_graph: HashSet<&str> = graph.node.iter().map(|node| node.id.as_str()).collect();
+        let mut stored = HashMap::new();
+        for (_, source, target) in endpoints {
+            for id in [*source, *target] {
+                if !in_graph.contains(id) && !stored.contains_key(id) {
+                    if let Some(vertex) = self.entity_vertex(id)? {
+                        stored.insert(id, vertex);
+                    }
                 }
             }
         }
-
-        Ok(())
+        Ok(stored)
     }
 
     /// Creates a new commit for the current state of the database.
     pub fn commit(&self, branch: &str, author: String, message: String) -> Result<Cid> {
-        let branches_tree = self.db.open_tree(BRANCHES)?;
+        let branches_tree = self.tree(BRANCHES)?;
         let parent_cid_bytes = branches_tree.get(branch.as_bytes())?;
         let parents = if let Some(bytes) = parent_cid_bytes {
-            vec![Cid::try_from(bytes.to_vec()).map_err(|e| Error::Serialization(e.to_string()))?]
+            vec![cid_from_bytes(&bytes)?]
         } else {
             vec![]
         };
 
+        let root = self.put_dag(&self.snapshot()?)?;
+        self.write_commit(branch, root, parents, author, message)
+    }
+
+    /// Commits an explicit graph to a branch with the given parents.
+    ///
+    /// Unlike `commit`, the recorded snapshot is built from `graph` rather than
+    /// from the current database state, which is what merges need.
+    pub fn commit_graph(&self, branch: &str, graph: &Graph, parents: Vec<Cid>, author: String, message: String) -> Result<Cid> {
+        let root = self.put_dag(&self.snapshot_of(graph)?)?;
+        self.write_commit(branch, root, parents, author, message)
+    }
+
+    fn write_commit(&self, branch: &str, root: Cid, parents: Vec<Cid>, author: String, message: String) -> Result<Cid> {
         // 1. Create and store the transaction object
         let transaction = Transaction {
             timestamp: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(),
+            root: Some(root),
         };
-        let tx_data = serde_ipld_dagcbor::to_vec(&transaction).map_err(|e| Error::Serialization(e.to_string()))?;
-        let tx_cid = self.calculate_cid(&tx_data)?;
-        self.put_block(&tx_cid, &tx_data)?;
+        let tx_cid = self.put_dag(&transaction)?;
 
         // 2. Create and store the commit object
         let commit = Commit {
@@ -172,73 +342,269 @@ impl EngiDB {
             author,
             message,
         };
-        let commit_data = serde_ipld_dagcbor::to_vec(&commit).map_err(|e| Error::Serialization(e.to_string()))?;
-        let commit_cid = self.calculate_cid(&commit_data)?;
-        self.put_block(&commit_cid, &commit_data)?;
+        let commit_cid = self.put_dag(&commit)?;
+        self.tree(COMMITS)?.insert(commit_cid.to_bytes(), root.to_bytes())?;
 
         // 3. Update the branch to point to the new commit
-        branches_tree.insert(branch.as_bytes(), commit_cid.to_bytes())?;
+        self.tree(BRANCHES)?.insert(branch.as_bytes(), commit_cid.to_bytes())?;
 
         Ok(commit_cid)
     }
 
+    /// Builds the snapshot of a standalone graph, storing its node, edge and incidence blocks.
+    ///
+    /// Adjacency is taken from `source`/`target` incidences, as in `import_graph`.
+    pub fn snapshot_of(&self, graph: &Graph) -> Result<Snapshot> {
+        let mut node_cids = HashMap::new();
+        for node in &graph.node {
+            node_cids.insert(node.id.as_str(), self.put_dag(node)?);
+        }
+
+        let mut edges = BTreeSet::new();
+        for (edge, source, target) in edge_endpoints(&graph.edge, &graph.incidence) {
+            if let (Some(source), Some(target)) = (node_cids.get(source), node_cids.get(target)) {
+                edges.insert(SnapshotEdge { source: *source, kind: edge.kind.clone(), target: *target });
+            }
+        }
+
+        let mut hyperedges = BTreeSet::new();
+        for edge in &graph.edge {
+            hyperedges.insert(self.put_dag(edge)?);
+        }
+        let mut incidences = BTreeSet::new();
+        for incidence in &graph.incidence {
+            incidences.insert(self.put_dag(incidence)?);
+        }
+
+        let vertices: BTreeSet<Cid> = node_cids.into_values().collect();
+        Ok(Snapshot {
+            vertices: vertices.into_iter().collect(),
+            edges: edges.into_iter().collect(),
+            hyperedges: hyperedges.into_iter().collect(),
+            incidences: incidences.into_iter().collect(),
+        })
+    }
+
+    /// Builds the content-addressed snapshot of the current graph state.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let vertices_tree = self.tree(VERTICES)?;
+        let mut vertex_cids = HashMap::new();
+        for result in vertices_tree.iter() {
+            let (id_bytes, cid_bytes) = result?;
+            vertex_cids.insert(id_bytes.to_vec(), cid_from_bytes(&cid_bytes)?);
+        }
+
+        let mut edges = BTreeSet::new();
+        for result in self.tree(EDGES)?.iter() {
+            let (key, value) = result?;
+            let entry = Adjacency::from_outgoing(&key, &value)?;
+            // Edges added through the raw id API may point at unknown vertices.
+            if let (Some(source), Some(target)) = (
+                vertex_cids.get(entry.source.to_be_bytes().as_slice()),
+                vertex_cids.get(entry.target.to_be_bytes().as_slice()),
+            ) {
+                edges.insert(SnapshotEdge { source: *source, kind: entry.kind, target: *target });
+            }
+        }
+
+        let vertices: BTreeSet<Cid> = vertex_cids.into_values().collect();
+        let (hyperedges, incidences) = self.edge_record_cids()?;
+        Ok(Snapshot {
+            vertices: vertices.into_iter().collect(),
+            edges: edges.into_iter().collect(),
+            hyperedges,
+            incidences,
+        })
+    }
+
+    /// Serializes a value as DAG-CBOR and stores it as an IPLD block.
+    pub fn put_dag<T: Serialize>(&self, value: &T) -> Result<Cid> {
+        let data = serde_ipld_dagcbor::to_vec(value).map_err(|e| Error::Serialization(e.to_string()))?;
+        let cid = self.calculate_cid(&data)?;
+        self.put_block(&cid, &data)?;
+        Ok(cid)
+    }
+
+    /// Loads and decodes a DAG-CBOR block.
+    pub fn get_dag<T: DeserializeOwned>(&self, cid: &Cid) -> Result<Option<T>> {
+        match self.get_block(cid)? {
+            Some(data) => Ok(Some(
+                serde_ipld_dagcbor::from_slice(&data).map_err(|e| Error::Serialization(e.to_string()))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
     // Helper function to calculate CID for any serializable data
     fn calculate_cid(&self, data: &[u8]) -> Result<Cid> {
-        const SHA2_256_CODE: u64 = 0x12; // SHA-256 multihash code
-        let hash = Sha256::digest(data);
-        let multihash = Multihash::<64>::wrap(SHA2_256_CODE, &hash).unwrap();
-        Ok(Cid::new_v1(0x71, multihash))
+        Ok(calculate_cid(data))
     }
 
     /// Adds a vertex to the graph from a `kotoba` Node.
+    ///
+    /// Nodes are identified by `Node::id`: adding a node whose id is already
+    /// known updates that entity in place and returns its existing vertex id.
     pub fn add_vertex(&self, node: &Node) -> Result<u64> {
         // 1. Serialize node and calculate CID
         let data = serde_ipld_dagcbor::to_vec(node).map_err(|e| Error::Serialization(e.to_string()))?;
         let cid = self.calculate_cid(&data)?;
 
-        // 2. Check if vertex already exists
-        let cid_to_vertex_tree = self.db.open_tree(CID_TO_VERTEX)?;
-        if let Some(existing_id_bytes) = cid_to_vertex_tree.get(cid.to_bytes())? {
-            let existing_id = u64::from_be_bytes(existing_id_bytes.as_ref().try_into().unwrap());
-            return Ok(existing_id);
+        // 2. Record the node's attributes in the bitemporal fact log
+        self.record_node(node, None)?;
+
+        // 3. Store the block and point the entity at it
+        self.put_block(&cid, &data)?;
+        self.put_entity(&node.id, cid)
+    }
+
+    /// Store a TodoItem node
+    pub fn store_todo_item(&self, node: &kotoba_types::Node) -> Result<u64> {
+        self.add_vertex(node)
+    }
+}
+
+/// CIDv1 (DAG-CBOR, SHA-256) of an encoded block.
+fn calculate_cid(data: &[u8]) -> Cid {
+    const SHA2_256_CODE: u64 = 0x12; // SHA-256 multihash code
+    let hash = Sha256::digest(data);
+    let multihash = Multihash::<64>::wrap(SHA2_256_CODE, &hash).unwrap();
+    Cid::new_v1(0x71, multihash)
+}
+
+/// `(edge, source node, target node)` for every pairing of a `source` and a `target` incidence of an edge.
+pub(crate) fn edge_endpoints<'g>(edges: &'g [Edge], incidences: &'g [Incidence]) -> Vec<(&'g Edge, &'g str, &'g str)> {
+    let mut edge_sources: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut edge_targets: HashMap<&str, Vec<&str>> = HashMap::new();
+    for i in incidences {
+        if i.role == "source" {
+            edge_sources.entry(&i.edge).or_default().push(&i.node);
+        } else if i.role == "target" {
+            edge_targets.entry(&i.edge).or_default().push(&i.node);
         }
+    }
+    let mut endpoints = Vec::new();
+    for edge in edges {
+        let (Some(sources), Some(targets)) = (edge_sources.get(edge.id.as_str()), edge_targets.get(edge.id.as_str())) else {
+            continue;
+        };
+        for source in sources {
+            for target in targets {
+                endpoints.push((edge, *source, *target));
+            }
+        }
+    }
+    endpoints
+}
 
-        // 3. If not, create it
-        let vertices_tree = self.db.open_tree(VERTICES)?;
+/// Views, within one sled transaction, of every tree a graph write touches.
+pub(crate) struct GraphTrees<'t> {
+    pub blocks: &'t TransactionalTree,
+    pub vertices: &'t TransactionalTree,
+    pub cid_to_vertex: &'t TransactionalTree,
+    pub entities: &'t TransactionalTree,
+    pub edges: &'t TransactionalTree,
+    pub edges_in: &'t TransactionalTree,
+    pub edge_kinds: &'t TransactionalTree,
+    pub hyperedges: &'t TransactionalTree,
+    pub incidences: &'t TransactionalTree,
+    pub node_incidences: &'t TransactionalTree,
+    pub indexes: &'t TransactionalTree,
+    pub kinds: &'t TransactionalTree,
+    pub datoms: &'t TransactionalTree,
+    pub transactions: &'t TransactionalTree,
+}
 
-        // Get next vertex ID (simple counter)
-        let next_id = (vertices_tree.len() + 1) as u64;
+impl<'t> GraphTrees<'t> {
+    pub fn entity_trees(&self) -> EntityTrees<'t> {
+        EntityTrees {
+            vertices: self.vertices,
+            cid_to_vertex: self.cid_to_vertex,
+            entities: self.entities,
+            blocks: self.blocks,
+            indexes: self.indexes,
+            kinds: self.kinds,
+        }
+    }
 
-        // Store the data
-        self.put_block(&cid, &data)?;
-        vertices_tree.insert(&next_id.to_be_bytes(), cid.to_bytes())?;
-        cid_to_vertex_tree.insert(cid.to_bytes(), &next_id.to_be_bytes())?;
+    pub fn adjacency(&self) -> AdjacencyTrees<'t> {
+        AdjacencyTrees { edges: self.edges, edges_in: self.edges_in, edge_kinds: self.edge_kinds }
+    }
 
-        Ok(next_id)
+    /// Applies staged edge and incidence writes.
+    pub fn apply_edges(&self, writes: &EdgeWrites) -> ConflictableTransactionResult<(), Error> {
+        writes.apply(self.blocks, self.hyperedges, self.incidences, self.node_incidences, &self.adjacency())
     }
+}
 
-    /// Scan all TodoItem nodes from the database
-    pub fn scan_todo_items(&self) -> Result<Vec<kotoba_types::Node>> {
-        let vertices_tree = self.db.open_tree(VERTICES)?;
-        let mut todos = Vec::new();
+/// Writes of an `import_graph`, staged before its transaction.
+pub(crate) struct StagedImport {
+    tx: u64,
+    record_data: Vec<u8>,
+    /// Node ids with the vertex reserved for them, their CID and block.
+    nodes: Vec<(String, u64, Cid, Vec<u8>)>,
+    datoms: Vec<(Vec<u8>, Vec<u8>)>,
+    edges: EdgeWrites,
+    /// Edges with the ids of their source and target nodes.
+    endpoints: Vec<(Edge, String, String)>,
+    /// Vertex ids of endpoints stored before the import.
+    stored: HashMap<String, u64>,
+}
 
-        for result in vertices_tree.iter() {
-            let (_id_bytes, cid_bytes) = result?;
-            let cid = cid::Cid::try_from(cid_bytes.to_vec())
-                .map_err(|e| Error::Serialization(e.to_string()))?;
-            if let Some(block) = self.get_block(&cid)? {
-                let node: kotoba_types::Node =
-                    serde_ipld_dagcbor::from_slice(&block).map_err(|e| Error::Serialization(e.to_string()))?;
-                if node.kind == "TodoItem" {
-                    todos.push(node);
-                }
+impl StagedImport {
+    pub fn apply(&self, trees: &GraphTrees) -> ConflictableTransactionResult<(), Error> {
+        let entity_trees = trees.entity_trees();
+        let adjacency = trees.adjacency();
+        trees.apply_edges(&self.edges)?;
+        let mut node_id_map = HashMap::new();
+        for (id, vertex, cid, data) in &self.nodes {
+            trees.blocks.insert(cid.to_bytes(), data.as_slice())?;
+            node_id_map.insert(id.as_str(), entity_trees.set(id, *vertex, Some(*cid))?);
+        }
+        // Edges may also lead to nodes stored before.
+        let vertex = |id: &str| node_id_map.get(id).or_else(|| self.stored.get(id)).copied();
+        for (edge, source, target) in &self.endpoints {
+            if let (Some(source_vertex_id), Some(target_vertex_id)) = (vertex(source), vertex(target)) {
+                adjacency.insert(&Adjacency {
+                    source: source_vertex_id,
+                    kind: edge.kind.clone(),
+                    target: target_vertex_id,
+                    edge: Some(edge.id.clone()),
+                    layer: Some(edge.layer),
+                })?;
             }
         }
-        Ok(todos)
+        if !self.datoms.is_empty() {
+            for (key, data) in &self.datoms {
+                trees.datoms.insert(key.as_slice(), data.as_slice())?;
+            }
+            trees.transactions.insert(&self.tx.to_be_bytes(), self.record_data.as_slice())?;
+        }
+        Ok(())
     }
+}
 
-    /// Store a TodoItem node
-    pub fn store_todo_item(&self, node: &kotoba_types::Node) -> Result<u64> {
-        self.add_vertex(node)
-    }
+/// Runs a multi-tree sled transaction, surfacing aborts as the EngiDB error they carry.
+fn run_transaction<T, A>(
+    trees: T,
+    f: impl Fn(&T::View) -> sled::transaction::ConflictableTransactionResult<A, Error>,
+) -> Result<A>
+where
+    T: sled::Transactional<Error>,
+{
+    trees.transaction(f).map_err(|e| match e {
+        sled::transaction::TransactionError::Abort(e) => e,
+        sled::transaction::TransactionError::Storage(e) => Error::Sled(e),
+    })
+}
+
+/// Decodes a vertex id stored as big-endian bytes.
+fn vertex_id_from_bytes(bytes: &[u8]) -> Result<u64> {
+    let bytes: [u8; 8] = bytes.try_into()
+        .map_err(|_| Error::Serialization("Invalid vertex ID bytes".to_string()))?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+/// Decodes a CID stored as raw bytes in one of the sled trees.
+fn cid_from_bytes(bytes: &[u8]) -> Result<Cid> {
+    Cid::try_from(bytes).map_err(|e| Error::Serialization(e.to_string()))
 }
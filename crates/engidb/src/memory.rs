@@ -0,0 +1,681 @@
+//! In-memory `GraphAdapter`.
+//!
+//! `InMemoryAdapter` keeps EngiDB's model in plain collections: entities
+//! addressed by `Node::id` with vertex ids that are never reused, edge and
+//! incidence records under the same keys as the `hyperedges` and
+//! `incidences` trees, the adjacency derived from them, a block store and
+//! branches. Writes follow the rules of the corresponding `EngiDB` methods,
+//! so the same operations commit to the same snapshot CID on either backend.
+//! Nothing is persisted and no fact log is kept.
+
+use crate::adapter::GraphAdapter;
+use crate::adjacency::{Adjacency, Direction};
+use crate::history::read_dag;
+use crate::hyperedge::{incidence_key, incidence_prefix};
+use crate::scan::{NodeFilter, NodePage};
+use crate::temporal::{now, KIND_ATTRIBUTE};
+use crate::transact::TxOp;
+use crate::{calculate_cid, edge_endpoints, Commit, Error, Result, Snapshot, SnapshotEdge, Transaction};
+use cid::Cid;
+use indexmap::IndexMap;
+use kotoba_types::{Edge, Graph, Incidence, Layer, Node};
+#[cfg(feature = "fcdb")]
+use serde::{de::DeserializeOwned, Deserialize};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Bound;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// `(source, kind, target, edge)`, the identity of an adjacency entry.
+type AdjacencyKey = (u64, String, u64, Option<String>);
+
+/// Stored content with its CID; `None` when removed.
+type Stored<T> = Option<(Cid, T)>;
+
+/// An entity and, while it is live, its current content.
+#[derive(Clone)]
+struct Entity {
+    vertex: u64,
+    current: Stored<Node>,
+}
+
+#[derive(Default)]
+struct State {
+    entities: BTreeMap<String, Entity>,
+    /// Live vertices and the CID of their content.
+    vertices: BTreeMap<u64, Cid>,
+    next_vertex: u64,
+    /// Adjacency entries and their layer.
+    outgoing: BTreeMap<AdjacencyKey, Option<Layer>>,
+    /// `(target, kind, source, edge)` for every outgoing entry.
+    incoming: BTreeSet<AdjacencyKey>,
+    edges: BTreeMap<String, (Cid, Edge)>,
+    incidences: BTreeMap<Vec<u8>, (Cid, Incidence)>,
+    branches: BTreeMap<String, Cid>,
+    last_tx: u64,
+}
+
+/// The state of an `InMemoryAdapter` without its blocks, with nodes, edge
+/// records and incidences given by the CIDs of the blocks holding them.
+#[cfg(feature = "fcdb")]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Image {
+    /// Entities with their vertex id and the CID of their current content.
+    entities: BTreeMap<String, (u64, Option<Cid>)>,
+    next_vertex: u64,
+    /// Every adjacency entry with its layer.
+    adjacency: Vec<(AdjacencyKey, Option<Layer>)>,
+    edges: Vec<Cid>,
+    incidences: Vec<Cid>,
+    branches: BTreeMap<String, Cid>,
+    last_tx: u64,
+}
+
+/// Changes staged by a write, applied only once the whole write is valid.
+#[derive(Default)]
+struct Writes {
+    blocks: Vec<(Cid, Vec<u8>)>,
+    entities: Vec<(String, u64, Stored<Node>)>,
+    edges: Vec<(String, Stored<Edge>)>,
+    incidences: Vec<(Vec<u8>, Stored<Incidence>)>,
+    adjacency: Vec<(Adjacency, bool)>,
+}
+
+impl Writes {
+    fn put_block<T: Serialize>(&mut self, value: &T) -> Result<Cid> {
+        let data = serde_ipld_dagcbor::to_vec(value).map_err(|e| Error::Serialization(e.to_string()))?;
+        let cid = calculate_cid(&data);
+        self.blocks.push((cid, data));
+        Ok(cid)
+    }
+
+    fn put_edge(&mut self, edge: &Edge) -> Result<()> {
+        let cid = self.put_block(edge)?;
+        self.edges.push((edge.id.clone(), Some((cid, edge.clone()))));
+        Ok(())
+    }
+
+    fn put_incidence(&mut self, incidence: &Incidence) -> Result<()> {
+        let cid = self.put_block(incidence)?;
+        self.incidences.push((incidence_key(incidence), Some((cid, incidence.clone()))));
+        Ok(())
+    }
+}
+
+/// Working copy of an entity touched by a transaction.
+struct EntityState {
+    vertex_id: Option<u64>,
+    old_cid: Option<Cid>,
+    node: Option<Node>,
+}
+
+impl State {
+    fn entity_vertex(&self, entity: &str) -> Option<u64> {
+        self.entities.get(entity).filter(|e| e.current.is_some()).map(|e| e.vertex)
+    }
+
+    /// Reserves `count` consecutive vertex ids and returns the first one.
+    fn allocate_vertex_ids(&mut self, count: u64) -> u64 {
+        let first = self.next_vertex.max(1);
+        self.next_vertex = first + count;
+        first
+    }
+
+    fn incidences_of<'s>(&'s self, edge: &str) -> impl Iterator<Item = (&'s Vec<u8>, &'s Incidence)> + 's {
+        let prefix = incidence_prefix(edge).into_bytes();
+        self.incidences
+            .range(prefix.clone()..)
+            .take_while(move |(key, _)| key.starts_with(&prefix))
+            .map(|(key, (_, incidence))| (key, incidence))
+    }
+
+    fn edges_of(&self, vertex_id: u64, direction: Direction) -> Vec<Adjacency> {
+        let start = (vertex_id, String::new(), 0, None);
+        let mut entries = Vec::new();
+        if direction != Direction::Incoming {
+            for ((source, kind, target, edge), layer) in self.outgoing.range(start.clone()..) {
+                if *source != vertex_id {
+                    break;
+                }
+                entries.push(Adjacency { source: *source, kind: kind.clone(), target: *target, edge: edge.clone(), layer: *layer });
+            }
+        }
+        if direction != Direction::Outgoing {
+            for (target, kind, source, edge) in self.incoming.range(start..) {
+                if *target != vertex_id {
+                    break;
+                }
+                let key = (*source, kind.clone(), *target, edge.clone());
+                let layer = self.outgoing.get(&key).copied().flatten();
+                entries.push(Adjacency { source: *source, kind: kind.clone(), target: *target, edge: edge.clone(), layer });
+            }
+        }
+        // Self-loops are found in both directions.
+        entries.sort();
+        entries.dedup();
+        entries
+    }
+
+    /// Ids of the stored edges with an incidence on `node`.
+    fn edges_touching(&self, node: &str) -> Vec<String> {
+        let mut edges: Vec<String> = Vec::new();
+        for incidence in self.incidences.values().map(|(_, incidence)| incidence) {
+            if incidence.node == node && edges.last() != Some(&incidence.edge) {
+                edges.push(incidence.edge.clone());
+            }
+        }
+        edges
+    }
+
+    /// Ids of the stored edges of `kind` leading from `source` to `target`.
+    fn edges_between(&self, source: &str, kind: &str, target: &str) -> Vec<String> {
+        self.edges_touching(source)
+            .into_iter()
+            .filter(|id| self.edges.get(id).is_some_and(|(_, edge)| edge.kind == kind))
+            .filter(|id| {
+                let has = |role: &str, node: &str| self.incidences_of(id).any(|(_, i)| i.role == role && i.node == node);
+                has("source", source) && has("target", target)
+            })
+            .collect()
+    }
+
+    /// Stages the removal of the adjacency entries a stored edge contributes.
+    fn stage_adjacency_removal(&self, id: &str, writes: &mut Writes) {
+        let Some((_, edge)) = self.edges.get(id) else { return };
+        let incidences: Vec<Incidence> = self.incidences_of(id).map(|(_, i)| i.clone()).collect();
+        for (edge, source, target) in edge_endpoints(std::slice::from_ref(edge), &incidences) {
+            if let (Some(source), Some(target)) = (self.entity_vertex(source), self.entity_vertex(target)) {
+                writes.adjacency.push((
+                    Adjacency { source, kind: edge.kind.clone(), target, edge: Some(edge.id.clone()), layer: Some(edge.layer) },
+                    false,
+                ));
+            }
+        }
+    }
+
+    /// Stages the removal of an edge and all its incidences.
+    fn stage_edge_removal(&self, id: &str, writes: &mut Writes) {
+        self.stage_adjacency_removal(id, writes);
+        writes.edges.push((id.to_string(), None));
+        for (key, _) in self.incidences_of(id) {
+            writes.incidences.push((key.clone(), None));
+        }
+    }
+
+    /// Stages the edges and incidences of `graph` as `EngiDB::stage_graph_edges`.
+    fn stage_graph_edges(&self, graph: &Graph, writes: &mut Writes) -> Result<()> {
+        let mut new_keys: HashMap<&str, HashSet<Vec<u8>>> = HashMap::new();
+        for incidence in &graph.incidence {
+            new_keys.entry(incidence.edge.as_str()).or_default().insert(incidence_key(incidence));
+            writes.put_incidence(incidence)?;
+        }
+        for edge in &graph.edge {
+            self.stage_adjacency_removal(&edge.id, writes);
+            writes.put_edge(edge)?;
+            let keep = new_keys.get(edge.id.as_str());
+            for (key, _) in self.incidences_of(&edge.id) {
+                if !keep.is_some_and(|keep| keep.contains(key)) {
+                    writes.incidences.push((key.clone(), None));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_adjacency(&mut self, entry: Adjacency) {
+        self.incoming.insert((entry.target, entry.kind.clone(), entry.source, entry.edge.clone()));
+        self.outgoing.insert((entry.source, entry.kind, entry.target, entry.edge), entry.layer);
+    }
+
+    fn remove_adjacency(&mut self, entry: Adjacency) {
+        self.incoming.remove(&(entry.target, entry.kind.clone(), entry.source, entry.edge.clone()));
+        self.outgoing.remove(&(entry.source, entry.kind, entry.target, entry.edge));
+    }
+
+    /// Points an entity at new content, or deletes it when `current` is `None`.
+    fn set_entity(&mut self, id: String, vertex: u64, current: Stored<Node>) -> u64 {
+        let entity = self.entities.entry(id).or_insert(Entity { vertex, current: None });
+        match &current {
+            Some((cid, _)) => self.vertices.insert(entity.vertex, *cid),
+            None => self.vertices.remove(&entity.vertex),
+        };
+        entity.current = current;
+        entity.vertex
+    }
+
+    /// Applies staged changes in the order EngiDB's transactions write them.
+    fn apply(&mut self, writes: Writes, blocks: &mut HashMap<Cid, Vec<u8>>) {
+        blocks.extend(writes.blocks);
+        for (id, vertex, current) in writes.entities {
+            self.set_entity(id, vertex, current);
+        }
+        for (id, edge) in writes.edges {
+            match edge {
+                Some(edge) => self.edges.insert(id, edge),
+                None => self.edges.remove(&id),
+            };
+        }
+        for (key, incidence) in writes.incidences {
+            match incidence {
+                Some(incidence) => self.incidences.insert(key, incidence),
+                None => self.incidences.remove(&key),
+            };
+        }
+        for (entry, insert) in writes.adjacency {
+            if insert {
+                self.insert_adjacency(entry);
+            } else {
+                self.remove_adjacency(entry);
+            }
+        }
+    }
+
+    /// Builds the snapshot of the current state as `EngiDB::snapshot`.
+    fn snapshot(&self) -> Snapshot {
+        let mut edges = BTreeSet::new();
+        for (source, kind, target, _) in self.outgoing.keys() {
+            if let (Some(source), Some(target)) = (self.vertices.get(source), self.vertices.get(target)) {
+                edges.insert(SnapshotEdge { source: *source, kind: kind.clone(), target: *target });
+            }
+        }
+        let sorted = |cids: Vec<Cid>| cids.into_iter().collect::<BTreeSet<_>>().into_iter().collect();
+        Snapshot {
+            vertices: sorted(self.vertices.values().copied().collect()),
+            edges: edges.into_iter().collect(),
+            hyperedges: sorted(self.edges.values().map(|(cid, _)| *cid).collect()),
+            incidences: sorted(self.incidences.values().map(|(cid, _)| *cid).collect()),
+        }
+    }
+}
+
+/// `GraphAdapter` holding everything in memory.
+///
+/// Clones share the same data. Every write holds an exclusive lock for its
+/// whole duration, so writes are atomic and serialised.
+#[derive(Clone, Default)]
+pub struct InMemoryAdapter {
+    state: Arc<RwLock<State>>,
+    blocks: Arc<RwLock<HashMap<Cid, Vec<u8>>>>,
+}
+
+impl InMemoryAdapter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, State> {
+        self.state.read().unwrap()
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, State> {
+        self.state.write().unwrap()
+    }
+
+    fn put_dag<T: Serialize>(&self, value: &T) -> Result<Cid> {
+        let data = serde_ipld_dagcbor::to_vec(value).map_err(|e| Error::Serialization(e.to_string()))?;
+        let cid = calculate_cid(&data);
+        self.blocks.write().unwrap().insert(cid, data);
+        Ok(cid)
+    }
+
+    /// Checks that a revision resolves to a stored commit.
+    fn resolve_commit(&self, rev: &str) -> Result<Cid> {
+        let head = self.resolve(rev)?;
+        read_dag::<Commit, _>(&|cid: &Cid| self.get_block(cid), &head)?
+            .ok_or_else(|| Error::NotFound(format!("commit {}", head)))?;
+        Ok(head)
+    }
+
+    /// Commits the current state on `branch` with the given timestamp.
+    pub(crate) fn commit_at(&self, branch: &str, author: String, message: String, timestamp: u64) -> Result<Cid> {
+        let mut state = self.write();
+        let parents = state.branches.get(branch).copied().into_iter().collect();
+        let root = self.put_dag(&state.snapshot())?;
+        let transaction = Transaction { timestamp, root: Some(root) };
+        let transaction_cid = self.put_dag(&transaction)?;
+        let commit_cid = self.put_dag(&Commit { transaction_cid, parents, author, message })?;
+        state.branches.insert(branch.to_string(), commit_cid);
+        Ok(commit_cid)
+    }
+
+    /// Loads the current state of an entity into a transaction's working set.
+    fn load_entity<'s>(state: &State, states: &'s mut IndexMap<String, EntityState>, entity: &str) -> &'s mut EntityState {
+        states.entry(entity.to_string()).or_insert_with(|| match state.entities.get(entity) {
+            Some(record) => EntityState {
+                vertex_id: Some(record.vertex),
+                old_cid: record.current.as_ref().map(|(cid, _)| *cid),
+                node: record.current.as_ref().map(|(_, node)| node.clone()),
+            },
+            None => EntityState { vertex_id: None, old_cid: None, node: None },
+        })
+    }
+}
+
+#[cfg(feature = "fcdb")]
+impl InMemoryAdapter {
+    /// Captures the current state, apart from the blocks.
+    pub(crate) fn image(&self) -> Image {
+        let state = self.read();
+        let sorted = |cids: Vec<Cid>| cids.into_iter().collect::<BTreeSet<_>>().into_iter().collect();
+        Image {
+            entities: state.entities.iter()
+                .map(|(id, entity)| (id.clone(), (entity.vertex, entity.current.as_ref().map(|(cid, _)| *cid))))
+                .collect(),
+            next_vertex: state.next_vertex,
+            adjacency: state.outgoing.iter().map(|(key, layer)| (key.clone(), *layer)).collect(),
+            edges: sorted(state.edges.values().map(|(cid, _)| *cid).collect()),
+            incidences: sorted(state.incidences.values().map(|(cid, _)| *cid).collect()),
+            branches: state.branches.clone(),
+            last_tx: state.last_tx,
+        }
+    }
+
+    /// CIDs of every stored block, in order.
+    pub(crate) fn block_cids(&self) -> Vec<Cid> {
+        let mut cids: Vec<Cid> = self.blocks.read().unwrap().keys().copied().collect();
+        cids.sort();
+        cids
+    }
+
+    /// Rebuilds an adapter from an image of its state and its blocks, which
+    /// must hold the content the image refers to.
+    pub(crate) fn from_image(image: Image, blocks: HashMap<Cid, Vec<u8>>) -> Result<Self> {
+        fn decode<T: DeserializeOwned>(blocks: &HashMap<Cid, Vec<u8>>, cid: &Cid) -> Result<T> {
+            let data = blocks.get(cid).ok_or_else(|| Error::NotFound(format!("block {}", cid)))?;
+            serde_ipld_dagcbor::from_slice(data).map_err(|e| Error::Serialization(e.to_string()))
+        }
+
+        let mut state = State { next_vertex: image.next_vertex, branches: image.branches, last_tx: image.last_tx, ..State::default() };
+        for (id, (vertex, cid)) in image.entities {
+            let current = match cid {
+                Some(cid) => Some((cid, decode(&blocks, &cid)?)),
+                None => None,
+            };
+            state.set_entity(id, vertex, current);
+        }
+        for ((source, kind, target, edge), layer) in image.adjacency {
+            state.insert_adjacency(Adjacency { source, kind, target, edge, layer });
+        }
+        for cid in image.edges {
+            let edge: Edge = decode(&blocks, &cid)?;
+            state.edges.insert(edge.id.clone(), (cid, edge));
+        }
+        for cid in image.incidences {
+            let incidence: Incidence = decode(&blocks, &cid)?;
+            state.incidences.insert(incidence_key(&incidence), (cid, incidence));
+        }
+        Ok(InMemoryAdapter { state: Arc::new(RwLock::new(state)), blocks: Arc::new(RwLock::new(blocks)) })
+    }
+}
+
+impl GraphAdapter for InMemoryAdapter {
+    fn add_vertex(&self, node: &Node) -> Result<u64> {
+        let data = serde_ipld_dagcbor::to_vec(node).map_err(|e| Error::Serialization(e.to_string()))?;
+        let cid = calculate_cid(&data);
+        let mut state = self.write();
+        let vertex = match state.entities.get(&node.id) {
+            Some(entity) => entity.vertex,
+            None => state.allocate_vertex_ids(1),
+        };
+        self.blocks.write().unwrap().insert(cid, data);
+        Ok(state.set_entity(node.id.clone(), vertex, Some((cid, node.clone()))))
+    }
+
+    fn add_edge(&self, source_id: u64, edge_type: &str, target_id: u64) -> Result<()> {
+        let mut state = self.write();
+        let key = (source_id, edge_type.to_string(), target_id, None);
+        if !state.outgoing.contains_key(&key) {
+            state.insert_adjacency(Adjacency { source: source_id, kind: edge_type.to_string(), target: target_id, edge: None, layer: None });
+        }
+        Ok(())
+    }
+
+    fn get_edges_from(&self, source_id: u64, edge_type: &str) -> Result<Vec<u64>> {
+        let state = self.read();
+        let mut targets: Vec<u64> = state.outgoing
+            .range((source_id, edge_type.to_string(), 0, None)..)
+            .take_while(|((source, kind, _, _), _)| *source == source_id && kind == edge_type)
+            .map(|((_, _, target, _), _)| *target)
+            .collect();
+        // Parallel edges between the same vertices each have their own entry.
+        targets.dedup();
+        Ok(targets)
+    }
+
+    fn import_graph(&self, graph: &Graph) -> Result<()> {
+        let mut state = self.write();
+        let mut writes = Writes::default();
+
+        // Edge records are staged against the state before the import, as in EngiDB.
+        let mut nodes = Vec::with_capacity(graph.node.len());
+        for node in &graph.node {
+            let cid = writes.put_block(node)?;
+            nodes.push((node, state.entities.get(&node.id).map(|e| e.vertex), cid));
+        }
+        state.stage_graph_edges(graph, &mut writes)?;
+
+        let new_entities = nodes.iter().filter(|(_, vertex, _)| vertex.is_none()).count() as u64;
+        let mut next_id = if new_entities > 0 { state.allocate_vertex_ids(new_entities) } else { 0 };
+        let mut node_id_map = HashMap::new();
+        for (node, vertex, cid) in nodes {
+            let vertex = vertex.unwrap_or_else(|| {
+                next_id += 1;
+                next_id - 1
+            });
+            // A node listed twice keeps the vertex id of its first occurrence.
+            let vertex = *node_id_map.entry(node.id.as_str()).or_insert(vertex);
+            writes.entities.push((node.id.clone(), vertex, Some((cid, node.clone()))));
+        }
+        // Edges may also lead to nodes stored before.
+        let vertex = |id: &str| node_id_map.get(id).copied().or_else(|| state.entity_vertex(id));
+        for (edge, source, target) in edge_endpoints(&graph.edge, &graph.incidence) {
+            if let (Some(source), Some(target)) = (vertex(source), vertex(target)) {
+                writes.adjacency.push((
+                    Adjacency { source, kind: edge.kind.clone(), target, edge: Some(edge.id.clone()), layer: Some(edge.layer) },
+                    true,
+                ));
+            }
+        }
+
+        state.apply(writes, &mut self.blocks.write().unwrap());
+        Ok(())
+    }
+
+    fn get_node(&self, id: &str) -> Result<Option<Node>> {
+        Ok(self.read().entities.get(id).and_then(|e| e.current.as_ref()).map(|(_, node)| node.clone()))
+    }
+
+    fn vertex_id(&self, id: &str) -> Result<Option<u64>> {
+        Ok(self.read().entity_vertex(id))
+    }
+
+    fn vertex_node(&self, vertex_id: u64) -> Result<Option<Node>> {
+        match self.read().vertices.get(&vertex_id) {
+            Some(cid) => read_dag(&|cid: &Cid| self.get_block(cid), cid),
+            None => Ok(None),
+        }
+    }
+
+    fn scan_nodes(&self, filter: &NodeFilter) -> Result<NodePage> {
+        let state = self.read();
+        let start = filter.after.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+        filter.page(
+            state.entities
+                .range::<str, _>((start, Bound::Unbounded))
+                .filter_map(|(_, entity)| entity.current.as_ref())
+                .map(|(_, node)| Ok(node.clone())),
+        )
+    }
+
+    fn edges_of(&self, vertex_id: u64, direction: Direction) -> Result<Vec<Adjacency>> {
+        Ok(self.read().edges_of(vertex_id, direction))
+    }
+
+    fn transact(&self, ops: &[TxOp]) -> Result<u64> {
+        let mut state = self.write();
+        // Like EngiDB, a failed batch still uses up its transaction id.
+        state.last_tx += 1;
+        let tx = state.last_tx;
+
+        let mut states: IndexMap<String, EntityState> = IndexMap::new();
+        let mut edge_ops = Vec::new();
+        let mut writes = Writes::default();
+        for op in ops {
+            match op {
+                TxOp::Assert { entity, attribute, value } => {
+                    let node = Self::load_entity(&state, &mut states, entity).node.get_or_insert_with(|| Node {
+                        id: entity.clone(),
+                        kind: String::new(),
+                        properties: IndexMap::new(),
+                    });
+                    if attribute == KIND_ATTRIBUTE {
+                        node.kind = value.as_str()
+                            .ok_or_else(|| Error::Transaction(format!("{} of '{}' must be a string", KIND_ATTRIBUTE, entity)))?
+                            .to_string();
+                    } else {
+                        node.properties.insert(attribute.clone(), value.clone());
+                    }
+                }
+                TxOp::Retract { entity, attribute } => {
+                    if attribute == KIND_ATTRIBUTE {
+                        return Err(Error::Transaction(format!("cannot retract {} of '{}'; retract the entity instead", KIND_ATTRIBUTE, entity)));
+                    }
+                    let node = Self::load_entity(&state, &mut states, entity).node.as_mut()
+                        .ok_or_else(|| Error::NotFound(format!("entity '{}'", entity)))?;
+                    node.properties.shift_remove(attribute);
+                }
+                TxOp::RetractEntity { entity } => {
+                    Self::load_entity(&state, &mut states, entity).node.take()
+                        .ok_or_else(|| Error::NotFound(format!("entity '{}'", entity)))?;
+                    for edge in state.edges_touching(entity) {
+                        state.stage_edge_removal(&edge, &mut writes);
+                    }
+                }
+                TxOp::AssertEdge { source, kind, target } => {
+                    let id = format!("{}:{}:{}", source, kind, target);
+                    writes.put_edge(&Edge { id: id.clone(), layer: Layer::Data, kind: kind.clone(), properties: IndexMap::new() })?;
+                    for (role, node) in [("source", source), ("target", target)] {
+                        writes.put_incidence(&Incidence {
+                            node: node.clone(),
+                            edge: id.clone(),
+                            role: role.to_string(),
+                            pos: None,
+                            properties: IndexMap::new(),
+                        })?;
+                    }
+                    edge_ops.push((source.clone(), kind.clone(), target.clone(), true));
+                }
+                TxOp::RetractEdge { source, kind, target } => {
+                    for edge in state.edges_between(source, kind, target) {
+                        state.stage_edge_removal(&edge, &mut writes);
+                    }
+                    edge_ops.push((source.clone(), kind.clone(), target.clone(), false));
+                }
+            }
+        }
+
+        // Vertex ids are reserved before validation, so a failed batch uses them up as in EngiDB.
+        let new_entities = states.values().filter(|s| s.node.is_some() && s.vertex_id.is_none()).count() as u64;
+        let mut next_id = if new_entities > 0 { state.allocate_vertex_ids(new_entities) } else { 0 };
+        let mut vertex_writes = Vec::new();
+        for (entity, entity_state) in states.iter_mut() {
+            match &entity_state.node {
+                Some(node) if node.kind.is_empty() => {
+                    return Err(Error::Transaction(format!("new entity '{}' needs a {} assertion", entity, KIND_ATTRIBUTE)));
+                }
+                Some(node) => {
+                    let vertex_id = *entity_state.vertex_id.get_or_insert_with(|| {
+                        next_id += 1;
+                        next_id - 1
+                    });
+                    let data = serde_ipld_dagcbor::to_vec(node).map_err(|e| Error::Serialization(e.to_string()))?;
+                    let cid = calculate_cid(&data);
+                    if entity_state.old_cid == Some(cid) {
+                        continue;
+                    }
+                    writes.blocks.push((cid, data));
+                    vertex_writes.push((entity.clone(), vertex_id, Some((cid, node.clone()))));
+                }
+                None => {
+                    let (Some(vertex_id), Some(_)) = (entity_state.vertex_id, entity_state.old_cid) else { continue };
+                    vertex_writes.push((entity.clone(), vertex_id, None));
+                    for entry in state.edges_of(vertex_id, Direction::Both) {
+                        writes.adjacency.push((entry, false));
+                    }
+                }
+            }
+        }
+        writes.entities = vertex_writes;
+
+        for (source, kind, target, insert) in edge_ops {
+            let endpoint = |entity: &str| -> Result<u64> {
+                match states.get(entity) {
+                    Some(EntityState { node: Some(_), vertex_id: Some(id), .. }) => Ok(*id),
+                    Some(_) => Err(Error::NotFound(format!("entity '{}'", entity))),
+                    None => state.entity_vertex(entity)
+                        .ok_or_else(|| Error::NotFound(format!("entity '{}'", entity))),
+                }
+            };
+            let (source_vertex, target_vertex) = (endpoint(&source)?, endpoint(&target)?);
+            let mut entry = Adjacency { source: source_vertex, kind, target: target_vertex, edge: None, layer: None };
+            if insert {
+                entry.edge = Some(format!("{}:{}:{}", source, entry.kind, target));
+                entry.layer = Some(Layer::Data);
+                writes.adjacency.push((entry, true));
+            } else {
+                // Adjacency added through `add_edge` has no edge record behind it.
+                writes.adjacency.push((entry, false));
+            }
+        }
+
+        state.apply(writes, &mut self.blocks.write().unwrap());
+        Ok(tx)
+    }
+
+    fn commit(&self, branch: &str, author: String, message: String) -> Result<Cid> {
+        self.commit_at(branch, author, message, now())
+    }
+
+    fn resolve(&self, rev: &str) -> Result<Cid> {
+        if let Some(head) = self.read().branches.get(rev) {
+            return Ok(*head);
+        }
+        Cid::try_from(rev).map_err(|_| Error::NotFound(format!("revision '{}'", rev)))
+    }
+
+    fn list_branches(&self) -> Result<Vec<(String, Cid)>> {
+        Ok(self.read().branches.iter().map(|(name, head)| (name.clone(), *head)).collect())
+    }
+
+    fn create_branch(&self, name: &str, from: &str) -> Result<Cid> {
+        let head = self.resolve_commit(from)?;
+        let mut state = self.write();
+        if state.branches.contains_key(name) {
+            return Err(Error::AlreadyExists(format!("branch '{}'", name)));
+        }
+        state.branches.insert(name.to_string(), head);
+        Ok(head)
+    }
+
+    fn delete_branch(&self, name: &str) -> Result<Cid> {
+        self.write().branches.remove(name).ok_or_else(|| Error::NotFound(format!("branch '{}'", name)))
+    }
+
+    fn reset_branch(&self, name: &str, rev: &str) -> Result<Cid> {
+        if !self.read().branches.contains_key(name) {
+            return Err(Error::NotFound(format!("branch '{}'", name)));
+        }
+        let head = self.resolve_commit(rev)?;
+        self.write().branches.insert(name.to_string(), head);
+        Ok(head)
+    }
+
+    fn put_block(&self, cid: &Cid, data: &[u8]) -> Result<()> {
+        self.blocks.write().unwrap().insert(*cid, data.to_vec());
+        Ok(())
+    }
+
+    fn get_block(&self, cid: &Cid) -> Result<Option<Vec<u8>>> {
+        Ok(self.blocks.read().unwrap().get(cid).cloned())
+    }
+}
@@ -0,0 +1,394 @@
+//! Three-way merging of EngiDB branches.
+//!
+//! Nodes and edges are matched by id, incidences by `(edge, node, role, pos)`.
+//! Divergent changes to the same entity are merged property by property and
+//! whatever cannot be merged automatically is reported as a `MergeConflict`.
+
+use crate::hyperedge::EdgeWrites;
+use crate::transact::TxOp;
+use crate::{EngiDB, Error, Result, BRANCHES, HYPEREDGES, PRUNED_COMMITS};
+use cid::Cid;
+use indexmap::IndexMap;
+use kotoba_types::{Edge, Graph, Incidence, Node};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+/// How conflicting changes are resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeStrategy {
+    /// Keep the value from the branch being merged into.
+    Ours,
+    /// Keep the value from the branch being merged.
+    Theirs,
+    /// Report conflicts and leave the merge uncommitted.
+    Manual,
+}
+
+impl std::str::FromStr for MergeStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "ours" => Ok(Self::Ours),
+            "theirs" => Ok(Self::Theirs),
+            "manual" => Ok(Self::Manual),
+            _ => Err(Error::InvalidArgument(format!("unknown merge strategy '{}'; expected ours, theirs or manual", s))),
+        }
+    }
+}
+
+/// Kind of graph element a conflict is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictTarget {
+    Node,
+    Edge,
+    Incidence,
+}
+
+/// Part of a graph element both sides changed differently.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "field", content = "name", rename_all = "lowercase")]
+pub enum ConflictField {
+    /// One side deleted the element while the other modified it.
+    Presence,
+    Kind,
+    Layer,
+    Property(String),
+}
+
+/// A change that could not be merged automatically.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MergeConflict {
+    pub target: ConflictTarget,
+    pub id: String,
+    #[serde(flatten)]
+    pub field: ConflictField,
+    pub base: Option<Value>,
+    pub ours: Option<Value>,
+    pub theirs: Option<Value>,
+}
+
+/// Result of merging one revision into a branch.
+#[derive(Debug, Clone)]
+pub struct MergeOutcome {
+    /// Common ancestor of both heads, if any.
+    pub base: Option<Cid>,
+    /// New branch head, or `None` if nothing was committed.
+    pub commit: Option<Cid>,
+    /// Whether the branch was simply moved forward to the merged revision.
+    pub fast_forward: bool,
+    /// Merged graph; unresolved conflicts keep our side.
+    pub graph: Graph,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl EngiDB {
+    /// Finds the nearest common ancestor of two commits.
+    pub fn merge_base(&self, ours: &Cid, theirs: &Cid) -> Result<Option<Cid>> {
+        let ancestors: HashSet<Cid> = self.log(&ours.to_string())?
+            .into_iter()
+            .map(|(cid, _)| cid)
+            .collect();
+
+        let pruned = self.tree(PRUNED_COMMITS)?;
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([*theirs]);
+        while let Some(commit_cid) = queue.pop_front() {
+            if ancestors.contains(&commit_cid) {
+                return Ok(Some(commit_cid));
+            }
+            if !seen.insert(commit_cid) {
+                continue;
+            }
+            for parent in self.get_commit(&commit_cid)?.parents {
+                // History cut off by `gc` ends the walk, as it ends `log`.
+                if !pruned.contains_key(parent.to_bytes())? || self.get_block(&parent)?.is_some() {
+                    queue.push_back(parent);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Merges a revision into a branch, creating a merge commit with both heads as parents.
+    ///
+    /// The merged graph becomes the current graph state, so later commits to
+    /// the branch build on the merge. With `MergeStrategy::Manual` nothing is committed when conflicts remain;
+    /// the caller can resolve `MergeOutcome::graph` and commit it with `commit_merge`.
+    pub fn merge(&self, into: &str, from: &str, strategy: MergeStrategy, author: String, message: String) -> Result<MergeOutcome> {
+        let ours = self.branch_head(into)?;
+        let theirs = self.resolve(from)?;
+        let base = self.merge_base(&ours, &theirs)?;
+
+        if ours == theirs || base == Some(theirs) {
+            return Ok(MergeOutcome {
+                base,
+                commit: None,
+                fast_forward: false,
+                graph: self.checkout(&ours.to_string())?,
+                conflicts: Vec::new(),
+            });
+        }
+
+        if base == Some(ours) {
+            let graph = self.checkout(&theirs.to_string())?;
+            self.replace_graph(&graph)?;
+            self.reset_branch(into, &theirs.to_string())?;
+            return Ok(MergeOutcome {
+                base,
+                commit: Some(theirs),
+                fast_forward: true,
+                graph,
+                conflicts: Vec::new(),
+            });
+        }
+
+        let base_graph = match base {
+            Some(base) => self.checkout(&base.to_string())?,
+            None => Graph { node: Vec::new(), edge: Vec::new(), incidence: Vec::new() },
+        };
+        let ours_graph = self.checkout(&ours.to_string())?;
+        let theirs_graph = self.checkout(&theirs.to_string())?;
+        let (graph, conflicts) = merge_graphs(&base_graph, &ours_graph, &theirs_graph, strategy);
+
+        let commit = if strategy == MergeStrategy::Manual && !conflicts.is_empty() {
+            None
+        } else {
+            self.replace_graph(&graph)?;
+            Some(self.commit_graph(into, &graph, vec![ours, theirs], author, message)?)
+        };
+
+        Ok(MergeOutcome { base, commit, fast_forward: false, graph, conflicts })
+    }
+
+    /// Commits a manually resolved merge of a revision into a branch.
+    ///
+    /// Like `merge`, this makes `graph` the current graph state.
+    pub fn commit_merge(&self, into: &str, from: &str, graph: &Graph, author: String, message: String) -> Result<Cid> {
+        let ours = self.branch_head(into)?;
+        let theirs = self.resolve(from)?;
+        self.replace_graph(graph)?;
+        self.commit_graph(into, graph, vec![ours, theirs], author, message)
+    }
+
+    /// Makes the current graph state equal to `graph`.
+    ///
+    /// Stored edges and nodes missing from `graph` are removed, then `graph`
+    /// is imported over what is left, so entities present on both sides keep
+    /// their vertex ids and their attribute history. All of it is written in
+    /// one transaction, so the live graph is never left half-merged.
+    fn replace_graph(&self, graph: &Graph) -> Result<()> {
+        let edge_ids: HashSet<&str> = graph.edge.iter().map(|edge| edge.id.as_str()).collect();
+        let mut removals = EdgeWrites::default();
+        for result in self.tree(HYPEREDGES)?.iter() {
+            let (id, _) = result?;
+            let id = std::str::from_utf8(&id)?;
+            if !edge_ids.contains(id) {
+                self.stage_edge_removal(id, &mut removals)?;
+            }
+        }
+
+        let node_ids: HashSet<&str> = graph.node.iter().map(|node| node.id.as_str()).collect();
+        let retractions: Vec<TxOp> = self.graph_from_snapshot(&self.snapshot()?)?
+            .node
+            .into_iter()
+            .filter(|node| !node_ids.contains(node.id.as_str()))
+            .map(|node| TxOp::RetractEntity { entity: node.id })
+            .collect();
+        let retraction = if retractions.is_empty() { None } else { Some(self.stage_transact(&retractions)?) };
+
+        let import = self.stage_import(graph)?;
+        self.write_graph(|trees| {
+            trees.apply_edges(&removals)?;
+            if let Some(retraction) = &retraction {
+                retraction.apply(trees)?;
+            }
+            import.apply(trees)
+        })
+    }
+
+    fn branch_head(&self, branch: &str) -> Result<Cid> {
+        let branches_tree = self.tree(BRANCHES)?;
+        let head = branches_tree.get(branch.as_bytes())?
+            .ok_or_else(|| Error::NotFound(format!("branch '{}'", branch)))?;
+        crate::cid_from_bytes(&head)
+    }
+}
+
+/// Three-way merges two graphs against their common ancestor.
+///
+/// Returns the merged graph together with every conflict encountered, resolved
+/// according to `strategy` (`Manual` keeps our side). Incidences left pointing
+/// at a missing node or edge are dropped.
+pub fn merge_graphs(base: &Graph, ours: &Graph, theirs: &Graph, strategy: MergeStrategy) -> (Graph, Vec<MergeConflict>) {
+    let mut merger = Merger { strategy, conflicts: Vec::new() };
+
+    let node = merger.merge_all(&base.node, &ours.node, &theirs.node);
+    let edge = merger.merge_all(&base.edge, &ours.edge, &theirs.edge);
+    let mut incidence = merger.merge_all(&base.incidence, &ours.incidence, &theirs.incidence);
+
+    let node_ids: HashSet<&str> = node.iter().map(|n| n.id.as_str()).collect();
+    let edge_ids: HashSet<&str> = edge.iter().map(|e| e.id.as_str()).collect();
+    incidence.retain(|i| node_ids.contains(i.node.as_str()) && edge_ids.contains(i.edge.as_str()));
+
+    (Graph { node, edge, incidence }, merger.conflicts)
+}
+
+/// Graph element that can take part in a three-way merge.
+trait Mergeable: Clone + PartialEq + Serialize {
+    const TARGET: ConflictTarget;
+
+    fn key(&self) -> String;
+
+    /// Merges two versions that both diverged from `base`.
+    fn merge_fields(merger: &mut Merger, base: Option<&Self>, ours: &Self, theirs: &Self) -> Self;
+}
+
+impl Mergeable for Node {
+    const TARGET: ConflictTarget = ConflictTarget::Node;
+
+    fn key(&self) -> String {
+        self.id.clone()
+    }
+
+    fn merge_fields(merger: &mut Merger, base: Option<&Self>, ours: &Self, theirs: &Self) -> Self {
+        let id = ours.key();
+        let kind = merger.merge_value(Self::TARGET, &id, ConflictField::Kind,
+            base.map(|b| &b.kind), Some(&ours.kind), Some(&theirs.kind));
+        let properties = merger.merge_properties(Self::TARGET, &id,
+            base.map(|b| &b.properties), &ours.properties, &theirs.properties);
+        Node { id, kind: kind.unwrap_or_else(|| ours.kind.clone()), properties }
+    }
+}
+
+impl Mergeable for Edge {
+    const TARGET: ConflictTarget = ConflictTarget::Edge;
+
+    fn key(&self) -> String {
+        self.id.clone()
+    }
+
+    fn merge_fields(merger: &mut Merger, base: Option<&Self>, ours: &Self, theirs: &Self) -> Self {
+        let id = ours.key();
+        let kind = merger.merge_value(Self::TARGET, &id, ConflictField::Kind,
+            base.map(|b| &b.kind), Some(&ours.kind), Some(&theirs.kind));
+        let layer = merger.merge_value(Self::TARGET, &id, ConflictField::Layer,
+            base.map(|b| &b.layer), Some(&ours.layer), Some(&theirs.layer));
+        let properties = merger.merge_properties(Self::TARGET, &id,
+            base.map(|b| &b.properties), &ours.properties, &theirs.properties);
+        Edge {
+            id,
+            layer: layer.unwrap_or(ours.layer),
+            kind: kind.unwrap_or_else(|| ours.kind.clone()),
+            properties,
+        }
+    }
+}
+
+impl Mergeable for Incidence {
+    const TARGET: ConflictTarget = ConflictTarget::Incidence;
+
+    fn key(&self) -> String {
+        incidence_key(self)
+    }
+
+    fn merge_fields(merger: &mut Merger, base: Option<&Self>, ours: &Self, theirs: &Self) -> Self {
+        let properties = merger.merge_properties(Self::TARGET, &ours.key(),
+            base.map(|b| &b.properties), &ours.properties, &theirs.properties);
+        Incidence { properties, ..ours.clone() }
+    }
+}
+
+/// Identity of an incidence: `edge/node/role[/pos]`.
+pub(crate) fn incidence_key(incidence: &Incidence) -> String {
+    match incidence.pos {
+        Some(pos) => format!("{}/{}/{}/{}", incidence.edge, incidence.node, incidence.role, pos),
+        None => format!("{}/{}/{}", incidence.edge, incidence.node, incidence.role),
+    }
+}
+
+struct Merger {
+    strategy: MergeStrategy,
+    conflicts: Vec<MergeConflict>,
+}
+
+impl Merger {
+    fn merge_all<T: Mergeable>(&mut self, base: &[T], ours: &[T], theirs: &[T]) -> Vec<T> {
+        let index = |items: &[T]| -> BTreeMap<String, T> {
+            items.iter().map(|item| (item.key(), item.clone())).collect()
+        };
+        let (base, ours, theirs) = (index(base), index(ours), index(theirs));
+
+        let keys: std::collections::BTreeSet<&String> = base.keys().chain(ours.keys()).chain(theirs.keys()).collect();
+        let mut merged = Vec::new();
+        for key in keys {
+            let (b, o, t) = (base.get(key), ours.get(key), theirs.get(key));
+            let item = match (o, t) {
+                (Some(o), Some(t)) if o != t && b != Some(o) && b != Some(t) => {
+                    Some(T::merge_fields(self, b, o, t))
+                }
+                _ => self.merge_value(T::TARGET, key, ConflictField::Presence, b, o, t),
+            };
+            merged.extend(item);
+        }
+        merged
+    }
+
+    fn merge_properties(
+        &mut self,
+        target: ConflictTarget,
+        id: &str,
+        base: Option<&IndexMap<String, Value>>,
+        ours: &IndexMap<String, Value>,
+        theirs: &IndexMap<String, Value>,
+    ) -> IndexMap<String, Value> {
+        let mut merged = IndexMap::new();
+        for key in ours.keys().chain(theirs.keys()) {
+            if merged.contains_key(key) {
+                continue;
+            }
+            let value = self.merge_value(target, id, ConflictField::Property(key.clone()),
+                base.and_then(|b| b.get(key)), ours.get(key), theirs.get(key));
+            if let Some(value) = value {
+                merged.insert(key.clone(), value);
+            }
+        }
+        merged
+    }
+
+    /// Classic three-way rule: take whichever side changed, conflict if both did.
+    fn merge_value<T: Clone + PartialEq + Serialize>(
+        &mut self,
+        target: ConflictTarget,
+        id: &str,
+        field: ConflictField,
+        base: Option<&T>,
+        ours: Option<&T>,
+        theirs: Option<&T>,
+    ) -> Option<T> {
+        if ours == theirs || base == theirs {
+            return ours.cloned();
+        }
+        if base == ours {
+            return theirs.cloned();
+        }
+
+        let to_value = |v: Option<&T>| v.and_then(|v| serde_json::to_value(v).ok());
+        self.conflicts.push(MergeConflict {
+            target,
+            id: id.to_string(),
+            field,
+            base: to_value(base),
+            ours: to_value(ours),
+            theirs: to_value(theirs),
+        });
+
+        match self.strategy {
+            MergeStrategy::Theirs => theirs.cloned(),
+            MergeStrategy::Ours | MergeStrategy::Manual => ours.cloned(),
+        }
+    }
+}
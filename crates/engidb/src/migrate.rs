@@ -0,0 +1,146 @@
+//! On-disk format versioning.
+//!
+//! Every sled database keeps the version of its layout in its `meta` tree.
+//! A `Layout` lists the steps that bring a database from one version to the
+//! next; opening a database runs the steps it is missing, in order, and
+//! records the new version after each of them. A step must therefore be safe
+//! to run again if the process dies before its version is recorded.
+//!
+//! Databases created by the current code are stamped with the current
+//! version and never migrated. Databases written by newer code are refused.
+
+use crate::{EngiDB, Error, Result, META};
+use std::path::{Path, PathBuf};
+
+/// Key in the `meta` tree holding the format version as a big-endian `u32`.
+const FORMAT_VERSION: &[u8] = b"format_version";
+
+/// Step from version `from` to `from + 1`.
+pub struct Migration<T: 'static> {
+    pub from: u32,
+    pub description: &'static str,
+    pub run: fn(&T) -> Result<()>,
+}
+
+/// Versioned layout of a sled database.
+pub struct Layout<T: 'static> {
+    pub name: &'static str,
+    pub version: u32,
+    /// Steps ordered by `from`, covering every version below `version`.
+    pub migrations: &'static [Migration<T>],
+}
+
+/// Layout of the trees written by `EngiDB`.
+pub static ENGIDB_LAYOUT: Layout<EngiDB> = Layout {
+    name: "engidb",
+    version: 1,
+    migrations: &[Migration {
+        from: 0,
+        description: "encode adjacency keys with the tuple codec and give entities to the stored nodes",
+        run: |db| {
+            db.migrate_legacy_keys()?;
+            db.backfill_entities().map(drop)
+        },
+    }],
+};
+
+/// How `EngiDB::migrate` runs.
+#[derive(Debug, Clone, Default)]
+pub struct MigrateOptions {
+    /// Only report the steps that would run.
+    pub dry_run: bool,
+    /// Copy the database to this new directory before migrating it.
+    pub backup: Option<PathBuf>,
+}
+
+/// Outcome of a migration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    /// Descriptions of the steps run, or to be run in a dry run.
+    pub steps: Vec<&'static str>,
+    pub backup: Option<PathBuf>,
+}
+
+/// Format version recorded in a database; unversioned databases are version 0.
+pub(crate) fn format_version(db: &sled::Db) -> Result<u32> {
+    match db.open_tree(META)?.get(FORMAT_VERSION)? {
+        Some(bytes) => {
+            let bytes: [u8; 4] = bytes.as_ref().try_into()
+                .map_err(|_| Error::Serialization("invalid format version".to_string()))?;
+            Ok(u32::from_be_bytes(bytes))
+        }
+        None => Ok(0),
+    }
+}
+
+fn set_format_version(db: &sled::Db, version: u32) -> Result<()> {
+    db.open_tree(META)?.insert(FORMAT_VERSION, &version.to_be_bytes())?;
+    Ok(())
+}
+
+/// Brings `db` to the current version of `layout`, running each step on `target`.
+pub(crate) fn migrate<T>(db: &sled::Db, target: &T, layout: &Layout<T>, options: &MigrateOptions) -> Result<MigrationReport> {
+    if !db.was_recovered() && format_version(db)? == 0 {
+        set_format_version(db, layout.version)?;
+    }
+    let from = format_version(db)?;
+    if from > layout.version {
+        return Err(Error::Migration(format!(
+            "{} database has format version {}, newer than the supported {}", layout.name, from, layout.version,
+        )));
+    }
+    let steps: Vec<&Migration<T>> = layout.migrations.iter().filter(|m| m.from >= from).collect();
+    let mut report = MigrationReport {
+        from,
+        to: layout.version,
+        steps: steps.iter().map(|m| m.description).collect(),
+        backup: None,
+    };
+    if options.dry_run || steps.is_empty() {
+        return Ok(report);
+    }
+
+    if let Some(path) = &options.backup {
+        backup(db, path)?;
+        report.backup = Some(path.clone());
+    }
+    for step in steps {
+        (step.run)(target)?;
+        set_format_version(db, step.from + 1)?;
+    }
+    db.flush()?;
+    Ok(report)
+}
+
+/// Copies every tree of `db` into a new database at `path`.
+fn backup(db: &sled::Db, path: &Path) -> Result<()> {
+    if path.exists() {
+        return Err(Error::AlreadyExists(format!("backup path {}", path.display())));
+    }
+    let copy = sled::open(path)?;
+    copy.import(db.export());
+    copy.flush()?;
+    Ok(())
+}
+
+impl EngiDB {
+    /// Runs the pending format migrations of the database at `path`.
+    ///
+    /// Unlike `open`, this can report the steps without running them and back
+    /// the database up first. The database must already exist.
+    pub fn migrate<P: AsRef<Path>>(path: P, options: &MigrateOptions) -> Result<MigrationReport> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Err(Error::NotFound(format!("database {}", path.display())));
+        }
+        let db = EngiDB { db: sled::open(path)?, namespace: None };
+        migrate(&db.db, &db, &ENGIDB_LAYOUT, options)
+    }
+
+    /// Format version of the database.
+    pub fn format_version(&self) -> Result<u32> {
+        format_version(&self.db)
+    }
+}
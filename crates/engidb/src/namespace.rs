@@ -0,0 +1,54 @@
+//! Named graphs inside one database.
+//!
+//! A namespace is an independent graph with its own nodes, edges, blocks,
+//! indexes, branches and vertex ids. `EngiDB::open` returns a handle on the
+//! default graph, whose trees keep their plain names, so databases written
+//! before namespaces existed still find their data there. The trees of a
+//! named graph are called `<namespace>/<tree>`. Transaction ids are shared by
+//! all graphs of a database, and so is the format version, which is kept in
+//! the default graph.
+
+use crate::{EngiDB, Error, Result};
+
+/// Tree recording the names of the named graphs; it belongs to no namespace.
+const NAMESPACES: &str = "namespaces";
+/// Separator between a namespace and the names of its trees.
+const SEPARATOR: char = '/';
+
+impl EngiDB {
+    /// Handle on the named graph `name`, which is created on first use.
+    ///
+    /// Namespaces do not nest: every handle of a database returns the same
+    /// graph for the same name. Names must be non-empty and must not contain
+    /// `/`.
+    pub fn namespace(&self, name: &str) -> Result<EngiDB> {
+        if name.is_empty() || name.contains(SEPARATOR) {
+            return Err(Error::InvalidArgument(format!("namespace name '{}'", name)));
+        }
+        self.db.open_tree(NAMESPACES)?.insert(name, &[])?;
+        Ok(EngiDB { db: self.db.clone(), namespace: Some(name.to_string()) })
+    }
+
+    /// Name of the graph this handle works on; `None` for the default graph.
+    pub fn namespace_name(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// Names of the named graphs in the database, in order.
+    pub fn namespaces(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for result in self.db.open_tree(NAMESPACES)?.iter().keys() {
+            names.push(String::from_utf8(result?.to_vec()).map_err(|e| Error::Utf8(e.utf8_error()))?);
+        }
+        Ok(names)
+    }
+
+    /// Opens a tree of the graph this handle works on.
+    pub(crate) fn tree(&self, name: &str) -> Result<sled::Tree> {
+        let tree = match &self.namespace {
+            Some(namespace) => self.db.open_tree(format!("{}{}{}", namespace, SEPARATOR, name))?,
+            None => self.db.open_tree(name)?,
+        };
+        Ok(tree)
+    }
+}
@@ -0,0 +1,166 @@
+//! Filtered, paginated node scans.
+//!
+//! The `node_kinds` tree indexes every live node by kind under the tuple key
+//! `(kind, entity)`, so scanning one kind never reads nodes of another. The
+//! entity is encoded as a string value, which sorts bytewise like the keys of
+//! the `entities` tree, so every scan returns nodes in `Node::id` order. The
+//! index is maintained in the same transactions as the property indexes.
+//! Scans are paged by cursor: the id of the last node of the previous page.
+
+use crate::entity::EntityRecord;
+use crate::key::{split_number, successor, Key};
+use crate::{EngiDB, Error, Result, ENTITIES, NODE_KINDS};
+use kotoba_types::Node;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// Condition on a node property. A missing property compares as `null`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Eq(Value),
+    Ne(Value),
+    Lt(Value),
+    Le(Value),
+    Gt(Value),
+    Ge(Value),
+    /// The property is present.
+    Exists,
+}
+
+impl Predicate {
+    fn holds(&self, actual: Option<&Value>) -> bool {
+        let value = actual.unwrap_or(&Value::Null);
+        match self {
+            Predicate::Eq(expected) => compare(value, expected) == Some(Ordering::Equal),
+            Predicate::Ne(expected) => compare(value, expected) != Some(Ordering::Equal),
+            Predicate::Lt(bound) => compare(value, bound) == Some(Ordering::Less),
+            Predicate::Le(bound) => matches!(compare(value, bound), Some(Ordering::Less | Ordering::Equal)),
+            Predicate::Gt(bound) => compare(value, bound) == Some(Ordering::Greater),
+            Predicate::Ge(bound) => matches!(compare(value, bound), Some(Ordering::Greater | Ordering::Equal)),
+            Predicate::Exists => actual.is_some(),
+        }
+    }
+}
+
+/// Orders two values of the same type; numbers are compared by exact value,
+/// as in property index keys.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            let ((a, a_offset), (b, b_offset)) = (split_number(a), split_number(b));
+            Some(a.partial_cmp(&b)?.then(a_offset.cmp(&b_offset)))
+        }
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => (a == b).then_some(Ordering::Equal),
+    }
+}
+
+/// Selection of nodes for `EngiDB::scan_nodes`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeFilter {
+    /// Only nodes of this kind; scanned through the kind index.
+    pub kind: Option<String>,
+    /// Conditions on properties, all of which must hold.
+    pub predicates: Vec<(String, Predicate)>,
+    /// Maximum number of nodes per page.
+    pub limit: Option<usize>,
+    /// Cursor returned with the previous page.
+    pub after: Option<String>,
+}
+
+impl NodeFilter {
+    /// Whether a node satisfies the kind and property conditions.
+    pub fn matches(&self, node: &Node) -> bool {
+        self.kind.as_ref().is_none_or(|kind| *kind == node.kind)
+            && self.predicates.iter().all(|(property, predicate)| predicate.holds(node.properties.get(property)))
+    }
+
+    /// Pages through `nodes`, which must be in `Node::id` order.
+    ///
+    /// Lets stores without a kind index answer `scan_nodes` the same way.
+    pub fn page(&self, nodes: impl IntoIterator<Item = Result<Node>>) -> Result<NodePage> {
+        let mut page = NodePage::default();
+        if self.limit == Some(0) {
+            return Ok(page);
+        }
+        for node in nodes {
+            let node = node?;
+            if self.after.as_ref().is_some_and(|after| node.id <= *after) || !self.matches(&node) {
+                continue;
+            }
+            page.nodes.push(node);
+            if self.limit == Some(page.nodes.len()) {
+                page.next = page.nodes.last().map(|node| node.id.clone());
+                break;
+            }
+        }
+        Ok(page)
+    }
+}
+
+/// One page of a node scan.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodePage {
+    pub nodes: Vec<Node>,
+    /// Cursor for the next page, set when this page is full.
+    pub next: Option<String>,
+}
+
+fn kind_key(kind: &str, entity: &str) -> Key {
+    Key::new().str(kind).value(&Value::String(entity.to_string()))
+}
+
+fn kind_entry(node: &Node) -> Vec<u8> {
+    kind_key(&node.kind, &node.id).into_bytes()
+}
+
+/// Kind index entries to remove and insert when an entity's content changes.
+pub(crate) fn kind_changes(old: Option<&Node>, new: Option<&Node>) -> Vec<(Vec<u8>, bool)> {
+    let (old, new) = (old.map(kind_entry), new.map(kind_entry));
+    if old == new {
+        return Vec::new();
+    }
+    old.map(|key| (key, false)).into_iter().chain(new.map(|key| (key, true))).collect()
+}
+
+impl EngiDB {
+    /// Nodes matching `filter`, in key order, one page at a time.
+    ///
+    /// A page holds at most `filter.limit` nodes; pass its `next` cursor as
+    /// `filter.after` to continue. The page after a full one may be empty.
+    pub fn scan_nodes(&self, filter: &NodeFilter) -> Result<NodePage> {
+        // Appending 0 to a key gives the smallest key greater than it.
+        let past = |mut key: Vec<u8>| {
+            key.push(0);
+            key
+        };
+
+        let candidates: Box<dyn Iterator<Item = Result<Option<cid::Cid>>>> = match &filter.kind {
+            Some(kind) => {
+                let prefix = Key::new().str(kind);
+                let start = match &filter.after {
+                    Some(after) => past(kind_key(kind, after).into_bytes()),
+                    None => prefix.clone().into_bytes(),
+                };
+                let end = successor(prefix.into_bytes());
+                Box::new(self.tree(NODE_KINDS)?.range(start..end).values().map(move |entity| {
+                    let entity = entity?;
+                    Ok(self.entity_record(std::str::from_utf8(&entity)?)?.and_then(|r| r.cid))
+                }))
+            }
+            None => {
+                let start = filter.after.as_ref().map(|after| past(after.as_bytes().to_vec())).unwrap_or_default();
+                Box::new(self.tree(ENTITIES)?.range(start..).values().map(|value| {
+                    Ok(EntityRecord::from_bytes(&value?)?.cid)
+                }))
+            }
+        };
+
+        filter.page(candidates.filter_map(Result::transpose).map(|cid| {
+            let cid = cid?;
+            self.get_dag(&cid)?.ok_or_else(|| Error::NotFound(format!("vertex block {}", cid)))
+        }))
+    }
+
+}
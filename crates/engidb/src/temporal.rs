@@ -0,0 +1,272 @@
+//! Bitemporal fact log for EngiDB.
+//!
+//! Every node attribute change is recorded as a `Datom` carrying both a
+//! transaction time (the transaction that recorded it) and a valid time (when
+//! it became true in the modeled world). `TemporalView` answers "what did we
+//! believe as of transaction T about time V" by replaying those datoms.
+
+use crate::key::Key;
+use crate::{run_transaction, EngiDB, Error, Result, DATOMS, TRANSACTIONS};
+use indexmap::IndexMap;
+use kotoba_types::Node;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Attribute under which a node's `kind` is recorded.
+///
+/// Nodes cannot have a property of this name; writes of such nodes are
+/// rejected so the property is never mistaken for the kind.
+pub const KIND_ATTRIBUTE: &str = "@type";
+
+/// A single fact about an entity attribute.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Datom {
+    pub entity: String,
+    pub attribute: String,
+    pub value: Value,
+    /// Transaction that recorded the fact (transaction time).
+    pub tx: u64,
+    /// Seconds since the epoch from which the fact holds (valid time).
+    pub valid_from: u64,
+    /// `false` if the fact retracts a previously asserted value.
+    pub added: bool,
+}
+
+/// Entry of the transaction log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TxRecord {
+    pub tx: u64,
+    /// Seconds since the epoch at which the transaction was recorded.
+    pub timestamp: u64,
+}
+
+/// Read-only view of the fact log restricted in transaction and/or valid time.
+///
+/// An unset bound means "latest": the view sees all transactions and the
+/// facts valid now.
+#[derive(Clone, Copy)]
+pub struct TemporalView<'a> {
+    db: &'a EngiDB,
+    as_of: Option<u64>,
+    valid_at: Option<u64>,
+}
+
+pub(crate) fn now() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn datom_prefix(entity: &str) -> Key {
+    Key::new().str(entity)
+}
+
+pub(crate) fn datom_key(datom: &Datom) -> Vec<u8> {
+    datom_prefix(&datom.entity)
+        .str(&datom.attribute)
+        .uint(datom.tx)
+        .uint(datom.valid_from)
+        .into_bytes()
+}
+
+pub(crate) fn node_attributes(node: &Node) -> IndexMap<String, Value> {
+    let mut attributes = IndexMap::new();
+    attributes.insert(KIND_ATTRIBUTE.to_string(), Value::String(node.kind.clone()));
+    for (key, value) in &node.properties {
+        attributes.insert(key.clone(), value.clone());
+    }
+    attributes
+}
+
+fn attributes_to_node(entity: &str, mut attributes: IndexMap<String, Value>) -> Option<Node> {
+    let kind = match attributes.shift_remove(KIND_ATTRIBUTE)? {
+        Value::String(kind) => kind,
+        other => other.to_string(),
+    };
+    Some(Node { id: entity.to_string(), kind, properties: attributes })
+}
+
+impl EngiDB {
+    /// View of everything recorded so far, as valid now.
+    pub fn view(&self) -> TemporalView<'_> {
+        TemporalView { db: self, as_of: None, valid_at: None }
+    }
+
+    /// View of the database as it was known after transaction `tx`.
+    pub fn as_of(&self, tx: u64) -> TemporalView<'_> {
+        TemporalView { db: self, as_of: Some(tx), valid_at: None }
+    }
+
+    /// View of the facts valid at time `t` (seconds since the epoch).
+    pub fn valid_at(&self, t: u64) -> TemporalView<'_> {
+        TemporalView { db: self, as_of: None, valid_at: Some(t) }
+    }
+
+    /// Lists the transaction log in order.
+    pub fn transactions(&self) -> Result<Vec<TxRecord>> {
+        let tree = self.tree(TRANSACTIONS)?;
+        let mut records = Vec::new();
+        for result in tree.iter() {
+            let (_, data) = result?;
+            records.push(serde_ipld_dagcbor::from_slice(&data).map_err(|e| Error::Serialization(e.to_string()))?);
+        }
+        Ok(records)
+    }
+
+    /// Records the attributes of `node` as facts valid from `valid_from` (default: now).
+    ///
+    /// Only attributes that differ from what is currently believed at that
+    /// valid time are asserted, and attributes missing from `node` are
+    /// retracted. Returns the transaction id, or `None` if nothing changed.
+    pub fn record_node(&self, node: &Node, valid_from: Option<u64>) -> Result<Option<u64>> {
+        let valid_from = valid_from.unwrap_or_else(now);
+        let changes = self.attribute_changes(node, valid_from)?;
+        self.write_datoms(&node.id, changes, valid_from)
+    }
+
+    /// Attribute assertions and retractions needed to make `node` current at `valid_from`.
+    pub(crate) fn attribute_changes(&self, node: &Node, valid_from: u64) -> Result<Vec<(String, Value, bool)>> {
+        if node.properties.contains_key(KIND_ATTRIBUTE) {
+            return Err(Error::InvalidArgument(format!(
+                "property '{}' of node '{}' is reserved for the node kind",
+                KIND_ATTRIBUTE, node.id
+            )));
+        }
+        let current = self.valid_at(valid_from).attributes(&node.id)?;
+        let desired = node_attributes(node);
+
+        let mut changes = Vec::new();
+        for (attribute, value) in &desired {
+            if current.get(attribute) != Some(value) {
+                changes.push((attribute.clone(), value.clone(), true));
+            }
+        }
+        for (attribute, value) in current {
+            if !desired.contains_key(&attribute) {
+                changes.push((attribute, value, false));
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Retracts every attribute of an entity from `valid_from` (default: now) on.
+    pub fn retract_entity(&self, entity: &str, valid_from: Option<u64>) -> Result<Option<u64>> {
+        let valid_from = valid_from.unwrap_or_else(now);
+        let current = self.valid_at(valid_from).attributes(entity)?;
+        let changes = current.into_iter().map(|(a, v)| (a, v, false)).collect();
+        self.write_datoms(entity, changes, valid_from)
+    }
+
+    /// Writes the datoms of one transaction together with its log entry.
+    fn write_datoms(&self, entity: &str, changes: Vec<(String, Value, bool)>, valid_from: u64) -> Result<Option<u64>> {
+        if changes.is_empty() {
+            return Ok(None);
+        }
+
+        let record = self.allocate_tx()?;
+        let record_data = serde_ipld_dagcbor::to_vec(&record).map_err(|e| Error::Serialization(e.to_string()))?;
+        let mut datoms = Vec::with_capacity(changes.len());
+        for (attribute, value, added) in changes {
+            let datom = Datom { entity: entity.to_string(), attribute, value, tx: record.tx, valid_from, added };
+            let data = serde_ipld_dagcbor::to_vec(&datom).map_err(|e| Error::Serialization(e.to_string()))?;
+            datoms.push((datom_key(&datom), data));
+        }
+
+        let trees = (&self.tree(DATOMS)?, &self.tree(TRANSACTIONS)?);
+        run_transaction(trees, |(datoms_t, transactions_t)| {
+            for (key, data) in &datoms {
+                datoms_t.insert(key.as_slice(), data.as_slice())?;
+            }
+            transactions_t.insert(&record.tx.to_be_bytes(), record_data.as_slice())?;
+            Ok(())
+        })?;
+        Ok(Some(record.tx))
+    }
+
+    /// Allocates a transaction id; the caller logs it with the datoms it records.
+    pub(crate) fn allocate_tx(&self) -> Result<TxRecord> {
+        Ok(TxRecord { tx: self.db.generate_id()? + 1, timestamp: now() })
+    }
+}
+
+impl<'a> TemporalView<'a> {
+    /// Restricts the view to transactions up to and including `tx`.
+    pub fn as_of(self, tx: u64) -> Self {
+        TemporalView { as_of: Some(tx), ..self }
+    }
+
+    /// Restricts the view to facts valid at time `t`.
+    pub fn valid_at(self, t: u64) -> Self {
+        TemporalView { valid_at: Some(t), ..self }
+    }
+
+    /// Datoms recorded for an entity that are visible in this view.
+    pub fn history(&self, entity: &str) -> Result<Vec<Datom>> {
+        let tree = self.db.tree(DATOMS)?;
+        let mut datoms = Vec::new();
+        for result in tree.scan_prefix(datom_prefix(entity)) {
+            let (_, data) = result?;
+            let datom: Datom = serde_ipld_dagcbor::from_slice(&data).map_err(|e| Error::Serialization(e.to_string()))?;
+            if self.sees(&datom) {
+                datoms.push(datom);
+            }
+        }
+        Ok(datoms)
+    }
+
+    /// Attribute values of an entity in this view.
+    pub fn attributes(&self, entity: &str) -> Result<IndexMap<String, Value>> {
+        Ok(resolve(self.history(entity)?).remove(entity).unwrap_or_default())
+    }
+
+    /// The node an entity was in this view, if it existed.
+    pub fn entity(&self, entity: &str) -> Result<Option<Node>> {
+        Ok(attributes_to_node(entity, self.attributes(entity)?))
+    }
+
+    /// All nodes that existed in this view, ordered by id.
+    pub fn nodes(&self) -> Result<Vec<Node>> {
+        let tree = self.db.tree(DATOMS)?;
+        let mut datoms = Vec::new();
+        for result in tree.iter() {
+            let (_, data) = result?;
+            let datom: Datom = serde_ipld_dagcbor::from_slice(&data).map_err(|e| Error::Serialization(e.to_string()))?;
+            if self.sees(&datom) {
+                datoms.push(datom);
+            }
+        }
+        Ok(resolve(datoms)
+            .into_iter()
+            .filter_map(|(entity, attributes)| attributes_to_node(&entity, attributes))
+            .collect())
+    }
+
+    fn sees(&self, datom: &Datom) -> bool {
+        self.as_of.is_none_or(|tx| datom.tx <= tx)
+            && datom.valid_from <= self.valid_at.unwrap_or_else(now)
+    }
+}
+
+/// Folds visible datoms into attribute values per entity.
+///
+/// For each entity attribute the datom with the latest valid time wins, ties
+/// broken by the later transaction; a winning retraction removes the value.
+fn resolve(datoms: Vec<Datom>) -> BTreeMap<String, IndexMap<String, Value>> {
+    let mut latest: BTreeMap<(String, String), Datom> = BTreeMap::new();
+    for datom in datoms {
+        let key = (datom.entity.clone(), datom.attribute.clone());
+        match latest.get(&key) {
+            Some(seen) if (seen.valid_from, seen.tx) > (datom.valid_from, datom.tx) => {}
+            _ => {
+                latest.insert(key, datom);
+            }
+        }
+    }
+
+    let mut entities: BTreeMap<String, IndexMap<String, Value>> = BTreeMap::new();
+    for ((entity, attribute), datom) in latest {
+        if datom.added {
+            entities.entry(entity).or_default().insert(attribute, datom.value);
+        }
+    }
+    entities
+}
@@ -0,0 +1,266 @@
+//! Datom-level write transactions for EngiDB.
+//!
+//! `EngiDB::transact` takes a batch of assert/retract operations on
+//! entity/attribute/value triples, where entities are nodes addressed by
+//! `Node::id`, and applies them in a single multi-tree sled transaction.
+//! Updated nodes keep their vertex id; only the CID they point at changes.
+
+use crate::adjacency::{Adjacency, Direction};
+use crate::hyperedge::EdgeWrites;
+use crate::temporal::{datom_key, node_attributes, now, Datom, KIND_ATTRIBUTE};
+use crate::{EngiDB, Error, GraphTrees, Result};
+use cid::Cid;
+use indexmap::IndexMap;
+use kotoba_types::{Edge, Incidence, Layer, Node};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sled::transaction::{ConflictableTransactionError, ConflictableTransactionResult};
+
+/// Operation in an `EngiDB::transact` batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TxOp {
+    /// Sets an attribute of a node; `@type` sets its kind and creates the node if needed.
+    Assert { entity: String, attribute: String, value: Value },
+    /// Removes a property from a node.
+    Retract { entity: String, attribute: String },
+    /// Removes a node together with its attributes and incident edges.
+    RetractEntity { entity: String },
+    /// Adds a data-layer edge with `source` and `target` incidences between two nodes.
+    ///
+    /// The edge gets the id `source:kind:target`.
+    AssertEdge { source: String, kind: String, target: String },
+    /// Removes every edge of `kind` from `source` to `target`.
+    RetractEdge { source: String, kind: String, target: String },
+}
+
+/// Working copy of an entity touched by a transaction.
+struct EntityState {
+    vertex_id: Option<u64>,
+    old_cid: Option<Cid>,
+    node: Option<Node>,
+}
+
+/// Writes of a `transact` batch, staged before its transaction.
+pub(crate) struct StagedTx {
+    tx: u64,
+    record_data: Vec<u8>,
+    blocks: Vec<(Cid, Vec<u8>)>,
+    /// Entities with their vertex id and the CIDs they move from and to.
+    vertex_writes: Vec<(String, u64, Option<Cid>, Option<Cid>)>,
+    edges: EdgeWrites,
+    datoms: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl StagedTx {
+    /// Makes the writes, failing if a touched entity changed since staging.
+    pub fn apply(&self, trees: &GraphTrees) -> ConflictableTransactionResult<(), Error> {
+        let entity_trees = trees.entity_trees();
+        for (cid, data) in &self.blocks {
+            trees.blocks.insert(cid.to_bytes(), data.as_slice())?;
+        }
+        for (entity, vertex_id, old_cid, new_cid) in &self.vertex_writes {
+            if entity_trees.get(entity)?.and_then(|r| r.cid) != *old_cid {
+                return Err(ConflictableTransactionError::Abort(Error::Transaction(
+                    format!("entity '{}' was modified concurrently", entity),
+                )));
+            }
+            entity_trees.set(entity, *vertex_id, *new_cid)?;
+        }
+        trees.apply_edges(&self.edges)?;
+        for (key, data) in &self.datoms {
+            trees.datoms.insert(key.as_slice(), data.as_slice())?;
+        }
+        trees.transactions.insert(&self.tx.to_be_bytes(), self.record_data.as_slice())?;
+        Ok(())
+    }
+}
+
+impl EngiDB {
+    /// Applies a batch of operations atomically and returns its transaction id.
+    ///
+    /// Either every operation takes effect or none does. If another writer
+    /// changes one of the touched nodes concurrently, the batch is rejected
+    /// with `Error::Transaction`.
+    pub fn transact(&self, ops: &[TxOp]) -> Result<u64> {
+        let staged = self.stage_transact(ops)?;
+        self.write_graph(|trees| staged.apply(trees))?;
+        Ok(staged.tx)
+    }
+
+    /// Works out the writes of `transact` without making them.
+    pub(crate) fn stage_transact(&self, ops: &[TxOp]) -> Result<StagedTx> {
+        let record = self.allocate_tx()?;
+        let valid_from = now();
+
+        let mut states: IndexMap<String, EntityState> = IndexMap::new();
+        let mut datoms = Vec::new();
+        let mut edge_ops = Vec::new();
+        let mut edge_records = EdgeWrites::default();
+        let datom = |entity: &str, attribute: &str, value: Value, added: bool| Datom {
+            entity: entity.to_string(),
+            attribute: attribute.to_string(),
+            value,
+            tx: record.tx,
+            valid_from,
+            added,
+        };
+
+        for op in ops {
+            match op {
+                TxOp::Assert { entity, attribute, value } => {
+                    let state = self.load_entity(&mut states, entity)?;
+                    let node = state.node.get_or_insert_with(|| Node {
+                        id: entity.clone(),
+                        kind: String::new(),
+                        properties: IndexMap::new(),
+                    });
+                    if attribute == KIND_ATTRIBUTE {
+                        node.kind = value.as_str()
+                            .ok_or_else(|| Error::Transaction(format!("{} of '{}' must be a string", KIND_ATTRIBUTE, entity)))?
+                            .to_string();
+                    } else {
+                        node.properties.insert(attribute.clone(), value.clone());
+                    }
+                    datoms.push(datom(entity, attribute, value.clone(), true));
+                }
+                TxOp::Retract { entity, attribute } => {
+                    if attribute == KIND_ATTRIBUTE {
+                        return Err(Error::Transaction(format!("cannot retract {} of '{}'; retract the entity instead", KIND_ATTRIBUTE, entity)));
+                    }
+                    let state = self.load_entity(&mut states, entity)?;
+                    let node = state.node.as_mut()
+                        .ok_or_else(|| Error::NotFound(format!("entity '{}'", entity)))?;
+                    if let Some(old) = node.properties.shift_remove(attribute) {
+                        datoms.push(datom(entity, attribute, old, false));
+                    }
+                }
+                TxOp::RetractEntity { entity } => {
+                    let state = self.load_entity(&mut states, entity)?;
+                    let node = state.node.take()
+                        .ok_or_else(|| Error::NotFound(format!("entity '{}'", entity)))?;
+                    for (attribute, value) in node_attributes(&node) {
+                        datoms.push(datom(entity, &attribute, value, false));
+                    }
+                    for edge in self.edges_touching(entity)? {
+                        self.stage_edge_removal(&edge, &mut edge_records)?;
+                    }
+                }
+                TxOp::AssertEdge { source, kind, target } => {
+                    let id = format!("{}:{}:{}", source, kind, target);
+                    edge_records.put_edge(&Edge { id: id.clone(), layer: Layer::Data, kind: kind.clone(), properties: IndexMap::new() })?;
+                    for (role, node) in [("source", source), ("target", target)] {
+                        edge_records.put_incidence(&Incidence {
+                            node: node.clone(),
+                            edge: id.clone(),
+                            role: role.to_string(),
+                            pos: None,
+                            properties: IndexMap::new(),
+                        })?;
+                    }
+                    edge_ops.push((source.clone(), kind.clone(), target.clone(), true));
+                }
+                TxOp::RetractEdge { source, kind, target } => {
+                    for edge in self.edges_between(source, kind, target)? {
+                        self.stage_edge_removal(&edge, &mut edge_records)?;
+                    }
+                    edge_ops.push((source.clone(), kind.clone(), target.clone(), false));
+                }
+            }
+        }
+
+        // Allocate vertex ids for new entities and stage vertex changes.
+        let new_entities = states.values().filter(|s| s.node.is_some() && s.vertex_id.is_none()).count() as u64;
+        let mut next_id = if new_entities > 0 { self.allocate_vertex_ids(new_entities)? } else { 0 };
+        let mut blocks = Vec::new();
+        let mut vertex_writes = Vec::new();
+        for (entity, state) in states.iter_mut() {
+            match &state.node {
+                Some(node) if node.kind.is_empty() => {
+                    return Err(Error::Transaction(format!("new entity '{}' needs a {} assertion", entity, KIND_ATTRIBUTE)));
+                }
+                Some(node) => {
+                    let vertex_id = *state.vertex_id.get_or_insert_with(|| {
+                        next_id += 1;
+                        next_id - 1
+                    });
+                    let data = serde_ipld_dagcbor::to_vec(node).map_err(|e| Error::Serialization(e.to_string()))?;
+                    let cid = self.calculate_cid(&data)?;
+                    if state.old_cid == Some(cid) {
+                        continue;
+                    }
+                    blocks.push((cid, data));
+                    vertex_writes.push((entity.clone(), vertex_id, state.old_cid, Some(cid)));
+                }
+                None => {
+                    let (Some(vertex_id), Some(_)) = (state.vertex_id, state.old_cid) else { continue };
+                    vertex_writes.push((entity.clone(), vertex_id, state.old_cid, None));
+                    for entry in self.edges_of(vertex_id, Direction::Both)? {
+                        edge_records.remove_adjacency(entry);
+                    }
+                }
+            }
+        }
+
+        for (source, kind, target, insert) in edge_ops {
+            let endpoint = |entity: &str| -> Result<u64> {
+                match states.get(entity) {
+                    Some(EntityState { node: Some(_), vertex_id: Some(id), .. }) => Ok(*id),
+                    Some(_) => Err(Error::NotFound(format!("entity '{}'", entity))),
+                    None => self.entity_vertex(entity)?
+                        .ok_or_else(|| Error::NotFound(format!("entity '{}'", entity))),
+                }
+            };
+            let (source_vertex, target_vertex) = (endpoint(&source)?, endpoint(&target)?);
+            let mut entry = Adjacency { source: source_vertex, kind, target: target_vertex, edge: None, layer: None };
+            if insert {
+                entry.edge = Some(format!("{}:{}:{}", source, entry.kind, target));
+                entry.layer = Some(Layer::Data);
+                edge_records.put_adjacency(entry);
+            } else {
+                // Adjacency added through `add_edge` has no edge record behind it.
+                edge_records.remove_adjacency(entry);
+            }
+        }
+
+        let record_data = serde_ipld_dagcbor::to_vec(&record).map_err(|e| Error::Serialization(e.to_string()))?;
+        let mut datom_writes = Vec::with_capacity(datoms.len());
+        for datom in &datoms {
+            let data = serde_ipld_dagcbor::to_vec(datom).map_err(|e| Error::Serialization(e.to_string()))?;
+            datom_writes.push((datom_key(datom), data));
+        }
+
+        Ok(StagedTx { tx: record.tx, record_data, blocks, vertex_writes, edges: edge_records, datoms: datom_writes })
+    }
+
+    /// Loads the current state of an entity into the transaction's working set.
+    fn load_entity<'s>(&self, states: &'s mut IndexMap<String, EntityState>, entity: &str) -> Result<&'s mut EntityState> {
+        if !states.contains_key(entity) {
+            let mut state = EntityState { vertex_id: None, old_cid: None, node: None };
+            if let Some(record) = self.entity_record(entity)? {
+                state.vertex_id = Some(record.vertex);
+                if let Some(cid) = record.cid {
+                    state.old_cid = Some(cid);
+                    state.node = self.get_dag(&cid)?;
+                }
+            }
+            states.insert(entity.to_string(), state);
+        }
+        Ok(states.get_mut(entity).expect("entity state was just inserted"))
+    }
+
+    /// Ids of the stored edges of `kind` leading from `source` to `target`.
+    fn edges_between(&self, source: &str, kind: &str, target: &str) -> Result<Vec<String>> {
+        let mut edges = Vec::new();
+        for id in self.edges_touching(source)? {
+            if self.get_edge(&id)?.is_none_or(|edge| edge.kind != kind) {
+                continue;
+            }
+            let incidences = self.get_incidences(&id)?;
+            let has = |role: &str, node: &str| incidences.iter().any(|i| i.role == role && i.node == node);
+            if has("source", source) && has("target", target) {
+                edges.push(id);
+            }
+        }
+        Ok(edges)
+    }
+}
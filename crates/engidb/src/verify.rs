@@ -0,0 +1,261 @@
+//! Integrity checks over the stored data.
+//!
+//! `EngiDB::verify` rehashes every block against its CID and checks that the
+//! trees referring to blocks and vertices agree with each other: the vertex
+//! index and its inverse, edge and incidence records, the three adjacency
+//! indexes and the commit graph. It reports what it finds instead of stopping
+//! at the first problem, and never writes.
+
+use crate::adjacency::Adjacency;
+use crate::{
+    calculate_cid, cid_from_bytes, vertex_id_from_bytes, Commit, EngiDB, Result, Snapshot, Transaction, BRANCHES,
+    CID_TO_VERTEX, COMMITS, EDGES, EDGES_IN, EDGE_KINDS, HYPEREDGES, INCIDENCES, IPLD_BLOCKS, PRUNED_COMMITS, VERTICES,
+};
+use cid::Cid;
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Inconsistency found by `EngiDB::verify`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// A block whose content does not hash to its CID.
+    CorruptBlock { cid: Cid, actual: Cid },
+    /// A key or value that cannot be decoded.
+    Malformed { tree: &'static str, key: Vec<u8> },
+    /// A block that is referenced but not stored.
+    MissingBlock { cid: Cid, referenced_by: String },
+    /// A stored block that does not decode as what refers to it expects.
+    UndecodableBlock { cid: Cid, expected: &'static str },
+    /// Two indexes that should mirror each other disagree.
+    IndexMismatch(String),
+    /// An adjacency entry whose endpoint or edge record does not exist.
+    DanglingEdge { entry: Adjacency, missing: String },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::CorruptBlock { cid, actual } => write!(f, "block {} hashes to {}", cid, actual),
+            Problem::Malformed { tree, key } => write!(f, "malformed entry {:02x?} in {}", key, tree),
+            Problem::MissingBlock { cid, referenced_by } => write!(f, "block {} referenced by {} is missing", cid, referenced_by),
+            Problem::UndecodableBlock { cid, expected } => write!(f, "block {} is not a valid {}", cid, expected),
+            Problem::IndexMismatch(detail) => write!(f, "index mismatch: {}", detail),
+            Problem::DanglingEdge { entry, missing } => {
+                write!(f, "edge {} -[{}]-> {} refers to missing {}", entry.source, entry.kind, entry.target, missing)
+            }
+        }
+    }
+}
+
+/// Outcome of `EngiDB::verify`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    pub blocks: usize,
+    pub vertices: usize,
+    /// Adjacency entries checked.
+    pub edges: usize,
+    pub commits: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl EngiDB {
+    /// Checks every block and the indexes that refer to blocks and vertices.
+    pub fn verify(&self) -> Result<VerifyReport> {
+        let mut report = VerifyReport::default();
+        self.verify_blocks(&mut report)?;
+        self.verify_vertices(&mut report)?;
+        self.verify_edge_records(&mut report)?;
+        self.verify_adjacency(&mut report)?;
+        self.verify_commits(&mut report)?;
+        Ok(report)
+    }
+
+    fn verify_blocks(&self, report: &mut VerifyReport) -> Result<()> {
+        for result in self.tree(IPLD_BLOCKS)?.iter() {
+            let (key, data) = result?;
+            report.blocks += 1;
+            let Ok(cid) = cid_from_bytes(&key) else {
+                report.problems.push(Problem::Malformed { tree: IPLD_BLOCKS, key: key.to_vec() });
+                continue;
+            };
+            let actual = calculate_cid(&data);
+            if actual != cid {
+                report.problems.push(Problem::CorruptBlock { cid, actual });
+            }
+        }
+        Ok(())
+    }
+
+    fn verify_vertices(&self, report: &mut VerifyReport) -> Result<()> {
+        let blocks = self.tree(IPLD_BLOCKS)?;
+        let cid_to_vertex = self.tree(CID_TO_VERTEX)?;
+        for result in self.tree(VERTICES)?.iter() {
+            let (key, value) = result?;
+            report.vertices += 1;
+            let (Ok(vertex), Ok(cid)) = (vertex_id_from_bytes(&key), cid_from_bytes(&value)) else {
+                report.problems.push(Problem::Malformed { tree: VERTICES, key: key.to_vec() });
+                continue;
+            };
+            if !blocks.contains_key(cid.to_bytes())? {
+                report.problems.push(Problem::MissingBlock { cid, referenced_by: format!("vertex {}", vertex) });
+            }
+            if cid_to_vertex.get(cid.to_bytes())?.as_deref() != Some(key.as_ref()) {
+                report.problems.push(Problem::IndexMismatch(format!("vertex {} is not the vertex of its CID {}", vertex, cid)));
+            }
+        }
+
+        let vertices = self.tree(VERTICES)?;
+        for result in cid_to_vertex.iter() {
+            let (key, value) = result?;
+            let (Ok(cid), Ok(vertex)) = (cid_from_bytes(&key), vertex_id_from_bytes(&value)) else {
+                report.problems.push(Problem::Malformed { tree: CID_TO_VERTEX, key: key.to_vec() });
+                continue;
+            };
+            if vertices.get(&value)?.as_deref() != Some(key.as_ref()) {
+                report.problems.push(Problem::IndexMismatch(format!("CID {} maps to vertex {}, which does not hold it", cid, vertex)));
+            }
+        }
+        Ok(())
+    }
+
+    fn verify_edge_records(&self, report: &mut VerifyReport) -> Result<()> {
+        let blocks = self.tree(IPLD_BLOCKS)?;
+        for tree in [HYPEREDGES, INCIDENCES] {
+            for result in self.tree(tree)?.iter() {
+                let (key, value) = result?;
+                let Ok(cid) = cid_from_bytes(&value) else {
+                    report.problems.push(Problem::Malformed { tree, key: key.to_vec() });
+                    continue;
+                };
+                if !blocks.contains_key(cid.to_bytes())? {
+                    report.problems.push(Problem::MissingBlock { cid, referenced_by: format!("an entry of {}", tree) });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn verify_adjacency(&self, report: &mut VerifyReport) -> Result<()> {
+        let vertices = self.tree(VERTICES)?;
+        let hyperedges = self.tree(HYPEREDGES)?;
+        let mirrors = [(EDGES_IN, self.tree(EDGES_IN)?), (EDGE_KINDS, self.tree(EDGE_KINDS)?)];
+        let mut mirrored = 0;
+        for result in self.tree(EDGES)?.iter() {
+            let (key, value) = result?;
+            report.edges += 1;
+            let Ok(entry) = Adjacency::from_outgoing(&key, &value) else {
+                report.problems.push(Problem::Malformed { tree: EDGES, key: key.to_vec() });
+                continue;
+            };
+            for (end, vertex) in [("source", entry.source), ("target", entry.target)] {
+                if !vertices.contains_key(vertex.to_be_bytes())? {
+                    report.problems.push(Problem::DanglingEdge { entry: entry.clone(), missing: format!("{} vertex {}", end, vertex) });
+                }
+            }
+            if let Some(edge) = &entry.edge {
+                if !hyperedges.contains_key(edge.as_bytes())? {
+                    report.problems.push(Problem::DanglingEdge { entry: entry.clone(), missing: format!("edge record '{}'", edge) });
+                }
+            }
+            let [_, incoming, by_kind] = entry.keys();
+            for ((name, tree), key) in mirrors.iter().zip([incoming, by_kind]) {
+                if tree.contains_key(key)? {
+                    mirrored += 1;
+                } else {
+                    report.problems.push(Problem::IndexMismatch(format!(
+                        "edge {} -[{}]-> {} is missing from {}", entry.source, entry.kind, entry.target, name,
+                    )));
+                }
+            }
+        }
+        // Every entry of the mirror indexes must match an outgoing entry.
+        let stored: usize = mirrors.iter().map(|(_, tree)| tree.len()).sum();
+        if stored > mirrored {
+            report.problems.push(Problem::IndexMismatch(format!(
+                "{} entries of {} and {} have no outgoing entry", stored - mirrored, EDGES_IN, EDGE_KINDS,
+            )));
+        }
+        Ok(())
+    }
+
+    fn verify_commits(&self, report: &mut VerifyReport) -> Result<()> {
+        let pruned = self.tree(PRUNED_COMMITS)?;
+        let commits_tree = self.tree(COMMITS)?;
+        let mut pending = Vec::new();
+        for result in self.tree(BRANCHES)?.iter() {
+            let (name, head) = result?;
+            match cid_from_bytes(&head) {
+                Ok(cid) => pending.push((cid, format!("branch {}", String::from_utf8_lossy(&name)))),
+                Err(_) => report.problems.push(Problem::Malformed { tree: BRANCHES, key: name.to_vec() }),
+            }
+        }
+        for key in commits_tree.iter().keys() {
+            let key = key?;
+            match cid_from_bytes(&key) {
+                Ok(cid) => pending.push((cid, format!("the {} index", COMMITS))),
+                Err(_) => report.problems.push(Problem::Malformed { tree: COMMITS, key: key.to_vec() }),
+            }
+        }
+
+        let mut seen = HashSet::new();
+        while let Some((commit_cid, referenced_by)) = pending.pop() {
+            if !seen.insert(commit_cid) {
+                continue;
+            }
+            let Some(commit) = self.verify_block::<Commit>(&commit_cid, "commit", &referenced_by, report)? else { continue };
+            report.commits += 1;
+            let referenced_by = format!("commit {}", commit_cid);
+            for parent in &commit.parents {
+                // History cut off by `gc` with a retention window is not a problem.
+                if !pruned.contains_key(parent.to_bytes())? || self.get_block(parent)?.is_some() {
+                    pending.push((*parent, referenced_by.clone()));
+                }
+            }
+            let Some(transaction) =
+                self.verify_block::<Transaction>(&commit.transaction_cid, "transaction", &referenced_by, report)?
+            else {
+                continue;
+            };
+            // Commits written before snapshots existed have none to check.
+            if let Some(root) = &transaction.root {
+                self.verify_block::<Snapshot>(root, "snapshot", &referenced_by, report)?;
+            }
+            if let Some(root) = commits_tree.get(commit_cid.to_bytes())? {
+                if cid_from_bytes(&root).ok() != transaction.root {
+                    report.problems.push(Problem::IndexMismatch(format!(
+                        "commit {} is indexed under a root other than its own", commit_cid,
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads and decodes a referenced block, recording why it is unusable.
+    fn verify_block<T: DeserializeOwned>(
+        &self,
+        cid: &Cid,
+        expected: &'static str,
+        referenced_by: &str,
+        report: &mut VerifyReport,
+    ) -> Result<Option<T>> {
+        let Some(data) = self.get_block(cid)? else {
+            report.problems.push(Problem::MissingBlock { cid: *cid, referenced_by: referenced_by.to_string() });
+            return Ok(None);
+        };
+        match serde_ipld_dagcbor::from_slice(&data) {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => {
+                report.problems.push(Problem::UndecodableBlock { cid: *cid, expected });
+                Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,453 @@
+//! Behaviour every `GraphAdapter` must share.
+//!
+//! Each check is a generic function over `GraphAdapter`; the
+//! `conformance!` macro instantiates all of them for one backend, so a new
+//! adapter joins the suite with a single line. The tests at the end reopen
+//! the persistent backends and compare the snapshots every backend commits
+//! for the same operations.
+
+use cid::Cid;
+use engidb::adapter::{GraphAdapter, InMemoryAdapter, SledAdapter};
+#[cfg(feature = "fcdb")]
+use engidb::FcdbAdapter;
+use engidb::adjacency::Direction;
+use engidb::scan::{NodeFilter, Predicate};
+use engidb::transact::TxOp;
+use engidb::{Error, Transaction};
+use indexmap::IndexMap;
+use kotoba_types::{Edge, Graph, Incidence, Layer, Node};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+mod common;
+
+fn sled(name: &str) -> SledAdapter {
+    SledAdapter::open(common::fresh_dir(name)).unwrap()
+}
+
+#[cfg(feature = "fcdb")]
+fn fcdb(name: &str) -> FcdbAdapter {
+    FcdbAdapter::new_sync(common::fresh_dir(name)).unwrap()
+}
+
+fn memory(_name: &str) -> InMemoryAdapter {
+    InMemoryAdapter::new()
+}
+
+fn node(id: &str, kind: &str, rank: i64) -> Node {
+    let mut properties = IndexMap::new();
+    properties.insert("rank".to_string(), json!(rank));
+    Node { id: id.to_string(), kind: kind.to_string(), properties }
+}
+
+/// Adds an edge with `source` and `target` incidences to `graph`.
+fn link(graph: &mut Graph, id: &str, kind: &str, source: &str, target: &str) {
+    graph.edge.push(Edge { id: id.to_string(), layer: Layer::Data, kind: kind.to_string(), properties: IndexMap::new() });
+    for (node, role) in [(source, "source"), (target, "target")] {
+        graph.incidence.push(Incidence {
+            edge: id.to_string(),
+            node: node.to_string(),
+            role: role.to_string(),
+            pos: None,
+            properties: IndexMap::new(),
+        });
+    }
+}
+
+/// `a -> b -> c` through `next` edges.
+fn chain() -> Graph {
+    let mut graph = Graph {
+        node: vec![node("a", "Item", 1), node("b", "Item", 2), node("c", "Item", 3)],
+        edge: Vec::new(),
+        incidence: Vec::new(),
+    };
+    link(&mut graph, "ab", "next", "a", "b");
+    link(&mut graph, "bc", "next", "b", "c");
+    graph
+}
+
+/// Operations creating two `Item` nodes.
+fn create_items(a: &str, b: &str) -> Vec<TxOp> {
+    vec![
+        TxOp::Assert { entity: a.to_string(), attribute: "@type".to_string(), value: json!("Item") },
+        TxOp::Assert { entity: b.to_string(), attribute: "@type".to_string(), value: json!("Item") },
+    ]
+}
+
+fn vertex<A: GraphAdapter>(adapter: &A, id: &str) -> u64 {
+    adapter.vertex_id(id).unwrap().unwrap_or_else(|| panic!("'{}' is live", id))
+}
+
+fn targets<A: GraphAdapter>(adapter: &A, id: &str, direction: Direction) -> Vec<(u64, String, u64)> {
+    adapter.edges_of(vertex(adapter, id), direction).unwrap()
+        .into_iter()
+        .map(|entry| (entry.source, entry.kind, entry.target))
+        .collect()
+}
+
+fn nodes_are_identified_by_id<A: GraphAdapter>(adapter: &A) {
+    let first = adapter.add_vertex(&node("a", "Item", 1)).unwrap();
+    let updated = adapter.add_vertex(&node("a", "Item", 2)).unwrap();
+    let other = adapter.add_vertex(&node("b", "Item", 1)).unwrap();
+
+    assert_eq!(first, updated);
+    assert_ne!(first, other);
+    assert_eq!(adapter.vertex_id("a").unwrap(), Some(first));
+    assert_eq!(adapter.get_node("a").unwrap(), Some(node("a", "Item", 2)));
+    assert_eq!(adapter.get_node("missing").unwrap(), None);
+    assert_eq!(adapter.vertex_node(first).unwrap(), Some(node("a", "Item", 2)));
+    assert_eq!(adapter.vertex_node(other + 1).unwrap(), None);
+}
+
+fn vertex_ids_are_never_reused<A: GraphAdapter>(adapter: &A) {
+    let a = adapter.add_vertex(&node("a", "Item", 1)).unwrap();
+    adapter.delete_node("a").unwrap();
+    assert_eq!(adapter.vertex_id("a").unwrap(), None);
+    assert_eq!(adapter.get_node("a").unwrap(), None);
+    assert_eq!(adapter.vertex_node(a).unwrap(), None);
+
+    let b = adapter.add_vertex(&node("b", "Item", 1)).unwrap();
+    assert_ne!(a, b);
+    // A deleted entity comes back under its old vertex id.
+    assert_eq!(adapter.add_vertex(&node("a", "Item", 1)).unwrap(), a);
+}
+
+fn raw_edges_are_sorted_and_deduplicated<A: GraphAdapter>(adapter: &A) {
+    adapter.add_edge(1, "next", 3).unwrap();
+    adapter.add_edge(1, "next", 2).unwrap();
+    adapter.add_edge(1, "next", 3).unwrap();
+    adapter.add_edge(1, "other", 4).unwrap();
+
+    assert_eq!(adapter.get_edges_from(1, "next").unwrap(), vec![2, 3]);
+    assert_eq!(adapter.get_edges_from(1, "missing").unwrap(), Vec::<u64>::new());
+    assert_eq!(adapter.get_edges_from(2, "next").unwrap(), Vec::<u64>::new());
+}
+
+fn imported_edges_are_traversable<A: GraphAdapter>(adapter: &A) {
+    adapter.import_graph(&chain()).unwrap();
+    let (a, b, c) = (vertex(adapter, "a"), vertex(adapter, "b"), vertex(adapter, "c"));
+
+    assert_eq!(adapter.get_edges_from(a, "next").unwrap(), vec![b]);
+    assert_eq!(targets(adapter, "b", Direction::Outgoing), vec![(b, "next".to_string(), c)]);
+    assert_eq!(targets(adapter, "b", Direction::Incoming), vec![(a, "next".to_string(), b)]);
+    assert_eq!(targets(adapter, "b", Direction::Both).len(), 2);
+
+    let entry = &adapter.edges_of(a, Direction::Outgoing).unwrap()[0];
+    assert_eq!(entry.edge.as_deref(), Some("ab"));
+    assert_eq!(entry.layer, Some(Layer::Data));
+}
+
+fn edges_between_stored_nodes_are_traversable<A: GraphAdapter>(adapter: &A) {
+    adapter.add_vertex(&node("a", "Item", 1)).unwrap();
+    let mut edges = Graph { node: vec![node("b", "Item", 2)], edge: Vec::new(), incidence: Vec::new() };
+    link(&mut edges, "ab", "next", "a", "b");
+    adapter.import_graph(&edges).unwrap();
+
+    let (a, b) = (vertex(adapter, "a"), vertex(adapter, "b"));
+    assert_eq!(adapter.get_edges_from(a, "next").unwrap(), vec![b]);
+    assert_eq!(targets(adapter, "b", Direction::Incoming), vec![(a, "next".to_string(), b)]);
+}
+
+fn reimporting_an_edge_replaces_it<A: GraphAdapter>(adapter: &A) {
+    adapter.import_graph(&chain()).unwrap();
+    let mut moved = Graph { node: vec![node("a", "Item", 1), node("c", "Item", 3)], edge: Vec::new(), incidence: Vec::new() };
+    link(&mut moved, "ab", "next", "a", "c");
+    adapter.import_graph(&moved).unwrap();
+
+    let (a, c) = (vertex(adapter, "a"), vertex(adapter, "c"));
+    assert_eq!(adapter.get_edges_from(a, "next").unwrap(), vec![c]);
+    assert_eq!(targets(adapter, "b", Direction::Incoming), Vec::new());
+}
+
+fn scans_filter_and_page<A: GraphAdapter>(adapter: &A) {
+    for (i, kind) in ["Item", "Tag", "Item", "Item", "Tag"].iter().enumerate() {
+        adapter.add_vertex(&node(&format!("n{}", i), kind, i as i64)).unwrap();
+    }
+    let ids = |filter: &NodeFilter| {
+        let page = adapter.scan_nodes(filter).unwrap();
+        (page.nodes.into_iter().map(|n| n.id).collect::<Vec<_>>(), page.next)
+    };
+
+    let mut filter = NodeFilter { kind: Some("Item".to_string()), limit: Some(2), ..NodeFilter::default() };
+    assert_eq!(ids(&filter), (vec!["n0".to_string(), "n2".to_string()], Some("n2".to_string())));
+    filter.after = Some("n2".to_string());
+    assert_eq!(ids(&filter), (vec!["n3".to_string()], None));
+
+    let filter = NodeFilter { predicates: vec![("rank".to_string(), Predicate::Ge(json!(3)))], ..NodeFilter::default() };
+    assert_eq!(ids(&filter), (vec!["n3".to_string(), "n4".to_string()], None));
+}
+
+fn transactions_create_update_and_delete<A: GraphAdapter>(adapter: &A) {
+    let mut ops = create_items("a", "b");
+    ops.push(TxOp::Assert { entity: "a".to_string(), attribute: "title".to_string(), value: json!("first") });
+    ops.push(TxOp::AssertEdge { source: "a".to_string(), kind: "next".to_string(), target: "b".to_string() });
+    let first = adapter.transact(&ops).unwrap();
+
+    let a = adapter.get_node("a").unwrap().unwrap();
+    assert_eq!(a.kind, "Item");
+    assert_eq!(a.properties.get("title"), Some(&json!("first")));
+    let entry = &adapter.edges_of(vertex(adapter, "a"), Direction::Outgoing).unwrap()[0];
+    assert_eq!(entry.edge.as_deref(), Some("a:next:b"));
+    assert_eq!(entry.target, vertex(adapter, "b"));
+
+    let second = adapter.transact(&[
+        TxOp::Retract { entity: "a".to_string(), attribute: "title".to_string() },
+        TxOp::RetractEdge { source: "a".to_string(), kind: "next".to_string(), target: "b".to_string() },
+    ]).unwrap();
+    assert!(second > first);
+    assert!(adapter.get_node("a").unwrap().unwrap().properties.is_empty());
+    assert_eq!(targets(adapter, "a", Direction::Both), Vec::new());
+
+    adapter.transact(&[TxOp::AssertEdge { source: "b".to_string(), kind: "next".to_string(), target: "a".to_string() }]).unwrap();
+    adapter.transact(&[TxOp::RetractEntity { entity: "a".to_string() }]).unwrap();
+    assert_eq!(adapter.get_node("a").unwrap(), None);
+    assert_eq!(targets(adapter, "b", Direction::Both), Vec::new());
+}
+
+fn failed_transactions_change_nothing<A: GraphAdapter>(adapter: &A) {
+    adapter.add_vertex(&node("a", "Item", 1)).unwrap();
+    let create = TxOp::Assert { entity: "b".to_string(), attribute: "@type".to_string(), value: json!("Item") };
+
+    let missing = TxOp::Retract { entity: "missing".to_string(), attribute: "rank".to_string() };
+    assert!(matches!(adapter.transact(&[create.clone(), missing]), Err(Error::NotFound(_))));
+    let untyped = TxOp::Assert { entity: "c".to_string(), attribute: "rank".to_string(), value: json!(1) };
+    assert!(matches!(adapter.transact(&[create.clone(), untyped]), Err(Error::Transaction(_))));
+    let kind = TxOp::Retract { entity: "a".to_string(), attribute: "@type".to_string() };
+    assert!(matches!(adapter.transact(&[create.clone(), kind]), Err(Error::Transaction(_))));
+    let dangling = TxOp::AssertEdge { source: "b".to_string(), kind: "next".to_string(), target: "missing".to_string() };
+    assert!(matches!(adapter.transact(&[create, dangling]), Err(Error::NotFound(_))));
+
+    assert_eq!(adapter.get_node("b").unwrap(), None);
+    assert_eq!(adapter.get_node("a").unwrap(), Some(node("a", "Item", 1)));
+    assert_eq!(targets(adapter, "a", Direction::Both), Vec::new());
+}
+
+fn deleting_a_node_removes_its_edges<A: GraphAdapter>(adapter: &A) {
+    adapter.import_graph(&chain()).unwrap();
+    adapter.delete_node("b").unwrap();
+
+    assert_eq!(targets(adapter, "a", Direction::Both), Vec::new());
+    assert_eq!(targets(adapter, "c", Direction::Both), Vec::new());
+    assert!(matches!(adapter.delete_node("b"), Err(Error::NotFound(_))));
+}
+
+fn commits_and_branches<A: GraphAdapter>(adapter: &A) {
+    adapter.import_graph(&chain()).unwrap();
+    let first = adapter.commit("main", "alice".to_string(), "chain".to_string()).unwrap();
+    adapter.delete_node("c").unwrap();
+    let second = adapter.commit("main", "alice".to_string(), "drop c".to_string()).unwrap();
+
+    let log = adapter.log("main").unwrap();
+    assert_eq!(log.iter().map(|(cid, _)| *cid).collect::<Vec<_>>(), vec![second, first]);
+    assert_eq!(log[0].1.parents, vec![first]);
+    assert_eq!(log[0].1.message, "drop c");
+    assert_eq!(adapter.resolve(&first.to_string()).unwrap(), first);
+    assert!(matches!(adapter.resolve("missing"), Err(Error::NotFound(_))));
+
+    let mut ids: Vec<String> = adapter.checkout(&first.to_string()).unwrap().node.into_iter().map(|n| n.id).collect();
+    ids.sort();
+    assert_eq!(ids, ["a", "b", "c"]);
+    assert_eq!(adapter.checkout("main").unwrap().node.len(), 2);
+
+    assert_eq!(adapter.create_branch("feature", &first.to_string()).unwrap(), first);
+    assert!(matches!(adapter.create_branch("feature", "main"), Err(Error::AlreadyExists(_))));
+    assert_eq!(adapter.list_branches().unwrap(), vec![("feature".to_string(), first), ("main".to_string(), second)]);
+    assert_eq!(adapter.reset_branch("feature", "main").unwrap(), second);
+    assert!(matches!(adapter.reset_branch("missing", "main"), Err(Error::NotFound(_))));
+    assert_eq!(adapter.delete_branch("feature").unwrap(), second);
+    assert!(matches!(adapter.delete_branch("feature"), Err(Error::NotFound(_))));
+}
+
+/// A DAG-CBOR block and its CID.
+fn block() -> (Cid, Vec<u8>) {
+    let data = serde_ipld_dagcbor::to_vec(&node("a", "Item", 1)).unwrap();
+    let hash = multihash::Multihash::<64>::wrap(0x12, &Sha256::digest(&data)).unwrap();
+    (Cid::new_v1(0x71, hash), data)
+}
+
+fn blocks_round_trip<A: GraphAdapter>(adapter: &A) {
+    let (cid, data) = block();
+    assert_eq!(adapter.get_block(&cid).unwrap(), None);
+    adapter.put_block(&cid, &data).unwrap();
+    assert_eq!(adapter.get_block(&cid).unwrap(), Some(data));
+}
+
+macro_rules! conformance {
+    ($backend:ident, $open:expr, [$($check:ident),* $(,)?]) => {
+        mod $backend {
+            $(
+                #[test]
+                fn $check() {
+                    super::$check(&$open(concat!(stringify!($backend), "-", stringify!($check))));
+                }
+            )*
+        }
+    };
+}
+
+macro_rules! all_checks {
+    ($backend:ident, $open:expr) => {
+        conformance!($backend, $open, [
+            nodes_are_identified_by_id,
+            vertex_ids_are_never_reused,
+            raw_edges_are_sorted_and_deduplicated,
+            imported_edges_are_traversable,
+            edges_between_stored_nodes_are_traversable,
+            reimporting_an_edge_replaces_it,
+            scans_filter_and_page,
+            transactions_create_update_and_delete,
+            failed_transactions_change_nothing,
+            deleting_a_node_removes_its_edges,
+            commits_and_branches,
+            blocks_round_trip,
+        ]);
+    };
+}
+
+all_checks!(sled_adapter, super::sled);
+all_checks!(in_memory_adapter, super::memory);
+#[cfg(feature = "fcdb")]
+all_checks!(fcdb_adapter, super::fcdb);
+
+/// Writes to a persistent backend, reopens it and checks that nothing was lost.
+fn reopening_keeps_everything<A: GraphAdapter>(open: impl Fn() -> A) {
+    let adapter = open();
+    adapter.import_graph(&chain()).unwrap();
+    let mut failed = create_items("d", "e");
+    failed.push(TxOp::Assert { entity: "x".to_string(), attribute: "rank".to_string(), value: json!(1) });
+    assert!(adapter.transact(&failed).is_err());
+    let d = adapter.add_vertex(&node("d", "Tag", 4)).unwrap();
+    let commit = adapter.commit("main", "alice".to_string(), "chain".to_string()).unwrap();
+    adapter.create_branch("feature", "main").unwrap();
+    let (cid, data) = block();
+    adapter.put_block(&cid, &data).unwrap();
+    let vertices = [vertex(&adapter, "a"), vertex(&adapter, "b"), vertex(&adapter, "c"), d];
+    drop(adapter);
+
+    let adapter = open();
+    assert_eq!([vertex(&adapter, "a"), vertex(&adapter, "b"), vertex(&adapter, "c"), vertex(&adapter, "d")], vertices);
+    assert_eq!(adapter.get_node("b").unwrap(), Some(node("b", "Item", 2)));
+    assert_eq!(targets(&adapter, "b", Direction::Both).len(), 2);
+    assert_eq!(adapter.list_branches().unwrap(), vec![("feature".to_string(), commit), ("main".to_string(), commit)]);
+    assert_eq!(adapter.checkout("main").unwrap().node.len(), 4);
+    assert_eq!(adapter.get_block(&cid).unwrap(), Some(data));
+    assert!(adapter.add_vertex(&node("e", "Tag", 5)).unwrap() > d);
+}
+
+#[test]
+fn sled_keeps_everything_across_reopening() {
+    let dir = common::fresh_dir("sled-reopen");
+    reopening_keeps_everything(|| common::reopen(|| SledAdapter::open(&dir)));
+}
+
+#[cfg(feature = "fcdb")]
+#[test]
+fn fcdb_keeps_everything_across_reopening() {
+    let dir = common::fresh_dir("fcdb-reopen");
+    reopening_keeps_everything(|| FcdbAdapter::new_sync(dir.clone()).unwrap());
+}
+
+#[cfg(feature = "fcdb")]
+#[test]
+fn fcdb_reopens_from_a_checkpoint() {
+    let dir = common::fresh_dir("fcdb-checkpoint");
+    let adapter = FcdbAdapter::new_sync(dir.clone()).unwrap();
+    adapter.import_graph(&chain()).unwrap();
+    // Enough changes to checkpoint the state at least once.
+    for rank in 0..1200 {
+        adapter.add_vertex(&node(&format!("n{}", rank), "Tag", rank)).unwrap();
+    }
+    let commit = adapter.commit("main", "alice".to_string(), "many".to_string()).unwrap();
+    let (cid, data) = block();
+    adapter.put_block(&cid, &data).unwrap();
+    let last = vertex(&adapter, "n1199");
+    drop(adapter);
+
+    let adapter = FcdbAdapter::new_sync(dir.clone()).unwrap();
+    assert_eq!(vertex(&adapter, "n1199"), last);
+    assert_eq!(adapter.get_node("n7").unwrap(), Some(node("n7", "Tag", 7)));
+    assert_eq!(targets(&adapter, "b", Direction::Both).len(), 2);
+    assert_eq!(adapter.resolve("main").unwrap(), commit);
+    assert_eq!(adapter.checkout("main").unwrap().node.len(), 1203);
+    assert_eq!(adapter.get_block(&cid).unwrap(), Some(data));
+    adapter.transact(&[TxOp::RetractEntity { entity: "n7".to_string() }]).unwrap();
+    drop(adapter);
+
+    let adapter = FcdbAdapter::new_sync(dir).unwrap();
+    assert_eq!(adapter.get_node("n7").unwrap(), None);
+    assert!(adapter.add_vertex(&node("z", "Tag", 0)).unwrap() > last);
+}
+
+#[cfg(feature = "fcdb")]
+#[test]
+fn fcdb_refuses_stores_of_the_sled_layout() {
+    let dir = common::fresh_dir("fcdb-legacy");
+    std::fs::create_dir_all(dir.join("fcdb_data")).unwrap();
+    assert!(matches!(FcdbAdapter::new_sync(dir), Err(Error::Migration(_))));
+}
+
+#[cfg(feature = "fcdb")]
+#[test]
+fn fcdb_rejects_a_corrupted_log() {
+    let dir = common::fresh_dir("fcdb-corrupt");
+    FcdbAdapter::new_sync(dir.clone()).unwrap().import_graph(&chain()).unwrap();
+    let pack = dir.join("fcdb_cas").join("pack_00000000.dat");
+    let mut data = std::fs::read(&pack).unwrap();
+    data[0] ^= 0xff;
+    std::fs::write(&pack, data).unwrap();
+
+    assert!(matches!(FcdbAdapter::new_sync(dir), Err(Error::Serialization(_))));
+}
+
+#[cfg(feature = "fcdb")]
+#[test]
+fn fcdb_rejects_a_truncated_or_corrupted_index() {
+    let dir = common::fresh_dir("fcdb-index");
+    let adapter = FcdbAdapter::new_sync(dir.clone()).unwrap();
+    adapter.import_graph(&chain()).unwrap();
+    adapter.add_vertex(&node("d", "Tag", 4)).unwrap();
+    drop(adapter);
+    let index = dir.join("fcdb_cas").join("cidx.dat");
+    let data = std::fs::read(&index).unwrap();
+
+    // A record cut short, as by a crash while appending it, leaves the
+    // latest entry out of the index.
+    std::fs::write(&index, &data[..data.len() - 1]).unwrap();
+    assert!(matches!(FcdbAdapter::new_sync(dir.clone()), Err(Error::Serialization(_))));
+    // A record whose checksum no longer matches.
+    let mut corrupt = data.clone();
+    corrupt[0] ^= 0xff;
+    std::fs::write(&index, corrupt).unwrap();
+    assert!(matches!(FcdbAdapter::new_sync(dir.clone()), Err(Error::Serialization(_))));
+    // The second of two log entries, without the first.
+    std::fs::write(&index, &data[data.len() / 2..]).unwrap();
+    assert!(matches!(FcdbAdapter::new_sync(dir.clone()), Err(Error::Serialization(_))));
+
+    std::fs::write(&index, &data).unwrap();
+    assert_eq!(FcdbAdapter::new_sync(dir).unwrap().get_node("b").unwrap(), Some(node("b", "Item", 2)));
+}
+
+/// Applies the same writes to a backend and returns the snapshot root it commits.
+fn committed_root<A: GraphAdapter>(adapter: &A) -> Cid {
+    adapter.import_graph(&chain()).unwrap();
+    adapter.add_vertex(&node("d", "Tag", 4)).unwrap();
+    adapter.transact(&[
+        TxOp::Assert { entity: "a".to_string(), attribute: "title".to_string(), value: json!("head") },
+        TxOp::AssertEdge { source: "d".to_string(), kind: "tags".to_string(), target: "a".to_string() },
+        TxOp::RetractEntity { entity: "c".to_string() },
+    ]).unwrap();
+    let (a, d) = (vertex(adapter, "a"), vertex(adapter, "d"));
+    adapter.add_edge(a, "raw", d).unwrap();
+
+    let commit = adapter.commit("main", "alice".to_string(), "state".to_string()).unwrap();
+    let transaction = adapter.log(&commit.to_string()).unwrap()[0].1.transaction_cid;
+    let transaction: Transaction = serde_ipld_dagcbor::from_slice(&adapter.get_block(&transaction).unwrap().unwrap()).unwrap();
+    transaction.root.unwrap()
+}
+
+#[test]
+fn backends_commit_the_same_snapshot() {
+    assert_eq!(committed_root(&sled("same-snapshot")), committed_root(&memory("same-snapshot")));
+    #[cfg(feature = "fcdb")]
+    assert_eq!(committed_root(&fcdb("same-snapshot")), committed_root(&memory("same-snapshot")));
+}
@@ -0,0 +1,137 @@
+//! The outgoing, incoming and edge-type indexes stay in step: lookups of
+//! unknown vertices and kinds find nothing, and removed or rewired edges leave
+//! no entry behind in any of them.
+
+use engidb::adjacency::{Adjacency, Direction};
+use engidb::transact::TxOp;
+use engidb::EngiDB;
+use indexmap::IndexMap;
+use kotoba_types::{Edge, Graph, Incidence, Layer, Node};
+
+mod common;
+
+fn item(id: &str) -> Node {
+    Node { id: id.to_string(), kind: "Item".to_string(), properties: IndexMap::new() }
+}
+
+fn link(graph: &mut Graph, id: &str, kind: &str, layer: Layer, source: &str, target: &str) {
+    graph.edge.push(Edge { id: id.to_string(), layer, kind: kind.to_string(), properties: IndexMap::new() });
+    for (node, role) in [(source, "source"), (target, "target")] {
+        graph.incidence.push(Incidence {
+            edge: id.to_string(),
+            node: node.to_string(),
+            role: role.to_string(),
+            pos: None,
+            properties: IndexMap::new(),
+        });
+    }
+}
+
+fn vertex(db: &EngiDB, id: &str) -> u64 {
+    db.entity_vertex(id).unwrap().unwrap()
+}
+
+/// Number of entries in all three indexes that mention `kind`.
+fn entries_of_kind(db: &EngiDB, kind: &str, vertices: &[u64]) -> usize {
+    let mut count = db.edges_of_kind(kind).unwrap().len();
+    for vertex in vertices {
+        count += db.edges_of(*vertex, Direction::Both).unwrap().iter().filter(|entry| entry.kind == kind).count();
+    }
+    count
+}
+
+#[test]
+fn unknown_vertices_and_kinds_have_no_edges() {
+    let db = common::fresh_db("unknown");
+    let mut graph = Graph { node: vec![item("a"), item("b")], edge: Vec::new(), incidence: Vec::new() };
+    link(&mut graph, "ab", "next", Layer::Data, "a", "b");
+    db.import_graph(&graph).unwrap();
+    let (a, b) = (vertex(&db, "a"), vertex(&db, "b"));
+    let unknown = a.max(b) + 100;
+
+    assert!(db.get_edges_from(unknown, "next").unwrap().is_empty());
+    assert!(db.get_edges_to(unknown, "next").unwrap().is_empty());
+    for direction in [Direction::Outgoing, Direction::Incoming, Direction::Both] {
+        assert!(db.edges_of(unknown, direction).unwrap().is_empty());
+        assert!(db.neighbors_in_layer(unknown, direction, Layer::Data).unwrap().is_empty());
+    }
+    assert!(db.edges_of_kind("missing").unwrap().is_empty());
+    assert!(db.get_edges_from(a, "missing").unwrap().is_empty());
+
+    // Kinds are whole key components, so a kind never matches another it starts.
+    assert!(db.edges_of_kind("nex").unwrap().is_empty());
+    assert!(db.get_edges_to(b, "nex").unwrap().is_empty());
+    assert!(db.get_edges_to(b, "next_page").unwrap().is_empty());
+    assert_eq!(db.get_edges_to(b, "next").unwrap(), vec![a]);
+}
+
+#[test]
+fn self_loops_are_listed_once() {
+    let db = common::fresh_db("self-loop");
+    let mut graph = Graph { node: vec![item("a")], edge: Vec::new(), incidence: Vec::new() };
+    link(&mut graph, "aa", "next", Layer::Control, "a", "a");
+    db.import_graph(&graph).unwrap();
+    let a = vertex(&db, "a");
+
+    assert_eq!(db.edges_of(a, Direction::Both).unwrap().len(), 1);
+    assert_eq!(db.neighbors_in_layer(a, Direction::Both, Layer::Control).unwrap(), vec![a]);
+    assert!(db.neighbors_in_layer(a, Direction::Both, Layer::Data).unwrap().is_empty());
+}
+
+#[test]
+fn raw_edges_have_no_id_or_layer() {
+    let db = common::fresh_db("raw");
+    let (a, b) = (db.add_vertex(&item("a")).unwrap(), db.add_vertex(&item("b")).unwrap());
+    db.add_edge(a, "next", b).unwrap();
+
+    assert_eq!(
+        db.edges_of_kind("next").unwrap(),
+        vec![Adjacency { source: a, kind: "next".to_string(), target: b, edge: None, layer: None }],
+    );
+    assert_eq!(db.get_edges_to(b, "next").unwrap(), vec![a]);
+    assert!(db.neighbors_in_layer(a, Direction::Outgoing, Layer::Data).unwrap().is_empty());
+}
+
+#[test]
+fn rewired_and_removed_edges_leave_no_entries() {
+    let db = common::fresh_db("rewired");
+    let mut graph = Graph { node: vec![item("a"), item("b"), item("c")], edge: Vec::new(), incidence: Vec::new() };
+    link(&mut graph, "e", "next", Layer::Data, "a", "b");
+    db.import_graph(&graph).unwrap();
+    let (a, b, c) = (vertex(&db, "a"), vertex(&db, "b"), vertex(&db, "c"));
+
+    // Reimporting the edge between other nodes moves all three entries.
+    let mut rewired = Graph { node: Vec::new(), edge: Vec::new(), incidence: Vec::new() };
+    link(&mut rewired, "e", "next", Layer::Memory, "c", "a");
+    db.import_graph(&rewired).unwrap();
+    assert!(db.get_edges_to(b, "next").unwrap().is_empty());
+    assert!(db.get_edges_from(a, "next").unwrap().is_empty());
+    assert_eq!(db.get_edges_to(a, "next").unwrap(), vec![c]);
+    assert_eq!(db.neighbors_in_layer(a, Direction::Incoming, Layer::Memory).unwrap(), vec![c]);
+    assert_eq!(entries_of_kind(&db, "next", &[a, b, c]), 3);
+
+    // Removing an endpoint removes the edge from every index.
+    db.transact(&[TxOp::RetractEntity { entity: "c".to_string() }]).unwrap();
+    assert_eq!(entries_of_kind(&db, "next", &[a, b, c]), 0);
+}
+
+#[test]
+fn imported_edges_reach_nodes_stored_before() {
+    let db = common::fresh_db("stored");
+    let a = db.add_vertex(&item("a")).unwrap();
+    let mut edges = Graph { node: vec![item("b")], edge: Vec::new(), incidence: Vec::new() };
+    link(&mut edges, "ab", "next", Layer::Data, "a", "b");
+    link(&mut edges, "ba", "back", Layer::Data, "b", "a");
+    db.import_graph(&edges).unwrap();
+    let b = vertex(&db, "b");
+    assert_eq!(db.get_edges_from(a, "next").unwrap(), vec![b]);
+    assert_eq!(db.get_edges_to(a, "back").unwrap(), vec![b]);
+
+    let c = db.add_vertex(&item("c")).unwrap();
+    let mut more = Graph { node: Vec::new(), edge: Vec::new(), incidence: Vec::new() };
+    link(&mut more, "ac", "next", Layer::Data, "a", "c");
+    db.bulk_import_graph(&more, |_| {}).unwrap();
+    assert_eq!(db.get_edges_from(a, "next").unwrap(), vec![b, c]);
+    // The edges also reach the snapshots commits record.
+    assert_eq!(db.snapshot().unwrap().edges.len(), 3);
+}
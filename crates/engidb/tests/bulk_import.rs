@@ -0,0 +1,101 @@
+//! Edge cases of `EngiDB::bulk_import_graph`: empty graphs, rejected nodes,
+//! repeated ids, edges to unknown nodes and reruns.
+
+use engidb::bulk::{ImportPhase, ImportProgress};
+use engidb::temporal::KIND_ATTRIBUTE;
+use engidb::{EngiDB, Error};
+use indexmap::IndexMap;
+use kotoba_types::{Edge, Graph, Incidence, Layer, Node};
+use serde_json::json;
+
+mod common;
+
+fn node(id: &str, rank: i64) -> Node {
+    let mut properties = IndexMap::new();
+    properties.insert("rank".to_string(), json!(rank));
+    Node { id: id.to_string(), kind: "Item".to_string(), properties }
+}
+
+fn link(graph: &mut Graph, id: &str, source: &str, target: &str) {
+    graph.edge.push(Edge { id: id.to_string(), layer: Layer::Data, kind: "next".to_string(), properties: IndexMap::new() });
+    for (node, role) in [(source, "source"), (target, "target")] {
+        graph.incidence.push(Incidence {
+            edge: id.to_string(),
+            node: node.to_string(),
+            role: role.to_string(),
+            pos: None,
+            properties: IndexMap::new(),
+        });
+    }
+}
+
+fn bulk_import(db: &EngiDB, graph: &Graph) -> Result<Vec<ImportProgress>, Error> {
+    let mut reports = Vec::new();
+    db.bulk_import_graph(graph, |report| reports.push(report)).map(|()| reports)
+}
+
+#[test]
+fn empty_graphs_write_nothing() {
+    let db = common::fresh_db("empty");
+    let reports = bulk_import(&db, &Graph { node: Vec::new(), edge: Vec::new(), incidence: Vec::new() }).unwrap();
+    assert!(reports.iter().all(|report| report.total == 0 && report.done == 0));
+    assert_eq!(reports.last().map(|report| report.phase), Some(ImportPhase::Edges));
+    assert!(db.transactions().unwrap().is_empty());
+    assert_eq!(db.snapshot().unwrap(), Default::default());
+}
+
+#[test]
+fn rejected_nodes_stop_the_import_before_any_write() {
+    let db = common::fresh_db("rejected");
+    let mut clash = node("b", 2);
+    clash.properties.insert(KIND_ATTRIBUTE.to_string(), json!("Other"));
+    let graph = Graph { node: vec![node("a", 1), clash], edge: Vec::new(), incidence: Vec::new() };
+
+    let mut phases = Vec::new();
+    let result = db.bulk_import_graph(&graph, |report| phases.push(report.phase));
+    assert!(matches!(result, Err(Error::InvalidArgument(_))));
+    assert!(phases.iter().all(|phase| *phase == ImportPhase::Encode));
+    assert_eq!(db.get_node("a").unwrap(), None);
+    assert_eq!(db.snapshot().unwrap(), Default::default());
+}
+
+#[test]
+fn the_last_node_with_an_id_wins() {
+    let db = common::fresh_db("repeated");
+    let graph = Graph { node: vec![node("a", 1), node("a", 2)], edge: Vec::new(), incidence: Vec::new() };
+    bulk_import(&db, &graph).unwrap();
+    assert_eq!(db.get_node("a").unwrap(), Some(node("a", 2)));
+    assert_eq!(db.snapshot().unwrap().vertices.len(), 1);
+}
+
+#[test]
+fn edges_to_unknown_nodes_keep_their_records_but_no_adjacency() {
+    let db = common::fresh_db("dangling");
+    let mut graph = Graph { node: vec![node("a", 1)], edge: Vec::new(), incidence: Vec::new() };
+    link(&mut graph, "a-missing", "a", "missing");
+    let reports = bulk_import(&db, &graph).unwrap();
+
+    assert!(db.get_edge("a-missing").unwrap().is_some());
+    assert_eq!(db.get_incidences("a-missing").unwrap().len(), 2);
+    let a = db.entity_vertex("a").unwrap().unwrap();
+    assert!(db.get_edges_from(a, "next").unwrap().is_empty());
+    let edges = reports.iter().rfind(|report| report.phase == ImportPhase::Edges).unwrap();
+    assert_eq!(edges.total, 0);
+}
+
+#[test]
+fn rerunning_an_import_changes_nothing() {
+    let db = common::fresh_db("rerun");
+    let mut graph = Graph { node: vec![node("a", 1), node("b", 2)], edge: Vec::new(), incidence: Vec::new() };
+    link(&mut graph, "ab", "a", "b");
+    bulk_import(&db, &graph).unwrap();
+    let snapshot = db.snapshot().unwrap();
+    let transactions = db.transactions().unwrap();
+
+    let reports = bulk_import(&db, &graph).unwrap();
+    assert_eq!(db.snapshot().unwrap(), snapshot);
+    assert_eq!(db.transactions().unwrap(), transactions);
+    for phase in [ImportPhase::Blocks, ImportPhase::Facts, ImportPhase::Entities] {
+        assert!(reports.iter().filter(|report| report.phase == phase).all(|report| report.total == 0), "{:?}", phase);
+    }
+}
@@ -0,0 +1,207 @@
+//! CAR export and import: round trips, pruned history, unknown revisions and
+//! files that are empty, truncated, corrupt or do not fit the target branch.
+
+use cid::Cid;
+use engidb::gc::GcOptions;
+use engidb::{Commit, EngiDB, Error, Transaction};
+use indexmap::IndexMap;
+use kotoba_types::Node;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+mod common;
+
+/// The CID of a DAG-CBOR block that is never stored.
+fn missing(seed: &str) -> Cid {
+    let hash = multihash::Multihash::<64>::wrap(0x12, &Sha256::digest(seed.as_bytes())).unwrap();
+    Cid::new_v1(0x71, hash)
+}
+
+fn item(id: &str, rank: i64) -> Node {
+    let mut properties = IndexMap::new();
+    properties.insert("rank".to_string(), serde_json::json!(rank));
+    Node { id: id.to_string(), kind: "Item".to_string(), properties }
+}
+
+/// A database whose `main` branch has three commits.
+fn history(name: &str) -> (EngiDB, Cid) {
+    let db = common::fresh_db(name);
+    let mut head = None;
+    for rank in 1..=3 {
+        db.add_vertex(&item("a", rank)).unwrap();
+        db.add_vertex(&item(&format!("n{}", rank), rank)).unwrap();
+        head = Some(db.commit("main", "alice".to_string(), format!("commit {}", rank)).unwrap());
+    }
+    (db, head.unwrap())
+}
+
+fn export(db: &EngiDB, commit: &Cid) -> Vec<u8> {
+    let mut out = Vec::new();
+    db.export_car(commit, &mut out).unwrap();
+    out
+}
+
+fn varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// A CAR file with the given header version, roots and blocks of `source`.
+fn car(version: u64, roots: Vec<Cid>, source: &EngiDB, blocks: &[Cid]) -> Vec<u8> {
+    #[derive(Serialize)]
+    struct Header {
+        roots: Vec<Cid>,
+        version: u64,
+    }
+    let mut out = Vec::new();
+    let header = serde_ipld_dagcbor::to_vec(&Header { roots, version }).unwrap();
+    varint(&mut out, header.len());
+    out.extend_from_slice(&header);
+    for cid in blocks {
+        let data = source.get_block(cid).unwrap().unwrap();
+        varint(&mut out, cid.to_bytes().len() + data.len());
+        out.extend_from_slice(&cid.to_bytes());
+        out.extend_from_slice(&data);
+    }
+    out
+}
+
+/// Every block of a database, to check that a failed import wrote nothing.
+fn blocks(db: &EngiDB) -> usize {
+    db.verify().unwrap().blocks
+}
+
+#[test]
+fn histories_round_trip_onto_a_new_branch() {
+    let (source, head) = history("round-trip-source");
+    let file = export(&source, &head);
+    let target = common::fresh_db("round-trip-target");
+
+    let import = target.import_car(file.as_slice(), Some("imported")).unwrap();
+    assert_eq!(import.roots, vec![head]);
+    assert_eq!(import.commits, 3);
+    assert_eq!(import.blocks, source.export_car(&head, std::io::sink()).unwrap());
+    assert_eq!(target.checkout("imported").unwrap(), source.checkout("main").unwrap());
+    assert_eq!(target.log("imported").unwrap().len(), 3);
+    let report = target.verify().unwrap();
+    assert!(report.is_ok(), "{:?}", report.problems);
+
+    // Importing the same file again only needs a branch name of its own.
+    assert!(matches!(target.import_car(file.as_slice(), Some("imported")), Err(Error::AlreadyExists(_))));
+    assert_eq!(target.import_car(file.as_slice(), None).unwrap().commits, 3);
+    assert_eq!(blocks(&target), report.blocks);
+}
+
+#[test]
+fn exports_of_unknown_revisions_write_nothing() {
+    let (db, _) = history("unknown");
+    let mut out = Vec::new();
+    assert!(matches!(db.export_car(&missing("commit"), &mut out), Err(Error::NotFound(_))));
+    let root = db.resolve_root("main").unwrap();
+    assert!(matches!(db.export_car(&root, &mut out), Err(Error::Serialization(_))));
+    assert!(out.is_empty());
+}
+
+#[test]
+fn pruned_history_ends_the_export_and_stays_pruned() {
+    let (source, head) = history("pruned-source");
+    source.gc(&GcOptions { retain_commits: Some(1), ..GcOptions::default() }).unwrap();
+    let file = export(&source, &head);
+
+    let target = common::fresh_db("pruned-target");
+    let import = target.import_car(file.as_slice(), Some("main")).unwrap();
+    assert_eq!(import.commits, 1);
+    assert_eq!(target.log("main").unwrap().len(), 1);
+    assert_eq!(target.checkout("main").unwrap(), source.checkout("main").unwrap());
+    // The missing parent is known to be pruned rather than lost.
+    let report = target.verify().unwrap();
+    assert!(report.is_ok(), "{:?}", report.problems);
+}
+
+#[test]
+fn damaged_files_leave_the_database_untouched() {
+    let (source, head) = history("damaged-source");
+    let file = export(&source, &head);
+    let target = common::fresh_db("damaged-target");
+
+    assert!(matches!(target.import_car(&[][..], None), Err(Error::Serialization(_))));
+    assert!(matches!(target.import_car(&file[..file.len() - 1], None), Err(Error::Serialization(_))));
+    assert!(matches!(target.import_car(&file[..3], None), Err(Error::Serialization(_))));
+    assert!(matches!(target.import_car(&[0xff; 11][..], None), Err(Error::Serialization(_))));
+
+    let mut corrupt = file.clone();
+    let last = corrupt.len() - 1;
+    corrupt[last] ^= 0xff;
+    assert!(matches!(target.import_car(corrupt.as_slice(), None), Err(Error::InvalidArgument(_))));
+
+    let version_2 = car(2, vec![head], &source, &[head]);
+    assert!(matches!(target.import_car(version_2.as_slice(), None), Err(Error::InvalidArgument(_))));
+    assert_eq!(blocks(&target), 0);
+    assert!(target.list_branches().unwrap().is_empty());
+}
+
+#[test]
+fn branches_need_a_single_root_commit() {
+    let (source, head) = history("roots-source");
+    let target = common::fresh_db("roots-target");
+    let parent = source.get_commit(&head).unwrap().parents[0];
+
+    let two_roots = car(1, vec![head, parent], &source, &[head, parent]);
+    assert!(matches!(target.import_car(two_roots.as_slice(), Some("topic")), Err(Error::InvalidArgument(_))));
+    let no_roots = car(1, Vec::new(), &source, &[]);
+    assert!(matches!(target.import_car(no_roots.as_slice(), Some("topic")), Err(Error::InvalidArgument(_))));
+
+    let root = source.resolve_root("main").unwrap();
+    let not_a_commit = car(1, vec![root], &source, &[root]);
+    assert!(matches!(target.import_car(not_a_commit.as_slice(), Some("topic")), Err(Error::InvalidArgument(_))));
+    assert_eq!(blocks(&target), 0);
+    assert!(target.list_branches().unwrap().is_empty());
+
+    // Without a branch, any blocks can be imported.
+    assert_eq!(target.import_car(not_a_commit.as_slice(), None).unwrap().commits, 0);
+    assert_eq!(blocks(&target), 1);
+}
+
+#[test]
+fn imports_without_a_branch_survive_gc() {
+    let (source, head) = history("unbranched-source");
+    let target = common::fresh_db("unbranched-target");
+    let import = target.import_car(export(&source, &head).as_slice(), None).unwrap();
+    assert_eq!(blocks(&target), import.blocks);
+    assert_eq!(target.gc(&GcOptions::default()).unwrap().collected, 0);
+    assert_eq!(blocks(&target), import.blocks);
+    assert_eq!(target.log(&head.to_string()).unwrap().len(), 3);
+    assert_eq!(target.checkout(&head.to_string()).unwrap(), source.checkout("main").unwrap());
+
+    // A snapshot root keeps the nodes it lists.
+    let target = common::fresh_db("unbranched-snapshot");
+    let root = source.resolve_root("main").unwrap();
+    let mut snapshot = vec![root];
+    snapshot.extend(source.get_snapshot(&head).unwrap().vertices);
+    target.import_car(car(1, vec![root], &source, &snapshot).as_slice(), None).unwrap();
+    assert_eq!(target.gc(&GcOptions::default()).unwrap().collected, 0);
+    assert_eq!(blocks(&target), snapshot.len());
+}
+
+#[test]
+fn commits_without_their_transaction_are_not_imported() {
+    let scratch = common::fresh_db("no-transaction-scratch");
+    let transaction_cid = scratch.put_dag(&Transaction { timestamp: 0, root: None }).unwrap();
+    let commit = scratch
+        .put_dag(&Commit { transaction_cid, parents: Vec::new(), author: String::new(), message: String::new() })
+        .unwrap();
+
+    let target = common::fresh_db("no-transaction-target");
+    let file = car(1, vec![commit], &scratch, &[commit]);
+    assert!(matches!(target.import_car(file.as_slice(), Some("main")), Err(Error::NotFound(_))));
+    assert_eq!(blocks(&target), 0);
+
+    // With the transaction in the file, the commit imports.
+    let file = car(1, vec![commit], &scratch, &[commit, transaction_cid]);
+    let import = target.import_car(file.as_slice(), Some("main")).unwrap();
+    assert_eq!((import.blocks, import.commits), (2, 1));
+    assert_eq!(target.log("main").unwrap().len(), 1);
+}
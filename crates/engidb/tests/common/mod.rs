@@ -0,0 +1,37 @@
+//! Helpers shared by the integration tests.
+// Each test binary uses only some of them.
+#![allow(dead_code)]
+
+use engidb::EngiDB;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// An empty directory for a database, named after the test binary, `name`
+/// and the process, so runs of different tests never share one.
+pub fn fresh_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("engidb-{}-{}-{}", env!("CARGO_CRATE_NAME"), name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// A new, empty database.
+pub fn fresh_db(name: &str) -> EngiDB {
+    EngiDB::open(fresh_dir(name)).unwrap()
+}
+
+/// Opens a sled-backed store again right after its last handle was dropped.
+///
+/// sled 0.34 hands log buffer writes and segment truncations to a global
+/// thread pool, and those jobs hold the database's config, which owns the
+/// file lock, until they finish. Dropping the last `Db` therefore does not
+/// release the lock at once, and opening fails with `WouldBlock` until the
+/// jobs are done.
+pub fn reopen<T, E: std::fmt::Debug>(open: impl Fn() -> Result<T, E>) -> T {
+    for _ in 0..50 {
+        if let Ok(store) = open() {
+            return store;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    open().expect("store reopens once sled's background jobs release its lock")
+}
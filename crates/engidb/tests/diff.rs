@@ -0,0 +1,71 @@
+//! Failure modes and edge cases of `EngiDB::diff`: revisions that resolve to
+//! nothing, commits whose snapshot is gone, and empty graphs.
+
+use engidb::diff::{diff_graphs, Change};
+use engidb::{Commit, Error, Snapshot, Transaction};
+use indexmap::IndexMap;
+use kotoba_types::{Graph, Node};
+
+mod common;
+
+fn item(id: &str) -> Node {
+    Node { id: id.to_string(), kind: "Item".to_string(), properties: IndexMap::new() }
+}
+
+fn empty() -> Graph {
+    Graph { node: Vec::new(), edge: Vec::new(), incidence: Vec::new() }
+}
+
+#[test]
+fn unknown_revisions_are_not_found() {
+    let db = common::fresh_db("unknown");
+    assert!(matches!(db.diff("main", "main"), Err(Error::NotFound(_))));
+
+    db.add_vertex(&item("a")).unwrap();
+    db.commit("main", "alice".to_string(), "first".to_string()).unwrap();
+    assert!(matches!(db.diff("main", "topic"), Err(Error::NotFound(_))));
+    assert!(matches!(db.diff("topic", "main"), Err(Error::NotFound(_))));
+}
+
+#[test]
+fn commits_with_a_missing_snapshot_are_not_found() {
+    let db = common::fresh_db("missing");
+    let first = db.commit("main", "alice".to_string(), "empty".to_string()).unwrap();
+
+    // A commit whose snapshot block was never stored.
+    let other = common::fresh_db("missing-other");
+    other.add_vertex(&item("a")).unwrap();
+    let root = other.put_dag(&other.snapshot().unwrap()).unwrap();
+    let transaction_cid = db.put_dag(&Transaction { timestamp: 0, root: Some(root) }).unwrap();
+    let broken = db
+        .put_dag(&Commit { transaction_cid, parents: vec![first], author: String::new(), message: String::new() })
+        .unwrap();
+    assert!(matches!(db.diff(&first.to_string(), &broken.to_string()), Err(Error::NotFound(_))));
+
+    // A snapshot that is stored but names a vertex block that is not.
+    let vertex = other.snapshot().unwrap().vertices[0];
+    let root = db.put_dag(&Snapshot { vertices: vec![vertex], ..Snapshot::default() }).unwrap();
+    let transaction_cid = db.put_dag(&Transaction { timestamp: 0, root: Some(root) }).unwrap();
+    let broken = db
+        .put_dag(&Commit { transaction_cid, parents: vec![first], author: String::new(), message: String::new() })
+        .unwrap();
+    assert!(matches!(db.diff(&first.to_string(), &broken.to_string()), Err(Error::NotFound(_))));
+}
+
+#[test]
+fn empty_graphs_diff_as_additions_or_removals() {
+    let db = common::fresh_db("empty");
+    let empty_commit = db.commit("main", "alice".to_string(), "empty".to_string()).unwrap();
+    assert!(db.diff("main", "main").unwrap().is_empty());
+
+    db.add_vertex(&item("a")).unwrap();
+    let full_commit = db.commit("main", "alice".to_string(), "one item".to_string()).unwrap();
+    let added = db.diff(&empty_commit.to_string(), &full_commit.to_string()).unwrap();
+    assert_eq!(added.nodes.len(), 1);
+    assert_eq!(added.nodes[0].change, Change::Added);
+    let removed = db.diff(&full_commit.to_string(), &empty_commit.to_string()).unwrap();
+    assert_eq!(removed.nodes[0].change, Change::Removed);
+
+    assert!(diff_graphs(&empty(), &empty()).is_empty());
+    assert_eq!(diff_graphs(&empty(), &empty()).to_string(), "No differences.\n");
+}
@@ -0,0 +1,114 @@
+//! Round trip through the database: exporting an imported and committed
+//! graph gives back the same graph, up to the order of its elements.
+
+use engidb::EngiDB;
+use indexmap::IndexMap;
+use kotoba_types::{Edge, Graph, Incidence, Layer, Node};
+use proptest::prelude::*;
+use std::path::Path;
+
+mod common;
+
+/// Sorts nodes, edges and incidences so graphs compare independently of order.
+fn normalized(mut graph: Graph) -> Graph {
+    graph.node.sort_by(|a, b| a.id.cmp(&b.id));
+    graph.edge.sort_by(|a, b| a.id.cmp(&b.id));
+    graph.incidence.sort_by(|a, b| (&a.edge, &a.node, &a.role, a.pos).cmp(&(&b.edge, &b.node, &b.role, b.pos)));
+    graph
+}
+
+fn round_trip(graph: &Graph, db_path: &Path) -> Graph {
+    let db = EngiDB::open(db_path).unwrap();
+    db.import_graph(graph).unwrap();
+    db.commit("main", "test".to_string(), "import".to_string()).unwrap();
+    let exported = db.export_graph(&db.resolve_root("main").unwrap()).unwrap();
+    drop(db);
+    let _ = std::fs::remove_dir_all(db_path);
+    exported
+}
+
+#[test]
+fn examples_round_trip() {
+    let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples");
+    let mut checked = 0;
+    for entry in std::fs::read_dir(&examples).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        // Examples are written in JSON5; files without nodes are not graphs.
+        let source = std::fs::read_to_string(&path).unwrap();
+        let value: serde_json::Value = json5::from_str(&source).unwrap();
+        if value.get("node").is_none() {
+            continue;
+        }
+        let graph: Graph = serde_json::from_value(value)
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let exported = round_trip(&graph, &common::fresh_dir(&name));
+        assert_eq!(normalized(exported), normalized(graph), "{}", path.display());
+        checked += 1;
+    }
+    assert!(checked > 0, "no example graphs found in {}", examples.display());
+}
+
+fn value() -> impl Strategy<Value = serde_json::Value> {
+    prop_oneof![
+        Just(serde_json::Value::Null),
+        any::<bool>().prop_map(serde_json::Value::from),
+        any::<i64>().prop_map(serde_json::Value::from),
+        "[a-z ]{0,8}".prop_map(serde_json::Value::from),
+    ]
+}
+
+fn properties() -> impl Strategy<Value = IndexMap<String, serde_json::Value>> {
+    prop::collection::vec(("[a-z]{1,6}", value()), 0..4).prop_map(|entries| entries.into_iter().collect())
+}
+
+fn layer() -> impl Strategy<Value = Layer> {
+    prop::sample::select(vec![Layer::Syntax, Layer::Data, Layer::Control, Layer::Memory, Layer::Capability])
+}
+
+/// Graphs with unique node and edge ids whose incidences connect them in any way.
+fn graph() -> impl Strategy<Value = Graph> {
+    let nodes = prop::collection::vec(("[A-Z][a-z]{0,5}", properties()), 1..8);
+    let edges = prop::collection::vec((layer(), "[a-z]{1,5}", properties()), 0..6);
+    (nodes, edges).prop_flat_map(|(nodes, edges)| {
+        let incidence = (
+            0..edges.len().max(1),
+            0..nodes.len(),
+            prop::sample::select(vec!["source", "target", "cap_in", "cap_out", "arg"]),
+            prop::option::of(0..4usize),
+            properties(),
+        );
+        let incidences = prop::collection::vec(incidence, if edges.is_empty() { 0..1 } else { 0..12 });
+        (Just(nodes), Just(edges), incidences)
+    }).prop_map(|(nodes, edges, incidences)| {
+        let node: Vec<Node> = nodes.into_iter().enumerate()
+            .map(|(i, (kind, properties))| Node { id: format!("n{}", i), kind, properties })
+            .collect();
+        let edge: Vec<Edge> = edges.into_iter().enumerate()
+            .map(|(i, (layer, kind, properties))| Edge { id: format!("e{}", i), layer, kind, properties })
+            .collect();
+        let mut incidence: Vec<Incidence> = Vec::new();
+        for (e, n, role, pos, properties) in incidences {
+            let (edge, node) = (edge[e].id.clone(), node[n].id.clone());
+            // An edge has at most one incidence per node, role and position.
+            if !incidence.iter().any(|i| i.edge == edge && i.node == node && i.role == role && i.pos == pos) {
+                incidence.push(Incidence { node, edge, role: role.to_string(), pos, properties });
+            }
+        }
+        Graph { node, edge, incidence }
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn generated_graphs_round_trip(graph in graph()) {
+        let exported = round_trip(&graph, &common::fresh_dir("generated"));
+        prop_assert_eq!(normalized(exported), normalized(graph));
+    }
+}
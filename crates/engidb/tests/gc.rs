@@ -0,0 +1,112 @@
+//! `EngiDB::gc`: dry runs, empty stores, pruned history and blocks shared
+//! between branches.
+
+use engidb::gc::{GcOptions, GcReport};
+use engidb::{EngiDB, Error};
+use indexmap::IndexMap;
+use kotoba_types::Node;
+use serde_json::json;
+
+mod common;
+
+fn item(id: &str, rank: i64) -> Node {
+    let mut properties = IndexMap::new();
+    properties.insert("rank".to_string(), json!(rank));
+    Node { id: id.to_string(), kind: "Item".to_string(), properties }
+}
+
+fn commit(db: &EngiDB, branch: &str, message: &str) -> cid::Cid {
+    db.commit(branch, "alice".to_string(), message.to_string()).unwrap()
+}
+
+fn retain(commits: usize) -> GcOptions {
+    GcOptions { retain_commits: Some(commits), ..GcOptions::default() }
+}
+
+#[test]
+fn empty_databases_collect_nothing() {
+    let db = common::fresh_db("empty");
+    assert_eq!(db.gc(&GcOptions::default()).unwrap(), GcReport::default());
+    assert_eq!(db.gc(&retain(1)).unwrap(), GcReport::default());
+}
+
+#[test]
+fn dry_runs_report_without_removing() {
+    let db = common::fresh_db("dry-run");
+    db.add_vertex(&item("a", 1)).unwrap();
+    let old = db.entity_record("a").unwrap().unwrap().cid.unwrap();
+    db.add_vertex(&item("a", 2)).unwrap();
+
+    let dry_run = db.gc(&GcOptions { dry_run: true, ..GcOptions::default() }).unwrap();
+    assert_eq!(dry_run.collected, 1);
+    assert_eq!(dry_run.collected_commits, 0);
+    assert!(db.get_block(&old).unwrap().is_some());
+
+    assert_eq!(db.gc(&GcOptions::default()).unwrap(), dry_run);
+    assert_eq!(db.get_block(&old).unwrap(), None);
+    assert_eq!(db.get_node("a").unwrap(), Some(item("a", 2)));
+    // Nothing is left to collect.
+    assert_eq!(db.gc(&GcOptions::default()).unwrap().collected, 0);
+}
+
+#[test]
+fn committed_content_survives_until_its_commit_is_pruned() {
+    let db = common::fresh_db("pruned");
+    db.add_vertex(&item("a", 1)).unwrap();
+    let first = commit(&db, "main", "first");
+    db.add_vertex(&item("a", 2)).unwrap();
+    let second = commit(&db, "main", "second");
+    db.add_vertex(&item("a", 3)).unwrap();
+    let third = commit(&db, "main", "third");
+
+    assert_eq!(db.gc(&GcOptions::default()).unwrap().collected, 0);
+    assert_eq!(db.checkout(&first.to_string()).unwrap().node, vec![item("a", 1)]);
+    let report = db.gc(&retain(1)).unwrap();
+    assert_eq!(report.collected_commits, 2);
+
+    // The pruned parent ends the history of the head.
+    let log = db.log("main").unwrap();
+    assert_eq!(log.iter().map(|(cid, _)| *cid).collect::<Vec<_>>(), vec![third]);
+    assert_eq!(log[0].1.parents, vec![second]);
+    assert_eq!(db.checkout("main").unwrap().node, vec![item("a", 3)]);
+    for pruned in [first, second] {
+        assert!(matches!(db.checkout(&pruned.to_string()), Err(Error::NotFound(_))));
+        assert!(matches!(db.get_commit(&pruned), Err(Error::NotFound(_))));
+    }
+    // Pruned commits are expected history holes, not problems.
+    let verified = db.verify().unwrap();
+    assert!(verified.is_ok(), "{:?}", verified.problems);
+
+    // Commits go on from a pruned history.
+    db.add_vertex(&item("a", 4)).unwrap();
+    commit(&db, "main", "fourth");
+    assert_eq!(db.log("main").unwrap().len(), 2);
+}
+
+#[test]
+fn every_branch_keeps_its_own_history() {
+    let db = common::fresh_db("branches");
+    db.add_vertex(&item("a", 1)).unwrap();
+    let shared = commit(&db, "main", "shared");
+    db.create_branch("topic", "main").unwrap();
+    db.add_vertex(&item("a", 2)).unwrap();
+    commit(&db, "main", "ahead");
+
+    let report = db.gc(&retain(1)).unwrap();
+    assert_eq!(report.collected_commits, 0);
+    assert_eq!(db.checkout("topic").unwrap().node, vec![item("a", 1)]);
+    assert_eq!(db.checkout(&shared.to_string()).unwrap().node, vec![item("a", 1)]);
+
+    db.delete_branch("topic").unwrap();
+    assert_eq!(db.gc(&retain(1)).unwrap().collected_commits, 1);
+    assert!(matches!(db.checkout(&shared.to_string()), Err(Error::NotFound(_))));
+}
+
+#[test]
+fn branch_heads_are_never_collected() {
+    let db = common::fresh_db("heads");
+    db.add_vertex(&item("a", 1)).unwrap();
+    commit(&db, "main", "first");
+    assert!(matches!(db.gc(&retain(0)), Err(Error::InvalidArgument(_))));
+    assert_eq!(db.log("main").unwrap().len(), 1);
+}
@@ -0,0 +1,114 @@
+//! Failure modes of the history API: revisions that resolve to nothing,
+//! commits whose blocks are missing and branch commands that cannot apply.
+
+use cid::Cid;
+use engidb::{Commit, EngiDB, Error, Snapshot, Transaction};
+use indexmap::IndexMap;
+use kotoba_types::Node;
+use sha2::{Digest, Sha256};
+
+mod common;
+
+/// The CID of a DAG-CBOR block that is never stored.
+fn missing(seed: &str) -> Cid {
+    let hash = multihash::Multihash::<64>::wrap(0x12, &Sha256::digest(seed.as_bytes())).unwrap();
+    Cid::new_v1(0x71, hash)
+}
+
+fn item(id: &str) -> Node {
+    Node { id: id.to_string(), kind: "Item".to_string(), properties: IndexMap::new() }
+}
+
+/// Stores a commit whose transaction records `root`.
+fn commit_with_root(db: &EngiDB, root: Cid) -> Cid {
+    let transaction_cid = db.put_dag(&Transaction { timestamp: 0, root: Some(root) }).unwrap();
+    db.put_dag(&Commit { transaction_cid, parents: Vec::new(), author: "alice".to_string(), message: "broken".to_string() })
+        .unwrap()
+}
+
+#[test]
+fn unknown_revisions_are_not_found() {
+    let db = common::fresh_db("unknown");
+    assert!(matches!(db.checkout("main"), Err(Error::NotFound(_))));
+    assert!(matches!(db.checkout("no-such-branch"), Err(Error::NotFound(_))));
+    assert!(matches!(db.resolve_root("no-such-branch"), Err(Error::NotFound(_))));
+
+    let cid = missing("commit");
+    assert!(matches!(db.checkout(&cid.to_string()), Err(Error::NotFound(_))));
+    assert!(matches!(db.get_commit(&cid), Err(Error::NotFound(_))));
+    assert!(matches!(db.get_snapshot(&cid), Err(Error::NotFound(_))));
+}
+
+#[test]
+fn commits_with_missing_blocks_are_not_found() {
+    let db = common::fresh_db("missing");
+
+    let commit = db
+        .put_dag(&Commit { transaction_cid: missing("transaction"), parents: Vec::new(), author: String::new(), message: String::new() })
+        .unwrap();
+    assert!(matches!(db.checkout(&commit.to_string()), Err(Error::NotFound(_))));
+
+    let commit = commit_with_root(&db, missing("snapshot"));
+    assert!(matches!(db.get_snapshot(&commit), Err(Error::NotFound(_))));
+    assert!(matches!(db.checkout(&commit.to_string()), Err(Error::NotFound(_))));
+
+    let root = db.put_dag(&Snapshot { vertices: vec![missing("vertex")], ..Snapshot::default() }).unwrap();
+    let commit = commit_with_root(&db, root);
+    assert!(db.get_snapshot(&commit).is_ok());
+    assert!(matches!(db.checkout(&commit.to_string()), Err(Error::NotFound(_))));
+    assert!(matches!(db.export_graph(&root), Err(Error::NotFound(_))));
+}
+
+#[test]
+fn blocks_that_are_not_commits_fail_to_decode() {
+    let db = common::fresh_db("not-a-commit");
+    db.add_vertex(&item("a")).unwrap();
+    let root = db.put_dag(&db.snapshot().unwrap()).unwrap();
+    assert!(matches!(db.get_commit(&root), Err(Error::Serialization(_))));
+    assert!(matches!(db.checkout(&root.to_string()), Err(Error::Serialization(_))));
+}
+
+#[test]
+fn commits_of_an_empty_graph_check_out_empty() {
+    let db = common::fresh_db("empty");
+    let commit = db.commit("main", "alice".to_string(), "nothing yet".to_string()).unwrap();
+    let graph = db.checkout(&commit.to_string()).unwrap();
+    assert!(graph.node.is_empty() && graph.edge.is_empty() && graph.incidence.is_empty());
+    assert_eq!(db.get_snapshot(&commit).unwrap(), Snapshot::default());
+}
+
+#[test]
+fn log_of_an_unknown_revision_or_missing_head_is_not_found() {
+    let db = common::fresh_db("log");
+    assert!(matches!(db.log("main"), Err(Error::NotFound(_))));
+    assert!(matches!(db.log(&missing("head").to_string()), Err(Error::NotFound(_))));
+
+    // A parent that was never stored ends the history instead of failing it.
+    let transaction_cid = db.put_dag(&Transaction { timestamp: 0, root: None }).unwrap();
+    let head = db
+        .put_dag(&Commit { transaction_cid, parents: vec![missing("parent")], author: String::new(), message: String::new() })
+        .unwrap();
+    let log = db.log(&head.to_string()).unwrap();
+    assert_eq!(log.iter().map(|(cid, _)| *cid).collect::<Vec<_>>(), vec![head]);
+}
+
+#[test]
+fn branch_commands_reject_unknown_and_existing_branches() {
+    let db = common::fresh_db("branches");
+    assert!(matches!(db.create_branch("topic", "main"), Err(Error::NotFound(_))));
+    assert!(matches!(db.delete_branch("main"), Err(Error::NotFound(_))));
+    assert!(db.list_branches().unwrap().is_empty());
+
+    db.add_vertex(&item("a")).unwrap();
+    let head = db.commit("main", "alice".to_string(), "first".to_string()).unwrap();
+    assert!(matches!(db.create_branch("main", "main"), Err(Error::AlreadyExists(_))));
+    assert!(matches!(db.reset_branch("topic", "main"), Err(Error::NotFound(_))));
+    assert!(matches!(db.reset_branch("main", "no-such-branch"), Err(Error::NotFound(_))));
+    assert!(matches!(db.reset_branch("main", &missing("head").to_string()), Err(Error::NotFound(_))));
+
+    // Branches only point at commits.
+    let root = db.resolve_root("main").unwrap();
+    assert!(db.create_branch("topic", &root.to_string()).is_err());
+    assert!(db.reset_branch("main", &root.to_string()).is_err());
+    assert_eq!(db.list_branches().unwrap(), vec![("main".to_string(), head)]);
+}
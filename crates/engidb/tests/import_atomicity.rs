@@ -0,0 +1,101 @@
+//! Fault injection for `EngiDB::import_graph`.
+//!
+//! The test re-runs itself as a child process that imports a large graph,
+//! kills the child at increasing delays, and checks that the reopened
+//! database holds either none or all of the imported graph.
+
+use engidb::EngiDB;
+use indexmap::IndexMap;
+use kotoba_types::{Edge, Graph, Incidence, Layer, Node};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+mod common;
+
+const CHILD_DB_ENV: &str = "ENGIDB_IMPORT_CHILD_DB";
+const TEST_NAME: &str = "import_is_all_or_nothing_when_killed";
+const NODES: usize = 5_000;
+
+fn node(id: String, index: usize) -> Node {
+    let mut properties = IndexMap::new();
+    properties.insert("index".to_string(), serde_json::json!(index));
+    Node { id, kind: "Item".to_string(), properties }
+}
+
+/// A chain of `NODES` nodes linked by `next` edges.
+fn chain_graph() -> Graph {
+    let mut graph = Graph { node: Vec::new(), edge: Vec::new(), incidence: Vec::new() };
+    for i in 0..NODES {
+        graph.node.push(node(format!("item_{}", i), i));
+    }
+    for i in 1..NODES {
+        let edge_id = format!("next_{}", i);
+        graph.edge.push(Edge { id: edge_id.clone(), kind: "next".to_string(), layer: Layer::Data, properties: IndexMap::new() });
+        for (node, role) in [(format!("item_{}", i - 1), "source"), (format!("item_{}", i), "target")] {
+            graph.incidence.push(Incidence { edge: edge_id.clone(), node, role: role.to_string(), pos: None, properties: IndexMap::new() });
+        }
+    }
+    graph
+}
+
+#[test]
+fn import_is_all_or_nothing_when_killed() {
+    if let Ok(path) = std::env::var(CHILD_DB_ENV) {
+        let db = EngiDB::open(&path).unwrap();
+        db.import_graph(&chain_graph()).unwrap();
+        db.flush().unwrap();
+        return;
+    }
+
+    let mut outcomes = Vec::new();
+    // The last run is never killed, so the import itself completes.
+    let delays = [0, 20, 50, 100, 200, 400, 800, 1600, 3000].map(Some);
+    for delay_ms in delays.into_iter().chain([None]) {
+        let after = delay_ms.map_or("the whole import".to_string(), |ms| format!("{}ms", ms));
+        let dir = common::fresh_dir(&format!("kill-{}", after.replace(' ', "-")));
+
+        // Existing content that must survive any outcome.
+        {
+            let db = EngiDB::open(&dir).unwrap();
+            db.add_vertex(&node("seed".to_string(), 0)).unwrap();
+            db.flush().unwrap();
+        }
+
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", TEST_NAME, "--test-threads=1"])
+            .env(CHILD_DB_ENV, &dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        if let Some(delay_ms) = delay_ms {
+            std::thread::sleep(Duration::from_millis(delay_ms));
+            let _ = child.kill();
+        }
+        child.wait().unwrap();
+
+        let db = common::reopen(|| EngiDB::open(&dir));
+        let snapshot = db.snapshot().unwrap();
+        let facts = db.view().nodes().unwrap();
+        let imported = match snapshot.vertices.len() {
+            1 => {
+                assert!(snapshot.edges.is_empty(), "edges without their vertices after {}", after);
+                assert_eq!(facts.len(), 1, "facts without their vertices after {}", after);
+                false
+            }
+            n if n == NODES + 1 => {
+                assert_eq!(snapshot.edges.len(), NODES - 1, "partial edges after {}", after);
+                assert_eq!(facts.len(), NODES + 1, "partial facts after {}", after);
+                true
+            }
+            n => panic!("partial import after {}: {} of {} vertices", after, n - 1, NODES),
+        };
+        assert!(db.entity_vertex("seed").unwrap().is_some());
+        outcomes.push(imported);
+
+        drop(db);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    assert_eq!(outcomes.last(), Some(&true));
+}
@@ -0,0 +1,209 @@
+//! Databases written before this version of EngiDB: `todo.db` at the root
+//! of the repository holds sixteen todo items committed one by one, before
+//! commits recorded their graph state, and databases whose adjacency keys
+//! still join their components with `:`.
+
+use engidb::adapter::{GraphAdapter, SledAdapter};
+use engidb::adjacency::Adjacency;
+use engidb::entity::Backfill;
+use engidb::migrate::{MigrateOptions, ENGIDB_LAYOUT};
+use engidb::scan::{NodeFilter, Predicate};
+use engidb::transact::TxOp;
+use engidb::{EngiDB, Error};
+use indexmap::IndexMap;
+use kotoba_types::{Edge, Graph, Incidence, Layer, Node};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+mod common;
+
+/// A copy of the baseline `todo.db`, so the tests never touch the original.
+fn baseline_copy(name: &str) -> PathBuf {
+    let dir = common::fresh_dir(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    let original = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../todo.db");
+    for entry in std::fs::read_dir(original).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+    }
+    dir
+}
+
+fn todo() -> NodeFilter {
+    NodeFilter { kind: Some("TodoItem".to_string()), ..NodeFilter::default() }
+}
+
+#[test]
+fn commits_without_a_snapshot_cannot_be_checked_out() {
+    let db = EngiDB::open(baseline_copy("commits")).unwrap();
+    let log = db.log("main").unwrap();
+    assert_eq!(log.len(), 16);
+    let blocks = db.verify().unwrap().blocks;
+
+    // The database kept only its current graph, not the state of each commit.
+    let head = db.get_transaction(&log[0].1).unwrap();
+    assert_eq!(head.root, None);
+    assert!(matches!(db.checkout("main"), Err(Error::NotFound(_))));
+    assert!(matches!(db.get_snapshot(&log[15].0), Err(Error::NotFound(_))));
+    assert!(matches!(db.resolve_root("main"), Err(Error::NotFound(_))));
+    assert!(matches!(db.diff(&log[15].0.to_string(), "main"), Err(Error::NotFound(_))));
+    assert!(matches!(SledAdapter::new(db.clone()).checkout("main"), Err(Error::NotFound(_))));
+    assert_eq!(db.scan_nodes(&todo()).unwrap().nodes.len(), 16);
+
+    // Reading history wrote nothing.
+    let report = db.verify().unwrap();
+    assert_eq!((report.commits, report.blocks), (16, blocks));
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+
+    // New commits on top of legacy history record their snapshot again.
+    let commit = db.commit("main", "alice".to_string(), "after the upgrade".to_string()).unwrap();
+    let transaction = db.get_transaction(&db.get_commit(&commit).unwrap()).unwrap();
+    assert_eq!(transaction.root, Some(db.resolve_root("main").unwrap()));
+    assert_eq!(db.get_commit(&commit).unwrap().parents, vec![log[0].0]);
+    assert_eq!(db.checkout("main").unwrap().node.len(), 16);
+}
+
+#[test]
+fn backfilled_nodes_become_entities() {
+    let path = baseline_copy("entities");
+    let report = EngiDB::migrate(&path, &MigrateOptions { dry_run: true, ..MigrateOptions::default() }).unwrap();
+    assert_eq!(report.from, 0);
+    assert_eq!(report.steps, vec![ENGIDB_LAYOUT.migrations[0].description]);
+
+    // Opening the database gives every stored node an entity.
+    let db = common::reopen(|| EngiDB::open(&path));
+    let nodes = db.scan_nodes(&todo()).unwrap().nodes;
+    assert_eq!(nodes.len(), 16);
+    for node in &nodes {
+        assert!(db.entity_vertex(&node.id).unwrap().is_some());
+    }
+    assert_eq!(db.backfill_entities().unwrap(), Backfill::default());
+
+    // Two pairs of todos were added within the same second and share an id;
+    // one of each pair is renamed after its vertex, and stays reachable
+    // through the id it was stored with.
+    let renamed: Vec<_> = nodes.iter().filter_map(|node| node.id.split_once('~').map(|ids| (node, ids))).collect();
+    assert_eq!(renamed.len(), 2);
+    for (node, (old, vertex)) in renamed {
+        assert_eq!(db.entity_vertex(&node.id).unwrap(), Some(vertex.parse().unwrap()));
+        assert_ne!(db.get_node(old).unwrap().unwrap().properties, node.properties);
+        assert_eq!(db.renamed_entities(old).unwrap(), vec![node.id.clone()]);
+    }
+}
+
+#[test]
+fn baseline_todos_are_found_updated_and_deleted() {
+    let db = EngiDB::open(baseline_copy("queries")).unwrap();
+    assert_eq!(db.format_version().unwrap(), ENGIDB_LAYOUT.version);
+    let completed = |value: bool| {
+        let filter = NodeFilter { predicates: vec![("completed".to_string(), Predicate::Eq(json!(value)))], ..todo() };
+        db.scan_nodes(&filter).unwrap().nodes
+    };
+    let open = completed(false);
+    let done = completed(true).len();
+    assert_eq!(open.len() + done, 16);
+    let page = db.scan_nodes(&NodeFilter { limit: Some(10), ..todo() }).unwrap();
+    assert_eq!(db.scan_nodes(&NodeFilter { after: page.next, ..todo() }).unwrap().nodes.len(), 6);
+
+    // What `todo complete` and `todo delete` do.
+    let (first, second) = (&open[0].id, &open[1].id);
+    db.transact(&[TxOp::Assert { entity: first.clone(), attribute: "completed".to_string(), value: json!(true) }])
+        .unwrap();
+    assert_eq!(db.get_node(first).unwrap().unwrap().properties["completed"], json!(true));
+    assert_eq!(completed(true).len(), done + 1);
+    db.transact(&[TxOp::RetractEntity { entity: second.clone() }]).unwrap();
+    assert_eq!(db.get_node(second).unwrap(), None);
+    assert_eq!(db.scan_nodes(&todo()).unwrap().nodes.len(), 15);
+
+    db.commit("main", "alice".to_string(), "after the upgrade".to_string()).unwrap();
+    assert_eq!(db.checkout("main").unwrap().node.len(), 15);
+}
+
+/// Stores nodes `a` and `b` and returns their vertex ids.
+fn two_nodes(db: &EngiDB, edges: Vec<Edge>, incidences: Vec<Incidence>) -> (u64, u64) {
+    let item = |id: &str| Node { id: id.to_string(), kind: "Item".to_string(), properties: IndexMap::new() };
+    db.import_graph(&Graph { node: vec![item("a"), item("b")], edge: edges, incidence: incidences }).unwrap();
+    (db.entity_vertex("a").unwrap().unwrap(), db.entity_vertex("b").unwrap().unwrap())
+}
+
+/// Replaces the adjacency trees by `edges` and marks the database as written
+/// before the tuple codec.
+fn write_legacy_adjacency(dir: &Path, edges: &[(String, &str)]) {
+    let raw = common::reopen(|| sled::open(dir));
+    for name in ["edges", "edges_in", "edge_kinds"] {
+        raw.open_tree(name).unwrap().clear().unwrap();
+    }
+    let tree = raw.open_tree("edges").unwrap();
+    for (key, value) in edges {
+        tree.insert(key.as_bytes(), *value).unwrap();
+    }
+    let meta = raw.open_tree("meta").unwrap();
+    meta.remove("tuple_keys").unwrap();
+    meta.remove("format_version").unwrap();
+    raw.flush().unwrap();
+}
+
+#[test]
+fn kinds_and_edge_ids_may_contain_the_old_separator() {
+    let dir = common::fresh_dir("separator");
+    let db = EngiDB::open(&dir).unwrap();
+    let edge = Edge { id: "e:1".to_string(), layer: Layer::Control, kind: "part:of".to_string(), properties: IndexMap::new() };
+    let incidences = [("a", "source"), ("b", "target")].map(|(node, role)| Incidence {
+        edge: edge.id.clone(),
+        node: node.to_string(),
+        role: role.to_string(),
+        pos: None,
+        properties: IndexMap::new(),
+    });
+    let (a, b) = two_nodes(&db, vec![edge], incidences.to_vec());
+    db.flush().unwrap();
+    drop(db);
+
+    write_legacy_adjacency(&dir, &[
+        (format!("{}:part:of:{}:e:1", a, b), "control"),
+        (format!("{}:x:1:{}", a, b), ""),
+    ]);
+
+    let db = common::reopen(|| EngiDB::open(&dir));
+    assert_eq!(db.get_edges_from(a, "part:of").unwrap(), vec![b]);
+    assert_eq!(db.get_edges_to(b, "x:1").unwrap(), vec![a]);
+    assert_eq!(
+        db.edges_of_kind("part:of").unwrap(),
+        vec![Adjacency { source: a, kind: "part:of".to_string(), target: b, edge: Some("e:1".to_string()), layer: Some(Layer::Control) }],
+    );
+    assert_eq!(
+        db.edges_of_kind("x:1").unwrap(),
+        vec![Adjacency { source: a, kind: "x:1".to_string(), target: b, edge: None, layer: None }],
+    );
+    assert!(db.get_edges_from(a, "part").unwrap().is_empty());
+}
+
+#[test]
+fn undecodable_adjacency_keys_fail_the_migration() {
+    let dir = common::fresh_dir("undecodable");
+    let db = EngiDB::open(&dir).unwrap();
+    let (a, b) = two_nodes(&db, Vec::new(), Vec::new());
+    db.flush().unwrap();
+    drop(db);
+
+    write_legacy_adjacency(&dir, &[
+        (format!("{}:next:{}", a, b), ""),
+        ("garbage".to_string(), ""),
+        (format!("{}:next:{}:unknown-edge", a, b), "data"),
+    ]);
+
+    // Open until the lock is free; the failed migration keeps failing.
+    let open = || {
+        for _ in 0..50 {
+            match EngiDB::open(&dir) {
+                Err(Error::Migration(message)) => return message,
+                Err(Error::Io(_)) | Err(Error::Sled(_)) => std::thread::sleep(std::time::Duration::from_millis(100)),
+                other => panic!("expected a failed migration, got {:?}", other.map(|_| ())),
+            }
+        }
+        panic!("database stays locked");
+    };
+    let message = open();
+    assert!(message.contains("2 adjacency keys"), "{}", message);
+    assert_eq!(open(), message);
+}
@@ -0,0 +1,131 @@
+//! Merges update the current graph state as well as the branch, so the next
+//! commit builds on the merge instead of reverting it.
+
+use engidb::diff::diff_graphs;
+use engidb::gc::GcOptions;
+use engidb::merge::MergeStrategy;
+use engidb::{EngiDB, Error};
+use indexmap::IndexMap;
+use kotoba_types::{Edge, Graph, Incidence, Layer, Node};
+use serde_json::json;
+
+mod common;
+
+fn node(id: &str, rank: i64) -> Node {
+    let mut properties = IndexMap::new();
+    properties.insert("rank".to_string(), json!(rank));
+    Node { id: id.to_string(), kind: "Item".to_string(), properties }
+}
+
+fn link(graph: &mut Graph, id: &str, source: &str, target: &str) {
+    graph.edge.push(Edge { id: id.to_string(), layer: Layer::Data, kind: "next".to_string(), properties: IndexMap::new() });
+    for (node, role) in [(source, "source"), (target, "target")] {
+        graph.incidence.push(Incidence {
+            edge: id.to_string(),
+            node: node.to_string(),
+            role: role.to_string(),
+            pos: None,
+            properties: IndexMap::new(),
+        });
+    }
+}
+
+fn current_graph(db: &EngiDB) -> Graph {
+    db.graph_from_snapshot(&db.snapshot().unwrap()).unwrap()
+}
+
+#[test]
+fn commits_after_a_merge_keep_the_merged_graph() {
+    let db = common::fresh_db("three-way");
+    let mut base = Graph { node: vec![node("a", 1), node("b", 2), node("c", 3)], edge: Vec::new(), incidence: Vec::new() };
+    link(&mut base, "ab", "a", "b");
+    link(&mut base, "bc", "b", "c");
+    db.import_graph(&base).unwrap();
+    let base_commit = db.commit("main", "alice".to_string(), "base".to_string()).unwrap();
+    db.create_branch("topic", "main").unwrap();
+
+    // Theirs deletes `c` with its edge and adds `d`.
+    let mut theirs = base.clone();
+    theirs.node.retain(|node| node.id != "c");
+    theirs.node.push(node("d", 4));
+    theirs.edge.retain(|edge| edge.id != "bc");
+    theirs.incidence.retain(|incidence| incidence.edge != "bc");
+    db.commit_graph("topic", &theirs, vec![base_commit], "bob".to_string(), "topic".to_string()).unwrap();
+
+    // Ours changes `a` and drops the edge between `a` and `b`.
+    let mut ours = base.clone();
+    ours.node[0] = node("a", 10);
+    ours.edge.retain(|edge| edge.id != "ab");
+    ours.incidence.retain(|incidence| incidence.edge != "ab");
+    db.commit_graph("main", &ours, vec![base_commit], "alice".to_string(), "ours".to_string()).unwrap();
+
+    let outcome = db.merge("main", "topic", MergeStrategy::Theirs, "alice".to_string(), "merge".to_string()).unwrap();
+    assert!(outcome.conflicts.is_empty());
+    let merge_commit = outcome.commit.unwrap();
+    let mut ids: Vec<_> = outcome.graph.node.iter().map(|node| node.id.as_str()).collect();
+    ids.sort_unstable();
+    assert_eq!(ids, ["a", "b", "d"]);
+    assert!(outcome.graph.edge.is_empty());
+
+    // The live trees hold the merged graph.
+    assert!(diff_graphs(&outcome.graph, &current_graph(&db)).is_empty());
+    assert_eq!(db.entity_vertex("c").unwrap(), None);
+    assert_eq!(db.get_node("a").unwrap(), Some(node("a", 10)));
+    let a = db.entity_vertex("a").unwrap().unwrap();
+    assert!(db.get_edges_from(a, "next").unwrap().is_empty());
+    assert_eq!(db.get_edge("bc").unwrap(), None);
+
+    let next = db.commit("main", "alice".to_string(), "after the merge".to_string()).unwrap();
+    assert_eq!(db.get_commit(&next).unwrap().parents, vec![merge_commit]);
+    assert!(db.diff(&merge_commit.to_string(), "main").unwrap().is_empty());
+}
+
+#[test]
+fn fast_forward_merges_update_the_current_graph() {
+    let db = common::fresh_db("fast-forward");
+    db.add_vertex(&node("a", 1)).unwrap();
+    let base_commit = db.commit("main", "alice".to_string(), "base".to_string()).unwrap();
+    db.create_branch("topic", "main").unwrap();
+    let theirs = Graph { node: vec![node("b", 2)], edge: Vec::new(), incidence: Vec::new() };
+    let topic = db.commit_graph("topic", &theirs, vec![base_commit], "bob".to_string(), "topic".to_string()).unwrap();
+
+    let outcome = db.merge("main", "topic", MergeStrategy::Manual, "alice".to_string(), "merge".to_string()).unwrap();
+    assert!(outcome.fast_forward);
+    assert_eq!(outcome.commit, Some(topic));
+    assert!(diff_graphs(&theirs, &current_graph(&db)).is_empty());
+
+    db.commit("main", "alice".to_string(), "after the merge".to_string()).unwrap();
+    assert!(db.diff(&topic.to_string(), "main").unwrap().is_empty());
+}
+
+#[test]
+fn merges_stop_at_history_pruned_by_gc() {
+    let db = common::fresh_db("pruned");
+    db.add_vertex(&node("a", 1)).unwrap();
+    let base_commit = db.commit("main", "alice".to_string(), "base".to_string()).unwrap();
+    db.create_branch("topic", "main").unwrap();
+    let theirs = Graph { node: vec![node("a", 1), node("t", 2)], edge: Vec::new(), incidence: Vec::new() };
+    db.commit_graph("topic", &theirs, vec![base_commit], "bob".to_string(), "topic".to_string()).unwrap();
+    db.add_vertex(&node("m", 3)).unwrap();
+    db.commit("main", "alice".to_string(), "ours".to_string()).unwrap();
+
+    // Only the heads survive, so the two branches no longer share a commit.
+    let report = db.gc(&GcOptions { retain_commits: Some(1), ..GcOptions::default() }).unwrap();
+    assert_eq!(report.collected_commits, 1);
+    assert!(db.get_commit(&base_commit).is_err());
+
+    let outcome = db.merge("main", "topic", MergeStrategy::Manual, "alice".to_string(), "merge".to_string()).unwrap();
+    assert_eq!(outcome.base, None);
+    assert!(outcome.conflicts.is_empty());
+    let mut ids: Vec<_> = outcome.graph.node.iter().map(|node| node.id.as_str()).collect();
+    ids.sort_unstable();
+    assert_eq!(ids, ["a", "m", "t"]);
+    assert!(diff_graphs(&outcome.graph, &current_graph(&db)).is_empty());
+    assert_eq!(db.log("main").unwrap().len(), 3);
+}
+
+#[test]
+fn unknown_strategies_are_invalid_arguments() {
+    assert!(matches!("ours".parse::<MergeStrategy>(), Ok(MergeStrategy::Ours)));
+    assert!(matches!("recursive".parse::<MergeStrategy>(), Err(Error::InvalidArgument(_))));
+}
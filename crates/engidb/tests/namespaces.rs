@@ -0,0 +1,58 @@
+//! Isolation of the named graphs of one database.
+
+use engidb::scan::NodeFilter;
+use engidb::{EngiDB, Error};
+use indexmap::IndexMap;
+use kotoba_types::{Graph, Node};
+
+mod common;
+
+fn graph(kind: &str, ids: &[&str]) -> Graph {
+    let node = |id: &&str| Node { id: id.to_string(), kind: kind.to_string(), properties: IndexMap::new() };
+    Graph { node: ids.iter().map(node).collect(), edge: Vec::new(), incidence: Vec::new() }
+}
+
+fn kinds(db: &EngiDB) -> Vec<(String, String)> {
+    db.scan_nodes(&NodeFilter::default()).unwrap().nodes.into_iter().map(|n| (n.id, n.kind)).collect()
+}
+
+#[test]
+fn namespaces_are_independent_graphs() {
+    let dir = common::fresh_dir("independent");
+    {
+        let db = EngiDB::open(&dir).unwrap();
+        let (app, ui) = (db.namespace("app").unwrap(), db.namespace("ui").unwrap());
+        db.import_graph(&graph("Todo", &["main"])).unwrap();
+        app.import_graph(&graph("Function", &["main", "helper"])).unwrap();
+        ui.import_graph(&graph("View", &["main"])).unwrap();
+
+        // The same id names a different node, with its own vertex id space, in every graph.
+        assert_eq!(db.entity_vertex("main").unwrap(), Some(1));
+        assert_eq!(app.entity_vertex("main").unwrap(), Some(1));
+        assert_eq!(ui.entity_vertex("main").unwrap(), Some(1));
+        assert_eq!(ui.entity_vertex("helper").unwrap(), None);
+        assert_eq!(kinds(&app), [("helper".to_string(), "Function".to_string()), ("main".to_string(), "Function".to_string())]);
+        assert_eq!(kinds(&ui), [("main".to_string(), "View".to_string())]);
+
+        let commit = app.commit("main", "alice".to_string(), "functions".to_string()).unwrap();
+        assert_eq!(app.list_branches().unwrap(), vec![("main".to_string(), commit)]);
+        assert!(db.list_branches().unwrap().is_empty());
+        assert!(matches!(ui.resolve("main"), Err(Error::NotFound(_))));
+        assert_eq!(app.namespace_name(), Some("app"));
+        assert_eq!(db.namespace_name(), None);
+    }
+
+    let db = common::reopen(|| EngiDB::open(&dir));
+    assert_eq!(db.namespaces().unwrap(), ["app", "ui"]);
+    assert_eq!(kinds(&db), [("main".to_string(), "Todo".to_string())]);
+    let app = db.namespace("ui").unwrap().namespace("app").unwrap();
+    assert_eq!(app.checkout("main").unwrap().node.len(), 2);
+}
+
+#[test]
+fn namespace_names_cannot_be_empty_or_contain_slashes() {
+    let db = EngiDB::open(common::fresh_dir("names")).unwrap();
+    assert!(matches!(db.namespace(""), Err(Error::InvalidArgument(_))));
+    assert!(matches!(db.namespace("a/b"), Err(Error::InvalidArgument(_))));
+    assert!(db.namespaces().unwrap().is_empty());
+}
@@ -0,0 +1,40 @@
+//! Property index lookups on integers too large for an `f64` to tell apart.
+
+use engidb::index::IndexDef;
+use indexmap::IndexMap;
+use kotoba_types::Node;
+use serde_json::json;
+
+mod common;
+
+fn by_number() -> IndexDef {
+    IndexDef { name: "by_number".to_string(), kind: "Account".to_string(), properties: vec!["number".to_string()] }
+}
+
+fn account(id: &str, number: serde_json::Value) -> Node {
+    let mut properties = IndexMap::new();
+    properties.insert("number".to_string(), number);
+    Node { id: id.to_string(), kind: "Account".to_string(), properties }
+}
+
+fn ids(nodes: Vec<Node>) -> Vec<String> {
+    nodes.into_iter().map(|node| node.id).collect()
+}
+
+#[test]
+fn large_integers_have_entries_of_their_own() {
+    let db = common::fresh_db("large");
+    let base = 1u64 << 53;
+    for (id, number) in [("a", base), ("b", base + 1), ("c", base + 2), ("d", u64::MAX)] {
+        db.add_vertex(&account(id, json!(number))).unwrap();
+    }
+    db.add_vertex(&account("e", json!(i64::MIN))).unwrap();
+    db.create_index(by_number()).unwrap();
+
+    assert_eq!(ids(db.lookup_index("by_number", &[json!(base + 1)]).unwrap()), ["b"]);
+    assert_eq!(ids(db.lookup_index("by_number", &[json!(u64::MAX - 1)]).unwrap()), Vec::<String>::new());
+    assert_eq!(ids(db.range_index("by_number", &[], json!(base + 1)..).unwrap()), ["b", "c", "d"]);
+    assert_eq!(ids(db.range_index("by_number", &[], ..json!(base + 1)).unwrap()), ["e", "a"]);
+    // Floats compare with integers by value.
+    assert_eq!(ids(db.range_index("by_number", &[], json!(-1.5)..=json!(base as f64)).unwrap()), ["a"]);
+}
@@ -0,0 +1,151 @@
+//! Edge cases of `EngiDB::scan_nodes`: unknown kinds, cursors that name no
+//! node, kind changes and deletions, predicates across types and blocks that
+//! went missing under the kind index.
+
+use engidb::scan::{NodeFilter, Predicate};
+use engidb::transact::TxOp;
+use engidb::{EngiDB, Error};
+use indexmap::IndexMap;
+use kotoba_types::Node;
+use serde_json::json;
+
+mod common;
+
+fn node(id: &str, kind: &str, properties: &[(&str, serde_json::Value)]) -> Node {
+    let properties = properties.iter().map(|(k, v)| (k.to_string(), v.clone())).collect::<IndexMap<_, _>>();
+    Node { id: id.to_string(), kind: kind.to_string(), properties }
+}
+
+fn of_kind(kind: &str) -> NodeFilter {
+    NodeFilter { kind: Some(kind.to_string()), ..NodeFilter::default() }
+}
+
+fn ids(db: &EngiDB, filter: &NodeFilter) -> (Vec<String>, Option<String>) {
+    let page = db.scan_nodes(filter).unwrap();
+    (page.nodes.into_iter().map(|node| node.id).collect(), page.next)
+}
+
+fn items(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn empty_databases_and_unknown_kinds_scan_empty() {
+    let db = common::fresh_db("empty");
+    assert_eq!(ids(&db, &NodeFilter::default()), (Vec::new(), None));
+    db.add_vertex(&node("a", "Item", &[])).unwrap();
+
+    assert_eq!(ids(&db, &of_kind("Missing")), (Vec::new(), None));
+    // Kinds are whole key components, so a kind never matches another it starts.
+    assert_eq!(ids(&db, &of_kind("Ite")), (Vec::new(), None));
+    assert_eq!(ids(&db, &of_kind("")), (Vec::new(), None));
+}
+
+#[test]
+fn cursors_need_not_name_a_stored_node() {
+    let db = common::fresh_db("cursor");
+    for id in ["a", "c", "e"] {
+        db.add_vertex(&node(id, "Item", &[])).unwrap();
+    }
+    for filter in [NodeFilter::default(), of_kind("Item")] {
+        let after = |after: &str| NodeFilter { after: Some(after.to_string()), ..filter.clone() };
+        assert_eq!(ids(&db, &after("b")), (items(&["c", "e"]), None));
+        assert_eq!(ids(&db, &after("")), (items(&["a", "c", "e"]), None));
+        assert_eq!(ids(&db, &after("e")), (Vec::new(), None));
+        assert_eq!(ids(&db, &after("z")), (Vec::new(), None));
+        // A zero limit reads nothing and offers no cursor to continue from.
+        assert_eq!(ids(&db, &NodeFilter { limit: Some(0), ..filter.clone() }), (Vec::new(), None));
+        // The page after a full last page is empty.
+        let full = NodeFilter { limit: Some(3), ..filter.clone() };
+        assert_eq!(ids(&db, &full), (items(&["a", "c", "e"]), Some("e".to_string())));
+        assert_eq!(ids(&db, &NodeFilter { after: Some("e".to_string()), ..full }), (Vec::new(), None));
+    }
+}
+
+#[test]
+fn kind_scans_page_in_id_order_whatever_the_id_length() {
+    let db = common::fresh_db("id-order");
+    for id in ["b", "aa", "a", "ab"] {
+        db.add_vertex(&node(id, "Item", &[])).unwrap();
+    }
+    for filter in [NodeFilter::default(), of_kind("Item")] {
+        let mut seen = Vec::new();
+        let mut page = NodeFilter { limit: Some(1), ..filter };
+        loop {
+            let (ids, next) = ids(&db, &page);
+            seen.extend(ids);
+            match next {
+                Some(next) => page.after = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(seen, items(&["a", "aa", "ab", "b"]));
+    }
+}
+
+#[test]
+fn changed_and_retracted_nodes_leave_their_kind() {
+    let db = common::fresh_db("kinds");
+    db.add_vertex(&node("a", "Item", &[])).unwrap();
+    db.add_vertex(&node("b", "Item", &[])).unwrap();
+
+    db.transact(&[TxOp::Assert { entity: "a".to_string(), attribute: "@type".to_string(), value: json!("Done") }])
+        .unwrap();
+    assert_eq!(ids(&db, &of_kind("Item")), (items(&["b"]), None));
+    assert_eq!(ids(&db, &of_kind("Done")), (items(&["a"]), None));
+
+    db.transact(&[TxOp::RetractEntity { entity: "b".to_string() }]).unwrap();
+    assert_eq!(ids(&db, &of_kind("Item")), (Vec::new(), None));
+    assert_eq!(ids(&db, &NodeFilter::default()), (items(&["a"]), None));
+}
+
+#[test]
+fn predicates_compare_only_values_of_one_type() {
+    let db = common::fresh_db("predicates");
+    let big = 1u64 << 60;
+    db.add_vertex(&node("number", "Item", &[("rank", json!(2))])).unwrap();
+    db.add_vertex(&node("string", "Item", &[("rank", json!("2"))])).unwrap();
+    db.add_vertex(&node("null", "Item", &[("rank", json!(null))])).unwrap();
+    db.add_vertex(&node("missing", "Item", &[])).unwrap();
+    db.add_vertex(&node("big", "Item", &[("rank", json!(big + 1))])).unwrap();
+    let rank = |predicate: Predicate| {
+        let filter = NodeFilter { predicates: vec![("rank".to_string(), predicate)], ..of_kind("Item") };
+        ids(&db, &filter).0
+    };
+
+    assert_eq!(rank(Predicate::Eq(json!(2))), items(&["number"]));
+    assert_eq!(rank(Predicate::Eq(json!(2.0))), items(&["number"]));
+    assert_eq!(rank(Predicate::Gt(json!(1))), items(&["big", "number"]));
+    assert_eq!(rank(Predicate::Lt(json!("3"))), items(&["string"]));
+    // A missing property compares as null but does not exist.
+    assert_eq!(rank(Predicate::Eq(json!(null))), items(&["missing", "null"]));
+    assert_eq!(rank(Predicate::Exists), items(&["big", "null", "number", "string"]));
+    assert_eq!(rank(Predicate::Ne(json!(2))), items(&["big", "missing", "null", "string"]));
+    // Integers beyond 2^53 compare exactly.
+    assert_eq!(rank(Predicate::Eq(json!(big))), Vec::<String>::new());
+    assert_eq!(rank(Predicate::Gt(json!(big))), items(&["big"]));
+    assert_eq!(rank(Predicate::Ge(json!(big + 2))), Vec::<String>::new());
+}
+
+#[test]
+fn nodes_whose_block_is_missing_fail_the_scan() {
+    let dir = common::fresh_dir("missing-block");
+    let db = EngiDB::open(&dir).unwrap();
+    db.add_vertex(&node("a", "Item", &[])).unwrap();
+    db.add_vertex(&node("b", "Item", &[])).unwrap();
+    let cid = db.entity_record("b").unwrap().unwrap().cid.unwrap();
+    db.flush().unwrap();
+    drop(db);
+
+    let raw = common::reopen(|| sled::open(&dir));
+    raw.open_tree("ipld_blocks").unwrap().remove(cid.to_bytes()).unwrap();
+    raw.flush().unwrap();
+    drop(raw);
+
+    let db = common::reopen(|| EngiDB::open(&dir));
+    for filter in [NodeFilter::default(), of_kind("Item")] {
+        assert!(matches!(db.scan_nodes(&filter), Err(Error::NotFound(_))));
+        // Pages that end before the node still read.
+        assert_eq!(ids(&db, &NodeFilter { limit: Some(1), ..filter }), (items(&["a"]), Some("a".to_string())));
+    }
+}
@@ -0,0 +1,65 @@
+//! The bitemporal fact log: transactions are logged together with their
+//! datoms, and the attribute recording a node's kind cannot be written as a
+//! property.
+
+use engidb::temporal::KIND_ATTRIBUTE;
+use engidb::transact::TxOp;
+use engidb::{EngiDB, Error};
+use indexmap::IndexMap;
+use kotoba_types::{Graph, Node};
+use serde_json::json;
+use std::collections::BTreeSet;
+
+mod common;
+
+fn node(id: &str, properties: &[(&str, serde_json::Value)]) -> Node {
+    let properties = properties.iter().map(|(k, v)| (k.to_string(), v.clone())).collect::<IndexMap<_, _>>();
+    Node { id: id.to_string(), kind: "Item".to_string(), properties }
+}
+
+/// Transaction ids of the log and of the recorded datoms.
+fn logged_and_used(db: &EngiDB, entities: &[&str]) -> (BTreeSet<u64>, BTreeSet<u64>) {
+    let logged = db.transactions().unwrap().into_iter().map(|record| record.tx).collect();
+    let mut used = BTreeSet::new();
+    for entity in entities {
+        used.extend(db.view().history(entity).unwrap().into_iter().map(|datom| datom.tx));
+    }
+    (logged, used)
+}
+
+#[test]
+fn every_logged_transaction_carries_its_datoms() {
+    let db = common::fresh_db("log");
+    db.record_node(&node("a", &[("rank", json!(1))]), Some(10)).unwrap();
+    // Nothing changes, so nothing is logged.
+    assert_eq!(db.record_node(&node("a", &[("rank", json!(1))]), Some(20)).unwrap(), None);
+    db.record_node(&node("a", &[("rank", json!(2))]), Some(30)).unwrap();
+    db.retract_entity("a", Some(40)).unwrap();
+    assert_eq!(db.retract_entity("a", Some(50)).unwrap(), None);
+
+    let (logged, used) = logged_and_used(&db, &["a"]);
+    assert_eq!(logged.len(), 3);
+    assert_eq!(logged, used);
+}
+
+#[test]
+fn the_kind_attribute_is_not_a_property() {
+    let db = common::fresh_db("kind");
+    let clash = node("a", &[(KIND_ATTRIBUTE, json!("Other"))]);
+    assert!(matches!(db.record_node(&clash, None), Err(Error::InvalidArgument(_))));
+    assert!(matches!(db.add_vertex(&clash), Err(Error::InvalidArgument(_))));
+    let graph = Graph { node: vec![node("b", &[]), clash], edge: Vec::new(), incidence: Vec::new() };
+    assert!(matches!(db.import_graph(&graph), Err(Error::InvalidArgument(_))));
+
+    assert!(db.transactions().unwrap().is_empty());
+    assert_eq!(db.get_node("a").unwrap(), None);
+    assert_eq!(db.get_node("b").unwrap(), None);
+
+    // Asserting the attribute sets the kind instead.
+    db.transact(&[TxOp::Assert { entity: "a".to_string(), attribute: KIND_ATTRIBUTE.to_string(), value: json!("Other") }])
+        .unwrap();
+    let a = db.get_node("a").unwrap().unwrap();
+    assert_eq!(a.kind, "Other");
+    assert!(a.properties.is_empty());
+    assert_eq!(db.view().entity("a").unwrap(), Some(a));
+}
@@ -0,0 +1,110 @@
+//! `EngiDB::transact` on entities with incident edges and under concurrent
+//! writers.
+
+use engidb::transact::TxOp;
+use engidb::Error;
+use indexmap::IndexMap;
+use kotoba_types::{Edge, Graph, Incidence, Layer, Node};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+mod common;
+
+fn item(id: &str) -> Node {
+    Node { id: id.to_string(), kind: "Item".to_string(), properties: IndexMap::new() }
+}
+
+/// Adds an edge with one incidence per `(node, role)`.
+fn edge(graph: &mut Graph, id: &str, incidences: &[(&str, &str)]) {
+    graph.edge.push(Edge { id: id.to_string(), layer: Layer::Data, kind: "link".to_string(), properties: IndexMap::new() });
+    for (node, role) in incidences {
+        graph.incidence.push(Incidence {
+            edge: id.to_string(),
+            node: node.to_string(),
+            role: role.to_string(),
+            pos: None,
+            properties: IndexMap::new(),
+        });
+    }
+}
+
+fn assert_op(entity: &str, attribute: &str, value: serde_json::Value) -> TxOp {
+    TxOp::Assert { entity: entity.to_string(), attribute: attribute.to_string(), value }
+}
+
+#[test]
+fn retracting_an_entity_removes_its_incident_edges() {
+    let db = common::fresh_db("retract");
+    let mut graph = Graph { node: vec![item("a"), item("b"), item("c")], edge: Vec::new(), incidence: Vec::new() };
+    edge(&mut graph, "ab", &[("a", "source"), ("b", "target")]);
+    edge(&mut graph, "bc", &[("b", "source"), ("c", "target")]);
+    edge(&mut graph, "call", &[("a", "arg"), ("b", "arg"), ("c", "arg")]);
+    edge(&mut graph, "ca", &[("c", "source"), ("a", "target")]);
+    db.import_graph(&graph).unwrap();
+    let (a, c) = (db.entity_vertex("a").unwrap().unwrap(), db.entity_vertex("c").unwrap().unwrap());
+
+    db.transact(&[TxOp::RetractEntity { entity: "b".to_string() }]).unwrap();
+
+    assert_eq!(db.get_node("b").unwrap(), None);
+    for id in ["ab", "bc", "call"] {
+        assert_eq!(db.get_edge(id).unwrap(), None, "edge {}", id);
+        assert!(db.get_incidences(id).unwrap().is_empty(), "incidences of {}", id);
+    }
+    assert!(db.get_edges_from(a, "link").unwrap().is_empty());
+    assert_eq!(db.get_edges_from(c, "link").unwrap(), vec![a]);
+    assert_eq!(db.get_incidences("ca").unwrap().len(), 2);
+
+    // Retracting the other end of the remaining edge removes it too.
+    db.transact(&[TxOp::RetractEntity { entity: "a".to_string() }]).unwrap();
+    assert_eq!(db.get_edge("ca").unwrap(), None);
+    assert!(db.get_edges_from(c, "link").unwrap().is_empty());
+}
+
+#[test]
+fn retracting_an_unknown_entity_is_not_found() {
+    let db = common::fresh_db("unknown");
+    assert!(matches!(db.transact(&[TxOp::RetractEntity { entity: "a".to_string() }]), Err(Error::NotFound(_))));
+    assert!(db.transactions().unwrap().is_empty());
+}
+
+#[test]
+fn concurrent_changes_to_an_entity_reject_the_transaction() {
+    let db = common::fresh_db("concurrent");
+    db.transact(&[assert_op("a", "@type", json!("Item")), assert_op("a", "rank", json!(0))]).unwrap();
+
+    // A large batch loads `a` first and writes it last, which leaves time
+    // for the other writer to change it in between.
+    let batch = |round: usize| {
+        let mut ops = vec![assert_op("a", "rank", json!(-1))];
+        ops.extend((0..2_000).map(|i| assert_op(&format!("item_{}_{}", round, i), "@type", json!("Item"))));
+        ops
+    };
+
+    let mut rejected = None;
+    for round in 0..20 {
+        let done = AtomicBool::new(false);
+        let result = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut rank = 1;
+                while !done.load(Ordering::Relaxed) {
+                    db.transact(&[assert_op("a", "rank", json!(rank))]).unwrap();
+                    rank += 1;
+                    std::thread::yield_now();
+                }
+            });
+            let result = db.transact(&batch(round));
+            done.store(true, Ordering::Relaxed);
+            result
+        });
+        if let Err(error) = result {
+            rejected = Some((round, error));
+            break;
+        }
+    }
+
+    let (round, error) = rejected.expect("the large batch overlaps a concurrent change at least once");
+    assert!(matches!(&error, Error::Transaction(message) if message.contains("modified concurrently")), "{:?}", error);
+    // Nothing of the rejected batch was written.
+    assert_eq!(db.get_node(&format!("item_{}_0", round)).unwrap(), None);
+    assert_eq!(db.get_node(&format!("item_{}_1999", round)).unwrap(), None);
+}
@@ -0,0 +1,127 @@
+//! Problems `EngiDB::verify` reports: corrupt and missing blocks, commits
+//! that do not decode, lost parents, dangling edges and index entries that
+//! disagree.
+
+use cid::Cid;
+use engidb::verify::{Problem, VerifyReport};
+use engidb::{Commit, EngiDB, Transaction};
+use indexmap::IndexMap;
+use kotoba_types::Node;
+use sha2::{Digest, Sha256};
+
+mod common;
+
+/// The CID of a DAG-CBOR block that is never stored.
+fn missing(seed: &str) -> Cid {
+    let hash = multihash::Multihash::<64>::wrap(0x12, &Sha256::digest(seed.as_bytes())).unwrap();
+    Cid::new_v1(0x71, hash)
+}
+
+fn item(id: &str) -> Node {
+    Node { id: id.to_string(), kind: "Item".to_string(), properties: IndexMap::new() }
+}
+
+fn commit_on(db: &EngiDB, transaction_cid: Cid, parents: Vec<Cid>) -> Cid {
+    db.put_dag(&Commit { transaction_cid, parents, author: "alice".to_string(), message: "broken".to_string() }).unwrap()
+}
+
+#[test]
+fn empty_and_consistent_databases_verify_clean() {
+    let db = common::fresh_db("clean");
+    assert_eq!(db.verify().unwrap(), VerifyReport::default());
+
+    let (a, b) = (db.add_vertex(&item("a")).unwrap(), db.add_vertex(&item("b")).unwrap());
+    db.add_edge(a, "next", b).unwrap();
+    db.commit("main", "alice".to_string(), "first".to_string()).unwrap();
+    db.commit("main", "alice".to_string(), "second".to_string()).unwrap();
+    let report = db.verify().unwrap();
+    assert!(report.is_ok(), "{:?}", report.problems);
+    assert_eq!((report.vertices, report.edges, report.commits), (2, 1, 2));
+}
+
+#[test]
+fn blocks_that_do_not_match_their_cid_are_corrupt() {
+    let db = common::fresh_db("corrupt");
+    let cid = db.put_dag(&item("a")).unwrap();
+    db.put_block(&cid, b"something else").unwrap();
+    let actual = db.put_dag(&item("b")).unwrap();
+    db.put_block(&actual, b"something else").unwrap();
+
+    let report = db.verify().unwrap();
+    assert_eq!(report.blocks, 2);
+    let corrupt: Vec<_> = report.problems.iter().filter(|p| matches!(p, Problem::CorruptBlock { .. })).collect();
+    assert_eq!(corrupt.len(), 2);
+    // Both blocks hold the same bytes, so they hash alike.
+    assert!(matches!(corrupt[..], [Problem::CorruptBlock { actual: x, .. }, Problem::CorruptBlock { actual: y, .. }] if x == y));
+}
+
+#[test]
+fn unpruned_parents_and_unreadable_commit_blocks_are_reported() {
+    let db = common::fresh_db("commits");
+    let parent = missing("parent");
+    let transaction = db.put_dag(&Transaction { timestamp: 0, root: None }).unwrap();
+    let lost_parent = commit_on(&db, transaction, vec![parent]);
+    db.create_branch("lost-parent", &lost_parent.to_string()).unwrap();
+
+    // A commit whose transaction is a node block, and one whose snapshot is missing.
+    let not_a_transaction = commit_on(&db, db.put_dag(&item("a")).unwrap(), Vec::new());
+    db.create_branch("not-a-transaction", &not_a_transaction.to_string()).unwrap();
+    let root = missing("snapshot");
+    let no_snapshot = commit_on(&db, db.put_dag(&Transaction { timestamp: 0, root: Some(root) }).unwrap(), Vec::new());
+    db.create_branch("no-snapshot", &no_snapshot.to_string()).unwrap();
+
+    let problems = db.verify().unwrap().problems;
+    assert_eq!(problems.len(), 3, "{:?}", problems);
+    assert!(problems.contains(&Problem::MissingBlock { cid: parent, referenced_by: format!("commit {}", lost_parent) }));
+    assert!(problems.iter().any(|p| matches!(p, Problem::UndecodableBlock { expected: "transaction", .. })));
+    assert!(problems.contains(&Problem::MissingBlock { cid: root, referenced_by: format!("commit {}", no_snapshot) }));
+}
+
+#[test]
+fn edges_to_unknown_vertices_dangle() {
+    let db = common::fresh_db("dangling");
+    let a = db.add_vertex(&item("a")).unwrap();
+    db.add_edge(a, "next", a + 100).unwrap();
+
+    let problems = db.verify().unwrap().problems;
+    assert_eq!(problems.len(), 1, "{:?}", problems);
+    assert!(matches!(&problems[0], Problem::DanglingEdge { entry, missing } if entry.source == a && missing.contains("target")));
+}
+
+#[test]
+fn lost_blocks_and_disagreeing_indexes_are_reported_without_writing() {
+    let dir = common::fresh_dir("raw");
+    let db = EngiDB::open(&dir).unwrap();
+    let (a, b) = (db.add_vertex(&item("a")).unwrap(), db.add_vertex(&item("b")).unwrap());
+    db.add_edge(a, "next", b).unwrap();
+    let head = db.commit("main", "alice".to_string(), "first".to_string()).unwrap();
+    let block = db.entity_record("a").unwrap().unwrap().cid.unwrap();
+    let transaction = db.get_commit(&head).unwrap().transaction_cid;
+    db.flush().unwrap();
+    drop(db);
+
+    let raw = common::reopen(|| sled::open(&dir));
+    for cid in [block, transaction] {
+        raw.open_tree("ipld_blocks").unwrap().remove(cid.to_bytes()).unwrap();
+    }
+    raw.open_tree("edges_in").unwrap().clear().unwrap();
+    raw.open_tree("edge_kinds").unwrap().insert(b"garbage", &[]).unwrap();
+    raw.open_tree("edges").unwrap().insert(b"garbage", &[]).unwrap();
+    raw.flush().unwrap();
+    drop(raw);
+
+    let db = common::reopen(|| EngiDB::open(&dir));
+    let report = db.verify().unwrap();
+    let problems = &report.problems;
+    assert!(problems.contains(&Problem::MissingBlock { cid: block, referenced_by: format!("vertex {}", a) }));
+    assert!(problems.contains(&Problem::MissingBlock { cid: transaction, referenced_by: format!("commit {}", head) }));
+    assert!(problems.contains(&Problem::Malformed { tree: "edges", key: b"garbage".to_vec() }));
+    let mismatches: Vec<_> = problems.iter().filter_map(|p| match p {
+        Problem::IndexMismatch(detail) => Some(detail.as_str()),
+        _ => None,
+    }).collect();
+    assert!(mismatches.iter().any(|detail| detail.contains("missing from edges_in")), "{:?}", mismatches);
+    assert!(mismatches.iter().any(|detail| detail.contains("have no outgoing entry")), "{:?}", mismatches);
+    // Verifying again finds the same, so nothing was repaired or removed.
+    assert_eq!(db.verify().unwrap(), report);
+}
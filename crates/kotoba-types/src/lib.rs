@@ -7,7 +7,7 @@ use indexmap::IndexMap;
 use std::collections::HashMap;
 
 /// Layer types in the EAF-IPG model
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Layer {
     Syntax,   // Ordered AST representation
@@ -34,19 +34,33 @@ impl Layer {
             _ => None,
         }
     }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Syntax => "syntax",
+            Self::Data => "data",
+            Self::Control => "control",
+            Self::Memory => "memory",
+            Self::Typing => "typing",
+            Self::Effect => "effect",
+            Self::Time => "time",
+            Self::Capability => "capability",
+        }
+    }
 }
 
 /// Node in the EAF-IPG graph
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Node {
     pub id: String,
     #[serde(rename = "type")]
     pub kind: String,
+    #[serde(default)]
     pub properties: IndexMap<String, serde_json::Value>,
 }
 
 /// Edge in the EAF-IPG graph
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Edge {
     pub id: String,
     pub layer: Layer,
@@ -65,6 +79,7 @@ impl<'de> Deserialize<'de> for Edge {
             layer: String,
             #[serde(rename = "type")]
             kind: String,
+            #[serde(default)]
             properties: IndexMap<String, serde_json::Value>,
         }
 
@@ -90,7 +105,7 @@ impl Serialize for Edge {
 
         let mut state = serializer.serialize_struct("Edge", 4)?;
         state.serialize_field("id", &self.id)?;
-        state.serialize_field("layer", &serde_json::to_string(&self.layer).map_err(serde::ser::Error::custom)?)?;
+        state.serialize_field("layer", &self.layer)?;
         state.serialize_field("type", &self.kind)?;
         state.serialize_field("properties", &self.properties)?;
         state.end()
@@ -98,7 +113,7 @@ impl Serialize for Edge {
 }
 
 /// Incidence relationship between nodes and edges
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Incidence {
     pub node: String,
     pub edge: String,
@@ -106,11 +121,12 @@ pub struct Incidence {
     pub role: String, // "source", "target", "cap_in", "cap_out", etc.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pos: Option<usize>, // Position for ordered arguments
+    #[serde(default)]
     pub properties: IndexMap<String, serde_json::Value>,
 }
 
 /// Complete EAF-IPG graph
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Graph {
     pub node: Vec<Node>,
     pub edge: Vec<Edge>,
@@ -277,11 +293,16 @@ impl UiNodeType {
 pub struct UiProperties {
     pub node_type: UiNodeType,
     pub html_tag: Option<String>,
+    #[serde(default)]
     pub tailwind_classes: Vec<String>,
+    #[serde(default)]
     pub htmx_attrs: IndexMap<String, String>,
     pub content: Option<String>,
+    #[serde(default)]
     pub children: Vec<String>, // child node IDs
+    #[serde(default)]
     pub attributes: IndexMap<String, String>,
+    #[serde(default)]
     pub bindings: IndexMap<String, String>, // state bindings
     pub route_path: Option<String>,
     pub style_value: Option<String>,
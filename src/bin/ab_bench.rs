@@ -3,7 +3,7 @@ use std::sync::Arc;
 use std::thread;
 use serde::Serialize;
 use eaf_ipg_runtime::engidb::adapter::GraphAdapter;
-use eaf_ipg_runtime::engidb::adapter::SledAdapter;
+use eaf_ipg_runtime::engidb::adapter::{InMemoryAdapter, SledAdapter};
 use eaf_ipg_runtime::engidb::EngiDB;
 #[cfg(feature = "fcdb")] use eaf_ipg_runtime::engidb::adapter::fcdb_adapter::FcdbAdapter;
 use kotoba_types::{Graph, Node};
@@ -13,6 +13,7 @@ struct Stat { p50_ms: f64, p95_ms: f64 }
 
 #[derive(Serialize)]
 struct Report {
+    backend: String,
     scenario: String,
     threads: usize,
     cold: bool,
@@ -51,23 +52,61 @@ fn make_line_graph(n: u64) -> Graph {
     Graph { node: nodes, edge: Vec::new(), incidence: Vec::new() }
 }
 
+type Adapter = Arc<dyn GraphAdapter + Send + Sync>;
+
+/// Names of the backends this build can benchmark.
+fn available_backends() -> Vec<&'static str> {
+    let mut backends = vec!["sled", "memory"];
+    if cfg!(feature = "fcdb") { backends.insert(1, "fcdb"); }
+    backends
+}
+
+/// Directory a persistent backend keeps its data in; `None` for the in-memory one.
+fn backend_dir(backend: &str, db_path: &str) -> Option<String> {
+    match backend {
+        "sled" => Some(db_path.to_string()),
+        "fcdb" => Some(format!("{}_fcdb", db_path)),
+        _ => None,
+    }
+}
+
+fn open_adapter(backend: &str, dir: Option<&str>) -> Adapter {
+    match (backend, dir) {
+        ("sled", Some(dir)) => Arc::new(SledAdapter::new(EngiDB::open(dir).expect("open db"))),
+        #[cfg(feature = "fcdb")]
+        ("fcdb", Some(dir)) => Arc::new(FcdbAdapter::new_sync(std::path::PathBuf::from(dir)).expect("open fcdb store")),
+        ("memory", _) => Arc::new(InMemoryAdapter::new()),
+        _ => panic!("unknown backend '{}'; available: {}", backend, available_backends().join(", ")),
+    }
+}
+
 fn main() {
     let args: Vec<String> = std::env::args().collect();
     let threads: usize = args.iter().position(|a| a=="--threads").and_then(|i| args.get(i+1)).and_then(|s| s.parse().ok()).unwrap_or(8);
     let cold = args.iter().any(|a| a=="--cold");
     let iters: usize = args.iter().position(|a| a=="--iters").and_then(|i| args.get(i+1)).and_then(|s| s.parse().ok()).unwrap_or(200);
     let db_path = args.iter().position(|a| a=="--db").and_then(|i| args.get(i+1)).map(|s| s.as_str()).unwrap_or("./bench_db");
+    // --backend NAME benchmarks one backend; by default every available backend is reported
+    let backends = match args.iter().position(|a| a=="--backend").and_then(|i| args.get(i+1)) {
+        Some(name) => vec![name.as_str()],
+        None => available_backends(),
+    };
+
+    let mut reports = Vec::new();
+    for backend in backends {
+        let dir = backend_dir(backend, db_path);
+        if cold { if let Some(dir) = &dir { std::fs::remove_dir_all(dir).ok(); } }
+        let adapter = open_adapter(backend, dir.as_deref());
+        reports.extend(run_scenarios(backend, &adapter, threads, iters, cold));
+    }
 
-    if cold { std::fs::remove_dir_all(db_path).ok(); }
+    let out = serde_json::to_string_pretty(&reports).unwrap();
+    println!("{}", out);
+}
 
-    #[cfg(feature = "fcdb")]
-    let adapter: Arc<dyn GraphAdapter + Send + Sync> = Arc::new(
-        FcdbAdapter::new_sync(std::path::PathBuf::from(db_path)).expect("create fcdb adapter")
-    );
-    #[cfg(not(feature = "fcdb"))]
-    let adapter: Arc<dyn GraphAdapter + Send + Sync> = {
-        let sled = EngiDB::open(db_path).expect("open db");
-        Arc::new(SledAdapter::new(sled))
+fn run_scenarios(backend: &str, adapter: &Adapter, threads: usize, iters: usize, cold: bool) -> Vec<Report> {
+    let report = |scenario: &str, iters: usize, stat: Stat| {
+        Report { backend: backend.into(), scenario: scenario.into(), threads, cold, iters, stat }
     };
 
     // Point lookup benchmark: add N nodes, then repeatedly add/get edges around one node
@@ -76,60 +115,57 @@ fn main() {
     let start_id = 1u64;
 
     let rep1 = {
-        let a = Arc::clone(&adapter);
+        let a = Arc::clone(adapter);
         let stat = bench_parallel(threads, iters, move || {
             // simulate adjacency fanout from start_id via a synthetic edge label
             let _ = a.get_edges_from(start_id, "next").ok();
         });
-        Report { scenario: "point_lookup".into(), threads, cold, iters, stat }
+        report("point_lookup", iters, stat)
     };
 
     // Fanout benchmark: create star edges from center
     for t in 2..(2+1_000u64) { let _ = adapter.add_edge(start_id, "next", t); }
     let rep2 = {
-        let a = Arc::clone(&adapter);
+        let a = Arc::clone(adapter);
         let stat = bench_parallel(threads, iters, move || { let _ = a.get_edges_from(start_id, "next").ok(); });
-        Report { scenario: "fanout_adjacent".into(), threads, cold, iters, stat }
+        report("fanout_adjacent", iters, stat)
     };
 
     // N-hop traversal approximation: do chained get_edges_from on synthetic chain
     for i in 1..5_000u64 { let _ = adapter.add_edge(i, "chain", i+1); }
-    let hop = |a: &Arc<dyn GraphAdapter + Send + Sync>, mut v: u64, k: usize| {
+    let hop = |a: &Adapter, mut v: u64, k: usize| {
         for _ in 0..k { if let Ok(ns) = a.get_edges_from(v, "chain") { if let Some(n1) = ns.first() { v = *n1; } } }
     };
     let rep3 = {
-        let a = Arc::clone(&adapter);
+        let a = Arc::clone(adapter);
         let stat = bench_parallel(threads, iters, move || { hop(&a, 1, 2); });
-        Report { scenario: "hop_2".into(), threads, cold, iters, stat }
+        report("hop_2", iters, stat)
     };
     let rep4 = {
-        let a = Arc::clone(&adapter);
+        let a = Arc::clone(adapter);
         let stat = bench_parallel(threads, iters, move || { hop(&a, 1, 3); });
-        Report { scenario: "hop_3".into(), threads, cold, iters, stat }
+        report("hop_3", iters, stat)
     };
     let rep5 = {
-        let a = Arc::clone(&adapter);
+        let a = Arc::clone(adapter);
         let stat = bench_parallel(threads, iters, move || { hop(&a, 1, 4); });
-        Report { scenario: "hop_4".into(), threads, cold, iters, stat }
+        report("hop_4", iters, stat)
     };
 
     // Write burst with and without batch (sled implicit). Simulate by inserting edges.
-    let write_burst = |a: &Arc<dyn GraphAdapter + Send + Sync>, n: u64| {
+    let write_burst = |a: &Adapter, n: u64| {
         for i in 0..n { let _ = a.add_edge(10_000+i, "wb", 20_000+i); }
     };
     let rep6 = {
-        let a = Arc::clone(&adapter);
+        let a = Arc::clone(adapter);
         let stat = bench_parallel(threads, 1, move || { write_burst(&a, 10_000); });
-        Report { scenario: "write_burst_10k".into(), threads, cold, iters: 1, stat }
+        report("write_burst_10k", 1, stat)
     };
     let rep7 = {
-        let a = Arc::clone(&adapter);
+        let a = Arc::clone(adapter);
         let stat = bench_parallel(threads, 1, move || { write_burst(&a, 100_000); });
-        Report { scenario: "write_burst_100k".into(), threads, cold, iters: 1, stat }
+        report("write_burst_100k", 1, stat)
     };
 
-    let out = serde_json::to_string_pretty(&vec![rep1, rep2, rep3, rep4, rep5, rep6, rep7]).unwrap();
-    println!("{}", out);
+    vec![rep1, rep2, rep3, rep4, rep5, rep6, rep7]
 }
-
-
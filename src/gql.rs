@@ -1,12 +1,22 @@
 //! Graph Query Language (GQL) Implementation for Kotoba
 //!
 //! ISO GQL compliant graph query language for complex data retrieval
-//! from EngiDB graph database.
-
-use crate::{engidb::EngiDB, Error, Result};
-use kotoba_types::{Node, Edge, Incidence};
-use std::collections::{HashMap, HashSet};
+//! from any `GraphAdapter` backend, EngiDB by default.
+
+use crate::{Error, Result};
+use crate::engidb::adapter::GraphAdapter;
+use crate::engidb::adjacency::{Adjacency, Direction};
+use crate::engidb::scan::NodeFilter;
+use kotoba_types::Node;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+mod parser;
+
+pub use parser::parse_query;
 
 /// GQL Query AST
 #[derive(Debug, Clone, PartialEq)]
@@ -21,12 +31,23 @@ pub enum GqlExpr {
     Bool(bool),
     /// Property access (node.property)
     Property(Box<GqlExpr>, String),
+    /// Unary operations
+    UnaryOp(UnaryOp, Box<GqlExpr>),
     /// Binary operations
     BinaryOp(Box<GqlExpr>, BinaryOp, Box<GqlExpr>),
     /// Function call
     FunctionCall(String, Vec<GqlExpr>),
 }
 
+/// Unary operators
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnaryOp {
+    /// `-x`
+    Neg,
+    /// `NOT x`
+    Not,
+}
+
 /// Binary operators
 #[derive(Debug, Clone, PartialEq)]
 pub enum BinaryOp {
@@ -48,6 +69,8 @@ pub enum GqlStatement {
     OptionalMatch(Vec<MatchPattern>),
     /// ORDER BY clause
     OrderBy(Vec<OrderBy>),
+    /// SKIP clause
+    Skip(usize),
     /// LIMIT clause
     Limit(usize),
 }
@@ -114,378 +137,665 @@ pub struct GqlResult {
     pub rows: Vec<HashMap<String, serde_json::Value>>,
 }
 
-/// GQL Parser and Interpreter
-pub struct GqlEngine {
-    pub engidb: EngiDB,
+impl GqlExpr {
+    /// Binding strength when printed; higher binds tighter.
+    fn precedence(&self) -> u8 {
+        match self {
+            GqlExpr::UnaryOp(op, _) => op.precedence(),
+            GqlExpr::BinaryOp(_, op, _) => op.precedence(),
+            GqlExpr::Number(n) if *n < 0.0 => UnaryOp::Neg.precedence(),
+            _ => 8,
+        }
+    }
 }
 
-impl GqlEngine {
-    pub fn new(engidb: EngiDB) -> Self {
-        GqlEngine { engidb }
+impl UnaryOp {
+    fn precedence(&self) -> u8 {
+        match self {
+            UnaryOp::Not => 3,
+            UnaryOp::Neg => 7,
+        }
+    }
+}
+
+impl BinaryOp {
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq | BinaryOp::Ne | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => 4,
+            BinaryOp::Plus | BinaryOp::Minus => 5,
+            BinaryOp::Mul | BinaryOp::Div => 6,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Eq => "=",
+            BinaryOp::Ne => "<>",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::And => "AND",
+            BinaryOp::Or => "OR",
+            BinaryOp::Plus => "+",
+            BinaryOp::Minus => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+        }
+    }
+}
+
+/// Prints an expression as query text that parses back to it; column names default to this.
+impl fmt::Display for GqlExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let wrapped = |expr: &GqlExpr, min: u8| if expr.precedence() < min { format!("({})", expr) } else { expr.to_string() };
+        match self {
+            GqlExpr::Identifier(name) => write_name(f, name),
+            GqlExpr::String(s) => write!(f, "'{}'", s.replace('\\', "\\\\").replace('\'', "\\'")),
+            GqlExpr::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            GqlExpr::Number(n) => write!(f, "{}", n),
+            GqlExpr::Bool(b) => write!(f, "{}", b),
+            GqlExpr::Property(object, name) => {
+                write!(f, "{}.", wrapped(object, 8))?;
+                write_name(f, name)
+            }
+            GqlExpr::UnaryOp(UnaryOp::Neg, operand) => write!(f, "-{}", wrapped(operand, 8)),
+            GqlExpr::UnaryOp(UnaryOp::Not, operand) => write!(f, "NOT {}", wrapped(operand, 3)),
+            GqlExpr::BinaryOp(left, op, right) => {
+                // Operators associate to the left, and comparisons do not chain.
+                let level = op.precedence();
+                let left_min = if level == 4 { 5 } else { level };
+                write!(f, "{} {} {}", wrapped(left, left_min), op.symbol(), wrapped(right, level + 1))
+            }
+            GqlExpr::FunctionCall(name, args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+/// Writes a name, backtick-quoting it when it is not a plain identifier.
+fn write_name(f: &mut fmt::Formatter<'_>, name: &str) -> fmt::Result {
+    let plain = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !parser::is_keyword(name);
+    if plain {
+        write!(f, "{}", name)
+    } else {
+        write!(f, "`{}`", name)
+    }
+}
+
+/// What a query variable is bound to in a row.
+#[derive(Debug, Clone)]
+enum Binding {
+    Node(Node),
+    Edge(Adjacency),
+    /// A `RETURN` alias, or `null` for what an `OPTIONAL MATCH` did not find.
+    Value(Value),
+}
+
+type Row = HashMap<String, Binding>;
+
+/// GQL Parser and Interpreter, running against any storage backend
+pub struct GqlEngine<A> {
+    pub adapter: A,
+}
+
+impl<A: GraphAdapter> GqlEngine<A> {
+    pub fn new(adapter: A) -> Self {
+        GqlEngine { adapter }
     }
 
     /// Execute a GQL query
     pub fn execute_query(&self, query: &str) -> Result<GqlResult> {
         println!("🔍 Executing GQL query: {}", query);
+        self.execute(&parse_query(query)?)
+    }
 
-        // Parse query (simplified for now)
-        let statements = self.parse_query(query)?;
-
-        // Execute statements
-        let mut result_sets = Vec::new();
+    /// Execute parsed statements.
+    ///
+    /// A `WHERE` filters the rows of every clause before it; following an
+    /// `OPTIONAL MATCH`, it is part of what that clause must find.
+    pub fn execute(&self, statements: &[GqlStatement]) -> Result<GqlResult> {
+        let mut rows = vec![Row::new()];
+        let mut projection: Option<(&[ReturnExpr], Vec<String>)> = None;
+        let mut statements = statements.iter().peekable();
 
-        for statement in statements {
+        while let Some(statement) = statements.next() {
             match statement {
                 GqlStatement::Match(patterns) => {
-                    let results = self.execute_match(&patterns)?;
-                    result_sets.push(results);
-                }
-                GqlStatement::Where(condition) => {
-                    // Apply WHERE filter to current result set
-                    if let Some(last_result) = result_sets.last_mut() {
-                        self.apply_where_filter(last_result, &condition)?;
+                    let mut matched = Vec::new();
+                    for row in &rows {
+                        matched.extend(self.match_patterns(row, patterns)?);
                     }
+                    rows = matched;
                 }
-                GqlStatement::Return(expressions) => {
-                    if let Some(last_result) = result_sets.last() {
-                        return self.execute_return(last_result, &expressions);
+                GqlStatement::OptionalMatch(patterns) => {
+                    let condition = match statements.peek() {
+                        Some(GqlStatement::Where(condition)) => {
+                            statements.next();
+                            Some(condition)
+                        }
+                        _ => None,
+                    };
+                    let mut matched = Vec::new();
+                    for row in &rows {
+                        let mut found = self.match_patterns(row, patterns)?;
+                        if let Some(condition) = condition {
+                            found = self.filter(found, condition)?;
+                        }
+                        if found.is_empty() {
+                            let mut row = row.clone();
+                            for variable in pattern_variables(patterns) {
+                                row.entry(variable).or_insert(Binding::Value(Value::Null));
+                            }
+                            found.push(row);
+                        }
+                        matched.extend(found);
                     }
+                    rows = matched;
                 }
-                GqlStatement::OrderBy(order_by) => {
-                    if let Some(last_result) = result_sets.last_mut() {
-                        self.apply_order_by(last_result, &order_by)?;
+                GqlStatement::Where(condition) => rows = self.filter(rows, condition)?,
+                GqlStatement::Return(items) => {
+                    let columns = items.iter().map(|item| item.alias.clone().unwrap_or_else(|| item.expr.to_string())).collect();
+                    // Aliases are bound so that ORDER BY can sort on them.
+                    for row in &mut rows {
+                        for item in items.iter().filter(|item| item.alias.is_some()) {
+                            let value = self.evaluate(row, &item.expr)?;
+                            row.insert(item.alias.clone().unwrap_or_default(), Binding::Value(value));
+                        }
                     }
+                    projection = Some((items, columns));
                 }
-                GqlStatement::Limit(limit) => {
-                    if let Some(last_result) = result_sets.last_mut() {
-                        last_result.rows.truncate(limit);
+                GqlStatement::OrderBy(keys) => {
+                    let mut keyed = Vec::with_capacity(rows.len());
+                    for row in rows {
+                        let values = keys.iter().map(|key| self.evaluate(&row, &key.expr)).collect::<Result<Vec<_>>>()?;
+                        keyed.push((values, row));
                     }
+                    keyed.sort_by(|(a, _), (b, _)| {
+                        keys.iter().zip(a.iter().zip(b)).map(|(key, (a, b))| {
+                            let ordering = total_order(a, b);
+                            if key.ascending { ordering } else { ordering.reverse() }
+                        }).find(|ordering| ordering.is_ne()).unwrap_or(Ordering::Equal)
+                    });
+                    rows = keyed.into_iter().map(|(_, row)| row).collect();
                 }
-                _ => {} // OptionalMatch not implemented yet
+                GqlStatement::Skip(skip) => {
+                    rows.drain(..rows.len().min(*skip));
+                }
+                GqlStatement::Limit(limit) => rows.truncate(*limit),
             }
         }
 
-        // Default empty result
-        Ok(GqlResult {
-            columns: vec![],
-            rows: vec![],
-        })
-    }
-
-    /// Parse GQL query string into statements (simplified)
-    fn parse_query(&self, query: &str) -> Result<Vec<GqlStatement>> {
-        let query = query.trim();
-
-        // Very basic parsing for demonstration
-        let mut statements = Vec::new();
-
-        if query.to_uppercase().starts_with("MATCH") {
-            // Parse MATCH statement
-            let patterns = self.parse_match_patterns(query)?;
-            statements.push(GqlStatement::Match(patterns));
+        let Some((items, columns)) = projection else {
+            return Ok(GqlResult { columns: vec![], rows: vec![] });
+        };
+        let mut result = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let mut projected = HashMap::new();
+            for (item, column) in items.iter().zip(&columns) {
+                let value = match &item.alias {
+                    Some(alias) => self.evaluate(row, &GqlExpr::Identifier(alias.clone()))?,
+                    None => self.evaluate(row, &item.expr)?,
+                };
+                projected.insert(column.clone(), value);
+            }
+            result.push(projected);
         }
+        Ok(GqlResult { columns, rows: result })
+    }
 
-        if let Some(where_clause) = self.extract_where_clause(query) {
-            // Parse WHERE statement
-            let condition = self.parse_where_condition(where_clause)?;
-            statements.push(GqlStatement::Where(condition));
+    fn filter(&self, rows: Vec<Row>, condition: &GqlExpr) -> Result<Vec<Row>> {
+        let mut kept = Vec::new();
+        for row in rows {
+            if self.evaluate(&row, condition)? == Value::Bool(true) {
+                kept.push(row);
+            }
         }
+        Ok(kept)
+    }
 
-        if query.to_uppercase().contains("RETURN") {
-            // Parse RETURN statement
-            let return_exprs = self.parse_return_expressions(query)?;
-            statements.push(GqlStatement::Return(return_exprs));
+    /// Extensions of `row` matching every pattern of a clause.
+    fn match_patterns(&self, row: &Row, patterns: &[MatchPattern]) -> Result<Vec<Row>> {
+        let mut rows = vec![row.clone()];
+        for pattern in patterns {
+            let mut matched = Vec::new();
+            for row in &rows {
+                matched.extend(self.match_pattern(row, pattern)?);
+            }
+            rows = matched;
         }
-
-        Ok(statements)
+        Ok(rows)
     }
 
-    /// Parse MATCH patterns (simplified)
-    fn parse_match_patterns(&self, query: &str) -> Result<Vec<MatchPattern>> {
-        // Extract content between MATCH and WHERE/RETURN
-        let match_part = self.extract_match_part(query);
-
-        // Parse basic node patterns like (n:TodoItem)
-        let mut patterns = Vec::new();
-        let mut pattern = MatchPattern {
-            nodes: vec![],
-            edges: vec![],
-            incidences: vec![],
+    /// Extensions of `row` matching one path pattern, walked from its first node.
+    ///
+    /// Nodes further along the path are loaded only once an edge reaches them.
+    fn match_pattern(&self, row: &Row, pattern: &MatchPattern) -> Result<Vec<Row>> {
+        let Some(first) = pattern.nodes.first() else { return Ok(vec![row.clone()]) };
+        let candidates = match first.variable.as_ref().and_then(|variable| row.get(variable)) {
+            Some(Binding::Node(node)) => vec![node.clone()],
+            Some(_) => Vec::new(),
+            None => {
+                // A single label is scanned through the kind index; otherwise every node is a candidate
+                let filter = NodeFilter {
+                    kind: match first.labels.as_slice() {
+                        [label] => Some(label.clone()),
+                        _ => None,
+                    },
+                    ..NodeFilter::default()
+                };
+                self.adapter.scan_nodes(&filter)?.nodes
+            }
         };
 
-        // Simple regex-like parsing for (variable:Label {properties})
-        let node_pattern = regex::Regex::new(r"\(([a-zA-Z_][a-zA-Z0-9_]*)(?::([a-zA-Z_][a-zA-Z0-9_]*))?(?:\s*\{([^}]*)\})?\)")
-            .map_err(|_| Error::Validation("Invalid regex".to_string()))?;
-
-        for cap in node_pattern.captures_iter(&match_part) {
-            let variable = cap.get(1).map(|m| m.as_str().to_string());
-            let label = cap.get(2).map(|m| m.as_str().to_string());
-
-            let node = NodePattern {
-                variable,
-                labels: label.map(|l| vec![l]).unwrap_or_default(),
-                properties: HashMap::new(), // TODO: Parse properties
-            };
-
-            pattern.nodes.push(node);
-        }
-
-        if !pattern.nodes.is_empty() {
-            patterns.push(pattern);
+        let mut starts = Vec::new();
+        for node in candidates {
+            if let Some(row) = self.bind_node(row, first, node.clone())? {
+                starts.push((row, node));
+            }
         }
-
-        Ok(patterns)
-    }
-
-    /// Extract MATCH clause from query
-    fn extract_match_part<'a>(&self, query: &'a str) -> &'a str {
-        let upper = query.to_uppercase();
-        let start = upper.find("MATCH").unwrap_or(0) + 5;
-        let end = upper.find("WHERE").or_else(|| upper.find("RETURN")).unwrap_or(query.len());
-        &query[start..end].trim()
-    }
-
-    /// Extract WHERE clause
-    fn extract_where_clause<'a>(&self, query: &'a str) -> Option<&'a str> {
-        let upper = query.to_uppercase();
-        if let Some(start) = upper.find("WHERE") {
-            let end = upper[start..].find("RETURN").map(|i| start + i).unwrap_or(query.len());
-            Some(&query[start + 5..end].trim())
-        } else {
-            None
+        if pattern.edges.is_empty() {
+            return Ok(starts.into_iter().map(|(row, _)| row).collect());
         }
-    }
 
-    /// Parse WHERE condition (simplified)
-    fn parse_where_condition(&self, condition: &str) -> Result<GqlExpr> {
-        // Very basic parsing: property = value
-        if let Some(eq_pos) = condition.find('=') {
-            let left = condition[..eq_pos].trim();
-            let right = condition[eq_pos + 1..].trim();
-
-            let left_expr = GqlExpr::Property(
-                Box::new(GqlExpr::Identifier(left.to_string())),
-                "completed".to_string() // Assume property name for demo
-            );
-
-            let right_expr = if right == "true" {
-                GqlExpr::Bool(true)
-            } else if right == "false" {
-                GqlExpr::Bool(false)
-            } else {
-                GqlExpr::String(right.trim_matches('"').to_string())
-            };
-
-            Ok(GqlExpr::BinaryOp(
-                Box::new(left_expr),
-                BinaryOp::Eq,
-                Box::new(right_expr)
-            ))
-        } else {
-            Err(Error::Validation(format!("Unsupported WHERE condition: {}", condition)))
-        }
-    }
-
-    /// Parse RETURN expressions
-    fn parse_return_expressions(&self, query: &str) -> Result<Vec<ReturnExpr>> {
-        let upper = query.to_uppercase();
-        if let Some(start) = upper.find("RETURN") {
-            let return_part = &query[start + 6..].trim();
-            let expressions = return_part.split(',')
-                .map(|expr| expr.trim())
-                .filter(|expr| !expr.is_empty())
-                .map(|expr| ReturnExpr {
-                    expr: GqlExpr::Identifier(expr.to_string()),
-                    alias: None,
-                })
-                .collect();
-
-            Ok(expressions)
-        } else {
-            Ok(vec![])
+        // Paths matched so far, with the vertex each one ends at.
+        let mut paths = Vec::new();
+        for (row, node) in starts {
+            if let Some(vertex) = self.adapter.vertex_id(&node.id)? {
+                paths.push((row, vertex));
+            }
         }
-    }
-
-    /// Execute MATCH patterns against EngiDB
-    fn execute_match(&self, patterns: &[MatchPattern]) -> Result<GqlResult> {
-        let mut results = Vec::new();
-
-        // For each pattern, find matching nodes
-        for pattern in patterns {
-            for node_pattern in &pattern.nodes {
-                // Get all nodes of matching types
-                let nodes = self.engidb.scan_todo_items()?; // TODO: Make this generic for all node types
-
-                for node in nodes {
-                    // Check if node matches pattern
-                    if self.node_matches_pattern(&node, node_pattern) {
-                        let mut row = HashMap::new();
-
-                        // Add node data to result
-                        if let Some(var) = &node_pattern.variable {
-                            row.insert(var.clone(), serde_json::to_value(&node.properties)
-                                .map_err(|e| Error::Validation(e.to_string()))?);
+        for (edge_pattern, node_pattern) in pattern.edges.iter().zip(&pattern.nodes[1..]) {
+            let mut extended = Vec::new();
+            for (row, vertex) in paths {
+                for entry in self.matching_edges(vertex, edge_pattern)? {
+                    let other = if entry.source == vertex { entry.target } else { entry.source };
+                    let mut row = row.clone();
+                    if let Some(variable) = &edge_pattern.variable {
+                        match row.get(variable) {
+                            Some(Binding::Edge(bound)) if *bound == entry => {}
+                            Some(_) => continue,
+                            None => {
+                                row.insert(variable.clone(), Binding::Edge(entry.clone()));
+                            }
                         }
-
-                        results.push(row);
+                    }
+                    let node = match node_pattern.variable.as_ref().and_then(|variable| row.get(variable)) {
+                        Some(Binding::Node(bound)) if self.adapter.vertex_id(&bound.id)? == Some(other) => bound.clone(),
+                        Some(_) => continue,
+                        None => match self.adapter.vertex_node(other)? {
+                            Some(node) => node,
+                            // Raw edges may lead to vertices without a node.
+                            None => continue,
+                        },
+                    };
+                    if let Some(row) = self.bind_node(&row, node_pattern, node)? {
+                        extended.push((row, other));
                     }
                 }
             }
+            paths = extended;
         }
-
-        Ok(GqlResult {
-            columns: vec!["node".to_string()], // TODO: Proper column names
-            rows: results,
-        })
+        Ok(paths.into_iter().map(|(row, _)| row).collect())
     }
 
-    /// Check if a node matches a pattern
-    fn node_matches_pattern(&self, node: &Node, pattern: &NodePattern) -> bool {
-        // Check labels (node types)
+    /// `row` with `node` bound to `pattern`, if it has the labels and properties asked for.
+    fn bind_node(&self, row: &Row, pattern: &NodePattern, node: Node) -> Result<Option<Row>> {
         if !pattern.labels.is_empty() && !pattern.labels.contains(&node.kind) {
-            return false;
+            return Ok(None);
         }
-
-        // Check properties
-        for (prop_name, expected_expr) in &pattern.properties {
-            if let Some(actual_value) = node.properties.get(prop_name) {
-                // For now, only support exact matches
-                match expected_expr {
-                    GqlExpr::String(expected) => {
-                        if let Some(actual_str) = actual_value.as_str() {
-                            if actual_str != expected {
-                                return false;
-                            }
-                        } else {
-                            return false;
-                        }
-                    }
-                    GqlExpr::Bool(expected) => {
-                        if let Some(actual_bool) = actual_value.as_bool() {
-                            if actual_bool != *expected {
-                                return false;
-                            }
-                        } else {
-                            return false;
-                        }
-                    }
-                    _ => return false, // Unsupported expression type
+        let mut row = row.clone();
+        if let Some(variable) = &pattern.variable {
+            match row.get(variable) {
+                Some(Binding::Node(bound)) if bound.id == node.id => {}
+                Some(_) => return Ok(None),
+                None => {
+                    row.insert(variable.clone(), Binding::Node(node.clone()));
                 }
-            } else {
-                return false; // Property not found
             }
         }
-
-        true
+        for (property, expected) in &pattern.properties {
+            let expected = self.evaluate(&row, expected)?;
+            let actual = node.properties.get(property).unwrap_or(&Value::Null);
+            if equals(actual, &expected) != Some(true) {
+                return Ok(None);
+            }
+        }
+        Ok(Some(row))
     }
 
-    /// Apply WHERE filter to result set
-    fn apply_where_filter(&self, result_set: &mut GqlResult, condition: &GqlExpr) -> Result<()> {
-        result_set.rows.retain(|row| {
-            self.evaluate_condition(row, condition).unwrap_or(false)
-        });
-        Ok(())
+    /// Adjacency entries of a vertex matching `pattern`, following its direction.
+    fn matching_edges(&self, vertex_id: u64, pattern: &EdgePattern) -> Result<Vec<Adjacency>> {
+        let direction = match pattern.direction {
+            EdgeDirection::Outgoing => Direction::Outgoing,
+            EdgeDirection::Incoming => Direction::Incoming,
+            EdgeDirection::Bidirectional => Direction::Both,
+        };
+        let mut entries: Vec<Adjacency> = self.adapter.edges_of(vertex_id, direction)?
+            .into_iter()
+            .filter(|entry| pattern.labels.is_empty() || pattern.labels.contains(&entry.kind))
+            .collect();
+        // A self-loop is both an outgoing and an incoming entry.
+        entries.sort();
+        entries.dedup();
+        Ok(entries)
     }
 
-    /// Evaluate WHERE condition against a row
-    fn evaluate_condition(&self, row: &HashMap<String, serde_json::Value>, condition: &GqlExpr) -> Result<bool> {
-        match condition {
-            GqlExpr::BinaryOp(left, op, right) => {
-                let left_val = self.evaluate_expr(row, left)?;
-                let right_val = self.evaluate_expr(row, right)?;
+    /// Vertices one edge matching `pattern` away from a vertex, following its direction.
+    pub fn expand(&self, vertex_id: u64, pattern: &EdgePattern) -> Result<Vec<u64>> {
+        let mut neighbors: Vec<u64> = self.matching_edges(vertex_id, pattern)?
+            .into_iter()
+            .map(|entry| if entry.source == vertex_id { entry.target } else { entry.source })
+            .collect();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        Ok(neighbors)
+    }
 
+    /// Evaluates an expression against a row.
+    ///
+    /// A node evaluates to its properties and an edge to its id, type and
+    /// layer. Operators follow SQL's three-valued logic: anything compared
+    /// with `null`, or with a value of another type, is `null`.
+    fn evaluate(&self, row: &Row, expr: &GqlExpr) -> Result<Value> {
+        Ok(match expr {
+            GqlExpr::Identifier(variable) => match row.get(variable) {
+                Some(Binding::Node(node)) => serde_json::to_value(&node.properties)?,
+                Some(Binding::Edge(entry)) => serde_json::json!({
+                    "id": entry.edge,
+                    "type": entry.kind,
+                    "layer": entry.layer.as_ref().map(|layer| layer.as_str()),
+                }),
+                Some(Binding::Value(value)) => value.clone(),
+                None => return Err(Error::Runtime(format!("unbound GQL variable `{}`", variable))),
+            },
+            GqlExpr::Property(object, property) => match object.as_ref() {
+                GqlExpr::Identifier(variable) if matches!(row.get(variable), Some(Binding::Node(_))) => {
+                    let Some(Binding::Node(node)) = row.get(variable) else { unreachable!() };
+                    node.properties.get(property).cloned().unwrap_or(Value::Null)
+                }
+                object => self.evaluate(row, object)?.get(property).cloned().unwrap_or(Value::Null),
+            },
+            GqlExpr::String(s) => Value::String(s.clone()),
+            GqlExpr::Number(n) => number_value(*n),
+            GqlExpr::Bool(b) => Value::Bool(*b),
+            GqlExpr::UnaryOp(op, operand) => {
+                let operand = self.evaluate(row, operand)?;
                 match op {
-                    BinaryOp::Eq => Ok(left_val == right_val),
-                    BinaryOp::Ne => Ok(left_val != right_val),
-                    _ => Ok(false), // Other operators not implemented
+                    UnaryOp::Neg => operand.as_f64().map_or(Value::Null, |n| number_value(-n)),
+                    UnaryOp::Not => operand.as_bool().map_or(Value::Null, |b| Value::Bool(!b)),
                 }
             }
-            _ => Ok(true), // Non-binary expressions evaluate to true
-        }
-    }
-
-    /// Evaluate expression against a row
-    fn evaluate_expr(&self, row: &HashMap<String, serde_json::Value>, expr: &GqlExpr) -> Result<serde_json::Value> {
-        match expr {
-            GqlExpr::Identifier(var) => {
-                if let Some(value) = row.get(var) {
-                    Ok(value.clone())
-                } else {
-                    Ok(serde_json::Value::Null)
-                }
+            GqlExpr::BinaryOp(left, op, right) => {
+                let (left, right) = (self.evaluate(row, left)?, self.evaluate(row, right)?);
+                binary(&left, op, &right)
             }
-            GqlExpr::Property(obj_expr, prop) => {
-                let obj_val = self.evaluate_expr(row, obj_expr)?;
-                if let Some(obj) = obj_val.as_object() {
-                    if let Some(prop_val) = obj.get(prop) {
-                        Ok(prop_val.clone())
-                    } else {
-                        Ok(serde_json::Value::Null)
-                    }
-                } else {
-                    Ok(serde_json::Value::Null)
+            GqlExpr::FunctionCall(name, args) => {
+                let element = args.first().and_then(|arg| match arg {
+                    GqlExpr::Identifier(variable) => row.get(variable),
+                    _ => None,
+                });
+                let value = match args.first() {
+                    Some(arg) => self.evaluate(row, arg)?,
+                    None => Value::Null,
+                };
+                match (name.as_str(), element) {
+                    ("id", Some(Binding::Node(node))) => Value::String(node.id.clone()),
+                    ("id", Some(Binding::Edge(entry))) => entry.edge.clone().map_or(Value::Null, Value::String),
+                    ("labels", Some(Binding::Node(node))) => Value::Array(vec![Value::String(node.kind.clone())]),
+                    ("type", Some(Binding::Edge(entry))) => Value::String(entry.kind.clone()),
+                    ("size", _) => match &value {
+                        Value::String(s) => Value::from(s.chars().count()),
+                        Value::Array(items) => Value::from(items.len()),
+                        Value::Object(map) => Value::from(map.len()),
+                        _ => Value::Null,
+                    },
+                    ("lower", _) => value.as_str().map_or(Value::Null, |s| Value::String(s.to_lowercase())),
+                    ("upper", _) => value.as_str().map_or(Value::Null, |s| Value::String(s.to_uppercase())),
+                    _ => Value::Null,
                 }
             }
-            GqlExpr::Bool(b) => Ok(serde_json::Value::Bool(*b)),
-            GqlExpr::String(s) => Ok(serde_json::Value::String(s.clone())),
-            _ => Ok(serde_json::Value::Null), // Other expressions not implemented
-        }
+        })
     }
+}
 
-    /// Apply ORDER BY to result set
-    fn apply_order_by(&self, result_set: &mut GqlResult, order_by: &[OrderBy]) -> Result<()> {
-        // Simple implementation: sort by first expression
-        if let Some(first_order) = order_by.first() {
-            result_set.rows.sort_by(|a, b| {
-                let a_val = self.evaluate_expr(a, &first_order.expr).unwrap_or(serde_json::Value::Null);
-                let b_val = self.evaluate_expr(b, &first_order.expr).unwrap_or(serde_json::Value::Null);
-
-                // Simple string-based comparison for ordering
-                let a_str = a_val.to_string();
-                let b_str = b_val.to_string();
-                if first_order.ascending {
-                    a_str.cmp(&b_str)
-                } else {
-                    b_str.cmp(&a_str)
-                }
-            });
-        }
-        Ok(())
-    }
+/// Variables the patterns of a clause bind.
+fn pattern_variables(patterns: &[MatchPattern]) -> impl Iterator<Item = String> + '_ {
+    patterns.iter().flat_map(|pattern| {
+        let nodes = pattern.nodes.iter().filter_map(|node| node.variable.clone());
+        nodes.chain(pattern.edges.iter().filter_map(|edge| edge.variable.clone()))
+    })
+}
 
-    /// Execute RETURN clause
-    fn execute_return(&self, result_set: &GqlResult, expressions: &[ReturnExpr]) -> Result<GqlResult> {
-        let mut columns = Vec::new();
-        let mut rows = Vec::new();
+/// Numbers without a fractional part come out as integers.
+fn number_value(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < 9_007_199_254_740_992.0 {
+        Value::from(n as i64)
+    } else {
+        serde_json::Number::from_f64(n).map_or(Value::Null, Value::Number)
+    }
+}
 
-        // Build column names
-        for expr in expressions {
-            let col_name = expr.alias.clone()
-                .unwrap_or_else(|| format!("{:?}", expr.expr)); // Simple fallback
-            columns.push(col_name);
-        }
+/// Equality of two values; `None` when either is `null`.
+fn equals(a: &Value, b: &Value) -> Option<bool> {
+    match (a, b) {
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Number(a), Value::Number(b)) => Some(a.as_f64() == b.as_f64()),
+        _ => Some(a == b),
+    }
+}
 
-        // Build rows
-        for row_data in &result_set.rows {
-            let mut row = HashMap::new();
+/// Order of two values of the same type; `None` otherwise.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
 
-            for (i, expr) in expressions.iter().enumerate() {
-                let value = self.evaluate_expr(row_data, &expr.expr)?;
-                row.insert(columns[i].clone(), value);
+fn binary(left: &Value, op: &BinaryOp, right: &Value) -> Value {
+    let truth = |value: &Value| value.as_bool();
+    let number = |value: &Value| value.as_f64();
+    match op {
+        BinaryOp::And => match (truth(left), truth(right)) {
+            (Some(false), _) | (_, Some(false)) => Value::Bool(false),
+            (Some(true), Some(true)) => Value::Bool(true),
+            _ => Value::Null,
+        },
+        BinaryOp::Or => match (truth(left), truth(right)) {
+            (Some(true), _) | (_, Some(true)) => Value::Bool(true),
+            (Some(false), Some(false)) => Value::Bool(false),
+            _ => Value::Null,
+        },
+        BinaryOp::Eq => equals(left, right).map_or(Value::Null, Value::Bool),
+        BinaryOp::Ne => equals(left, right).map_or(Value::Null, |equal| Value::Bool(!equal)),
+        BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => match compare(left, right) {
+            Some(ordering) => Value::Bool(match op {
+                BinaryOp::Lt => ordering.is_lt(),
+                BinaryOp::Le => ordering.is_le(),
+                BinaryOp::Gt => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            }),
+            None => Value::Null,
+        },
+        BinaryOp::Plus | BinaryOp::Minus | BinaryOp::Mul | BinaryOp::Div => {
+            if let (BinaryOp::Plus, Value::String(a), Value::String(b)) = (op, left, right) {
+                return Value::String(format!("{}{}", a, b));
+            }
+            let (Some(a), Some(b)) = (number(left), number(right)) else { return Value::Null };
+            match op {
+                BinaryOp::Plus => number_value(a + b),
+                BinaryOp::Minus => number_value(a - b),
+                BinaryOp::Mul => number_value(a * b),
+                _ if b == 0.0 => Value::Null,
+                _ => number_value(a / b),
             }
-
-            rows.push(row);
         }
-
-        Ok(GqlResult { columns, rows })
     }
 }
 
+/// Sort order over all values: by type, then within the type, with `null` last.
+fn total_order(a: &Value, b: &Value) -> Ordering {
+    let rank = |value: &Value| match value {
+        Value::Number(_) => 0,
+        Value::String(_) => 1,
+        Value::Bool(_) => 2,
+        Value::Array(_) | Value::Object(_) => 3,
+        Value::Null => 4,
+    };
+    rank(a).cmp(&rank(b))
+        .then_with(|| compare(a, b).unwrap_or_else(|| a.to_string().cmp(&b.to_string())))
+}
+
 /// Convenience function to execute GQL query
-pub fn execute_gql_query(engidb: &EngiDB, query: &str) -> Result<GqlResult> {
-    let engine = GqlEngine::new(engidb.clone());
+pub fn execute_gql_query<A: GraphAdapter + Clone>(adapter: &A, query: &str) -> Result<GqlResult> {
+    let engine = GqlEngine::new(adapter.clone());
     engine.execute_query(query)
 }
 
-// Add regex dependency for parsing
-extern crate regex;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engidb::adapter::InMemoryAdapter;
+    use crate::engidb::temporal::KIND_ATTRIBUTE;
+    use crate::engidb::transact::TxOp;
+    use serde_json::json;
+
+    fn assert(entity: &str, attribute: &str, value: Value) -> TxOp {
+        TxOp::Assert { entity: entity.to_string(), attribute: attribute.to_string(), value }
+    }
+
+    fn knows(source: &str, target: &str) -> TxOp {
+        TxOp::AssertEdge { source: source.to_string(), kind: "knows".to_string(), target: target.to_string() }
+    }
+
+    /// Ann (30) knows Bob (35), who knows Cid (no age), who knows Ann; Ann
+    /// works at Acme.
+    fn people() -> GqlEngine<InMemoryAdapter> {
+        let adapter = InMemoryAdapter::new();
+        let mut ops = Vec::new();
+        for (id, name, age) in [("ann", "Ann", Some(30)), ("bob", "Bob", Some(35)), ("cid", "Cid", None)] {
+            ops.push(assert(id, KIND_ATTRIBUTE, json!("Person")));
+            ops.push(assert(id, "name", json!(name)));
+            if let Some(age) = age {
+                ops.push(assert(id, "age", json!(age)));
+            }
+        }
+        ops.extend([assert("acme", KIND_ATTRIBUTE, json!("Company")), assert("acme", "name", json!("Acme"))]);
+        ops.extend([knows("ann", "bob"), knows("bob", "cid"), knows("cid", "ann")]);
+        ops.push(TxOp::AssertEdge { source: "ann".to_string(), kind: "works_at".to_string(), target: "acme".to_string() });
+        adapter.transact(&ops).unwrap();
+        GqlEngine::new(adapter)
+    }
+
+    /// The rows of a query, each as its values in column order.
+    fn rows(engine: &GqlEngine<InMemoryAdapter>, query: &str) -> Vec<Vec<Value>> {
+        let result = engine.execute_query(query).unwrap();
+        result.rows.iter().map(|row| result.columns.iter().map(|column| row[column].clone()).collect()).collect()
+    }
+
+    #[test]
+    fn optional_match_keeps_rows_without_a_match() {
+        let engine = people();
+        assert_eq!(
+            rows(&engine, "MATCH (p:Person) OPTIONAL MATCH (p)-[:works_at]->(c:Company) RETURN p.name, c.name ORDER BY p.name"),
+            vec![vec![json!("Ann"), json!("Acme")], vec![json!("Bob"), Value::Null], vec![json!("Cid"), Value::Null]],
+        );
+        // A WHERE after OPTIONAL MATCH is part of what it must find, not a filter on the rows.
+        assert_eq!(
+            rows(&engine, "MATCH (p:Person) OPTIONAL MATCH (p)-[:knows]->(f) WHERE f.age > 31 RETURN p.name, f.name ORDER BY p.name"),
+            vec![vec![json!("Ann"), json!("Bob")], vec![json!("Bob"), Value::Null], vec![json!("Cid"), Value::Null]],
+        );
+        assert_eq!(rows(&engine, "MATCH (p:Person) OPTIONAL MATCH (p)-[:works_at]->(c) WHERE c.name = 'Acme' RETURN p.name").len(), 3);
+    }
+
+    #[test]
+    fn conditions_follow_three_valued_logic() {
+        let engine = people();
+        let names = |condition: &str| rows(&engine, &format!("MATCH (p:Person) WHERE {} RETURN p.name ORDER BY p.name", condition));
+        assert_eq!(names("p.age > 31 OR p.name = 'Ann'"), vec![vec![json!("Ann")], vec![json!("Bob")]]);
+        // Cid has no age, so neither a comparison with it nor its negation holds.
+        assert_eq!(names("NOT p.age > 31"), vec![vec![json!("Ann")]]);
+        assert_eq!(names("p.age <> 30"), vec![vec![json!("Bob")]]);
+        assert_eq!(names("p.age > 31 OR true"), names("true"));
+        assert!(names("p.age = 'thirty'").is_empty());
+
+        assert_eq!(
+            rows(&engine, "MATCH (p:Person {name: 'Cid'}) RETURN p.age = 1, p.age > 1 OR true, p.age > 1 AND false, NOT p.age, -p.age, 'a' < 1"),
+            vec![vec![Value::Null, json!(true), json!(false), Value::Null, Value::Null, Value::Null]],
+        );
+        assert_eq!(
+            rows(&engine, "MATCH (p:Person {name: 'Bob'}) RETURN -p.age, NOT p.age > 31, 7 / 2, 1 / 0, 'a' + 'b'"),
+            vec![vec![json!(-35), json!(false), json!(3.5), Value::Null, json!("ab")]],
+        );
+    }
+
+    #[test]
+    fn order_by_skip_and_limit_page_through_sorted_rows() {
+        let engine = people();
+        let names = |clauses: &str| -> Vec<Value> {
+            rows(&engine, &format!("MATCH (p:Person) RETURN p.name AS name {}", clauses)).into_iter().map(|row| row[0].clone()).collect()
+        };
+        // Nulls sort last, and so first when descending.
+        assert_eq!(names("ORDER BY p.age"), vec![json!("Ann"), json!("Bob"), json!("Cid")]);
+        assert_eq!(names("ORDER BY p.age DESC"), vec![json!("Cid"), json!("Bob"), json!("Ann")]);
+        assert_eq!(names("ORDER BY name DESC SKIP 1"), vec![json!("Bob"), json!("Ann")]);
+        assert_eq!(names("ORDER BY name SKIP 1 LIMIT 1"), vec![json!("Bob")]);
+        assert_eq!(names("ORDER BY name LIMIT 2"), vec![json!("Ann"), json!("Bob")]);
+        assert!(names("ORDER BY name SKIP 5").is_empty());
+        assert!(names("ORDER BY name LIMIT 0").is_empty());
+    }
+
+    #[test]
+    fn functions_read_nodes_edges_and_values() {
+        let engine = people();
+        assert_eq!(
+            rows(
+                &engine,
+                "MATCH (p:Person {name: 'Ann'})-[e:knows]->(f) \
+                 RETURN id(p), labels(p), type(e), id(e), upper(f.name), lower('AbC'), size(p.name), size(labels(f)), size(p.age)",
+            ),
+            vec![vec![
+                json!("ann"), json!(["Person"]), json!("knows"), json!("ann:knows:bob"), json!("BOB"), json!("abc"), json!(3), json!(1),
+                Value::Null,
+            ]],
+        );
+    }
+
+    #[test]
+    fn traversal_follows_directions_and_bound_variables() {
+        let engine = people();
+        let ann = engine.adapter.vertex_id("ann").unwrap().unwrap();
+        // A raw edge to a vertex without a node leads nowhere.
+        engine.adapter.add_edge(ann, "knows", ann + 100).unwrap();
+
+        assert_eq!(rows(&engine, "MATCH (p {name: 'Ann'})-[:knows]->(q) RETURN q.name"), vec![vec![json!("Bob")]]);
+        assert_eq!(
+            rows(&engine, "MATCH (p)<-[:knows]-(q) RETURN p.name, q.name ORDER BY p.name"),
+            vec![vec![json!("Ann"), json!("Cid")], vec![json!("Bob"), json!("Ann")], vec![json!("Cid"), json!("Bob")]],
+        );
+        assert_eq!(
+            rows(&engine, "MATCH (p {name: 'Bob'})-[:knows]-(q) RETURN q.name ORDER BY q.name"),
+            vec![vec![json!("Ann")], vec![json!("Cid")]],
+        );
+        assert_eq!(
+            rows(&engine, "MATCH (a:Person)-[:knows]->(b)-[:knows]->(c) WHERE a.name = 'Ann' RETURN c.name"),
+            vec![vec![json!("Cid")]],
+        );
+        // A variable met again along the path must be the same node.
+        assert_eq!(
+            rows(&engine, "MATCH (a)-[:knows]->(b)-[:knows]->(c)-[:knows]->(a) RETURN a.name ORDER BY a.name"),
+            vec![vec![json!("Ann")], vec![json!("Bob")], vec![json!("Cid")]],
+        );
+        assert!(rows(&engine, "MATCH (a)-[:knows]->(b)-[:knows]->(a) RETURN a.name").is_empty());
+        assert_eq!(rows(&engine, "MATCH (a {name: 'Ann'}), (a)-[:works_at]->(c) RETURN c.name"), vec![vec![json!("Acme")]]);
+    }
+}
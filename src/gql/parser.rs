@@ -0,0 +1,759 @@
+//! Tokenizer and recursive-descent parser for GQL queries.
+//!
+//! A query is one or more `MATCH` / `OPTIONAL MATCH` clauses, each optionally
+//! followed by a `WHERE`, then a `RETURN` with optional `ORDER BY`, `SKIP`
+//! and `LIMIT`. Keywords are case-insensitive; variables, labels and property
+//! names may be backtick-quoted. Every error carries the byte span of the
+//! offending text.
+
+use super::{
+    BinaryOp, EdgeDirection, EdgePattern, GqlExpr, GqlStatement, IncidencePattern, MatchPattern, NodePattern, OrderBy, ReturnExpr,
+    UnaryOp,
+};
+use crate::{Error, Result};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// Words with a meaning of their own; they name a variable only when quoted.
+const KEYWORDS: &[&str] = &[
+    "MATCH", "OPTIONAL", "WHERE", "RETURN", "ORDER", "BY", "ASC", "ASCENDING", "DESC", "DESCENDING",
+    "SKIP", "LIMIT", "AS", "AND", "OR", "NOT", "TRUE", "FALSE",
+];
+
+/// Functions queries may call, with their number of arguments.
+const FUNCTIONS: &[(&str, usize)] = &[("id", 1), ("labels", 1), ("type", 1), ("size", 1), ("lower", 1), ("upper", 1)];
+
+/// Operators and punctuation, longest first so that `<=` wins over `<`.
+const SYMBOLS: &[&str] = &[
+    "<>", "!=", "<=", ">=", "(", ")", "[", "]", "{", "}", ":", ",", ".", "|", "=", "<", ">", "+", "-", "*", "/",
+];
+
+pub(crate) fn is_keyword(word: &str) -> bool {
+    KEYWORDS.iter().any(|keyword| keyword.eq_ignore_ascii_case(word))
+}
+
+fn syntax_error(message: impl Into<String>, span: Range<usize>) -> Error {
+    Error::GqlSyntax { message: message.into(), span }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Bare word: a keyword, variable, label, property or function name.
+    Word(String),
+    /// Backtick-quoted name, never a keyword.
+    Quoted(String),
+    Str(String),
+    Number(f64),
+    Symbol(&'static str),
+    End,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => format!("`{}`", word),
+            Token::Quoted(name) => format!("`{}`", name),
+            Token::Str(_) => "a string".to_string(),
+            Token::Number(_) => "a number".to_string(),
+            Token::Symbol(symbol) => format!("`{}`", symbol),
+            Token::End => "the end of the query".to_string(),
+        }
+    }
+}
+
+struct Lexeme {
+    token: Token,
+    span: Range<usize>,
+}
+
+fn tokenize(query: &str) -> Result<Vec<Lexeme>> {
+    let mut lexemes = Vec::new();
+    let mut chars = query.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let token = if c.is_alphabetic() || c == '_' {
+            let mut word = String::new();
+            while let Some(&(_, c)) = chars.peek().filter(|(_, c)| c.is_alphanumeric() || *c == '_') {
+                word.push(c);
+                chars.next();
+            }
+            Token::Word(word)
+        } else if c.is_ascii_digit() {
+            let mut end = start;
+            while let Some(&(i, _)) = chars.peek().filter(|(_, c)| c.is_ascii_digit()) {
+                end = i + 1;
+                chars.next();
+            }
+            // A dot continues the number only when a digit follows it.
+            if query[end..].starts_with('.') && query[end + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+                chars.next();
+                while let Some(&(i, _)) = chars.peek().filter(|(_, c)| c.is_ascii_digit()) {
+                    end = i + 1;
+                    chars.next();
+                }
+            }
+            let number = query[start..end].parse().map_err(|_| syntax_error("invalid number", start..end))?;
+            Token::Number(number)
+        } else if c == '\'' || c == '"' || c == '`' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, close)) if close == c => break,
+                    Some((i, '\\')) if c != '`' => match chars.next() {
+                        Some((_, escaped @ ('\\' | '\'' | '"'))) => text.push(escaped),
+                        Some((_, 'n')) => text.push('\n'),
+                        Some((_, 't')) => text.push('\t'),
+                        Some((j, other)) => {
+                            return Err(syntax_error(format!("unknown escape `\\{}`", other), i..j + other.len_utf8()))
+                        }
+                        None => return Err(syntax_error("unterminated string", start..query.len())),
+                    },
+                    Some((_, other)) => text.push(other),
+                    None if c == '`' => return Err(syntax_error("unterminated quoted name", start..query.len())),
+                    None => return Err(syntax_error("unterminated string", start..query.len())),
+                }
+            }
+            if c == '`' {
+                Token::Quoted(text)
+            } else {
+                Token::Str(text)
+            }
+        } else {
+            let symbol = SYMBOLS.iter().find(|symbol| query[start..].starts_with(**symbol));
+            let symbol = symbol.ok_or_else(|| syntax_error(format!("unexpected character `{}`", c), start..start + c.len_utf8()))?;
+            for _ in 0..symbol.len() {
+                chars.next();
+            }
+            Token::Symbol(symbol)
+        };
+        let end = chars.peek().map_or(query.len(), |&(i, _)| i);
+        lexemes.push(Lexeme { token, span: start..end });
+    }
+    lexemes.push(Lexeme { token: Token::End, span: query.len()..query.len() });
+    Ok(lexemes)
+}
+
+/// What a pattern variable stands for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Element {
+    Node,
+    Edge,
+}
+
+impl Element {
+    fn name(self) -> &'static str {
+        match self {
+            Element::Node => "node",
+            Element::Edge => "edge",
+        }
+    }
+}
+
+struct Parser {
+    lexemes: Vec<Lexeme>,
+    pos: usize,
+    /// Variables declared by the patterns parsed so far.
+    variables: HashMap<String, Element>,
+    /// `RETURN` aliases, which `ORDER BY` may refer to.
+    aliases: HashSet<String>,
+}
+
+/// Parses a query into its statements, in clause order.
+pub fn parse_query(query: &str) -> Result<Vec<GqlStatement>> {
+    let mut parser = Parser { lexemes: tokenize(query)?, pos: 0, variables: HashMap::new(), aliases: HashSet::new() };
+    parser.query()
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.lexemes[self.pos].token
+    }
+
+    fn span(&self) -> Range<usize> {
+        self.lexemes[self.pos].span.clone()
+    }
+
+    fn next(&mut self) -> Lexeme {
+        let lexeme = Lexeme { token: self.peek().clone(), span: self.span() };
+        if self.pos + 1 < self.lexemes.len() {
+            self.pos += 1;
+        }
+        lexeme
+    }
+
+    fn unexpected(&self, expected: &str) -> Error {
+        syntax_error(format!("expected {}, found {}", expected, self.peek().describe()), self.span())
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Token::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.at_keyword(keyword);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{}`", keyword)))
+        }
+    }
+
+    fn at_symbol(&self, symbol: &'static str) -> bool {
+        *self.peek() == Token::Symbol(symbol)
+    }
+
+    fn eat_symbol(&mut self, symbol: &'static str) -> bool {
+        let found = self.at_symbol(symbol);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: &'static str) -> Result<Range<usize>> {
+        if self.at_symbol(symbol) {
+            Ok(self.next().span)
+        } else {
+            Err(self.unexpected(&format!("`{}`", symbol)))
+        }
+    }
+
+    /// A variable, which must not be a keyword unless quoted.
+    fn variable(&mut self) -> Option<(String, Range<usize>)> {
+        match self.peek() {
+            Token::Word(word) if !is_keyword(word) => {}
+            Token::Quoted(_) => {}
+            _ => return None,
+        }
+        let Lexeme { token, span } = self.next();
+        match token {
+            Token::Word(name) | Token::Quoted(name) => Some((name, span)),
+            _ => None,
+        }
+    }
+
+    /// A label, property name or alias, where keywords are allowed.
+    fn name(&mut self, expected: &str) -> Result<(String, Range<usize>)> {
+        match self.peek() {
+            Token::Word(_) | Token::Quoted(_) => match self.next() {
+                Lexeme { token: Token::Word(name) | Token::Quoted(name), span } => Ok((name, span)),
+                _ => unreachable!(),
+            },
+            _ => Err(self.unexpected(expected)),
+        }
+    }
+
+    fn query(&mut self) -> Result<Vec<GqlStatement>> {
+        let mut statements = Vec::new();
+        loop {
+            let optional = self.eat_keyword("OPTIONAL");
+            if !optional && !self.at_keyword("MATCH") {
+                if statements.is_empty() {
+                    return Err(self.unexpected("`MATCH`"));
+                }
+                break;
+            }
+            self.expect_keyword("MATCH")?;
+            let mut patterns = vec![self.pattern()?];
+            while self.eat_symbol(",") {
+                patterns.push(self.pattern()?);
+            }
+            statements.push(if optional { GqlStatement::OptionalMatch(patterns) } else { GqlStatement::Match(patterns) });
+            if self.eat_keyword("WHERE") {
+                statements.push(GqlStatement::Where(self.expr()?));
+            }
+        }
+
+        self.expect_keyword("RETURN")?;
+        statements.push(GqlStatement::Return(self.return_items()?));
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            let mut keys = Vec::new();
+            loop {
+                let expr = self.expr()?;
+                let ascending = !(self.eat_keyword("DESC") || self.eat_keyword("DESCENDING"));
+                if ascending && !self.eat_keyword("ASC") {
+                    self.eat_keyword("ASCENDING");
+                }
+                keys.push(OrderBy { expr, ascending });
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+            statements.push(GqlStatement::OrderBy(keys));
+        }
+        if self.eat_keyword("SKIP") {
+            statements.push(GqlStatement::Skip(self.count()?));
+        }
+        if self.eat_keyword("LIMIT") {
+            statements.push(GqlStatement::Limit(self.count()?));
+        }
+        if *self.peek() != Token::End {
+            return Err(self.unexpected("the end of the query"));
+        }
+        Ok(statements)
+    }
+
+    /// The row count of a `SKIP` or `LIMIT`.
+    fn count(&mut self) -> Result<usize> {
+        let Lexeme { token, span } = self.next();
+        match token {
+            Token::Number(n) if n.fract() == 0.0 && n <= usize::MAX as f64 => Ok(n as usize),
+            _ => Err(syntax_error(format!("expected a non-negative integer, found {}", token.describe()), span)),
+        }
+    }
+
+    fn return_items(&mut self) -> Result<Vec<ReturnExpr>> {
+        let mut items = Vec::new();
+        let mut columns = HashSet::new();
+        loop {
+            let start = self.span().start;
+            let expr = self.expr()?;
+            let alias = if self.eat_keyword("AS") { Some(self.name("an alias")?.0) } else { None };
+            let column = alias.clone().unwrap_or_else(|| expr.to_string());
+            let end = self.lexemes[self.pos - 1].span.end;
+            if !columns.insert(column.clone()) {
+                return Err(syntax_error(format!("duplicate column `{}`", column), start..end));
+            }
+            items.push(ReturnExpr { expr, alias });
+            if !self.eat_symbol(",") {
+                break;
+            }
+        }
+        self.aliases = items.iter().filter_map(|item| item.alias.clone()).collect();
+        Ok(items)
+    }
+
+    fn declare(&mut self, name: &str, span: Range<usize>, element: Element) -> Result<()> {
+        match self.variables.get(name) {
+            Some(&declared) if declared != element => Err(syntax_error(
+                format!("`{}` is already bound to {} {}", name, if declared == Element::Edge { "an" } else { "a" }, declared.name()),
+                span,
+            )),
+            _ => {
+                self.variables.insert(name.to_string(), element);
+                Ok(())
+            }
+        }
+    }
+
+    /// `node (edge node)*`
+    fn pattern(&mut self) -> Result<MatchPattern> {
+        let mut pattern = MatchPattern { nodes: vec![self.node()?], edges: Vec::new(), incidences: Vec::new() };
+        while self.at_symbol("-") || self.at_symbol("<") {
+            let edge = self.edge()?;
+            let node = self.node()?;
+            let var = |variable: &Option<String>| variable.clone().unwrap_or_default();
+            let (left, right) = (var(&pattern.nodes[pattern.nodes.len() - 1].variable), var(&node.variable));
+            let (source, target) = if edge.direction == EdgeDirection::Incoming { (right, left) } else { (left, right) };
+            pattern.incidences.push(IncidencePattern { source, target, edge: var(&edge.variable) });
+            pattern.edges.push(edge);
+            pattern.nodes.push(node);
+        }
+        Ok(pattern)
+    }
+
+    /// `(var :Label|Other {key: expr, ...})`, every part optional.
+    fn node(&mut self) -> Result<NodePattern> {
+        self.expect_symbol("(")?;
+        let variable = self.variable();
+        if let Some((name, span)) = &variable {
+            self.declare(name, span.clone(), Element::Node)?;
+        }
+        let labels = self.labels()?;
+        let mut properties = HashMap::new();
+        if self.eat_symbol("{") {
+            loop {
+                let (key, span) = self.name("a property name")?;
+                self.expect_symbol(":")?;
+                let value = self.expr()?;
+                if properties.insert(key.clone(), value).is_some() {
+                    return Err(syntax_error(format!("duplicate property `{}`", key), span));
+                }
+                if !self.eat_symbol(",") {
+                    break;
+                }
+            }
+            self.expect_symbol("}")?;
+        }
+        self.expect_symbol(")")?;
+        Ok(NodePattern { variable: variable.map(|(name, _)| name), labels, properties })
+    }
+
+    fn labels(&mut self) -> Result<Vec<String>> {
+        let mut labels = Vec::new();
+        if self.eat_symbol(":") {
+            labels.push(self.name("a label")?.0);
+            while self.eat_symbol("|") {
+                labels.push(self.name("a label")?.0);
+            }
+        }
+        Ok(labels)
+    }
+
+    /// `-[..]->`, `<-[..]-` or `-[..]-`; the bracket may be left out, as in `-->`.
+    fn edge(&mut self) -> Result<EdgePattern> {
+        let start = self.span().start;
+        let incoming = self.eat_symbol("<");
+        self.expect_symbol("-")?;
+        let (mut variable, mut labels) = (None, Vec::new());
+        if self.eat_symbol("[") {
+            if let Some((name, span)) = self.variable() {
+                self.declare(&name, span, Element::Edge)?;
+                variable = Some(name);
+            }
+            labels = self.labels()?;
+            if self.at_symbol("{") {
+                return Err(syntax_error("edge property maps are not supported", self.span()));
+            }
+            self.expect_symbol("]")?;
+        }
+        self.expect_symbol("-")?;
+        let direction = match (incoming, self.at_symbol(">")) {
+            (true, true) => return Err(syntax_error("an edge cannot point both ways", start..self.span().end)),
+            (true, false) => EdgeDirection::Incoming,
+            (false, true) => EdgeDirection::Outgoing,
+            (false, false) => EdgeDirection::Bidirectional,
+        };
+        if direction == EdgeDirection::Outgoing {
+            self.next();
+        }
+        Ok(EdgePattern { variable, direction, labels, properties: HashMap::new() })
+    }
+
+    fn expr(&mut self) -> Result<GqlExpr> {
+        let mut left = self.and()?;
+        while self.eat_keyword("OR") {
+            left = GqlExpr::BinaryOp(Box::new(left), BinaryOp::Or, Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<GqlExpr> {
+        let mut left = self.not()?;
+        while self.eat_keyword("AND") {
+            left = GqlExpr::BinaryOp(Box::new(left), BinaryOp::And, Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<GqlExpr> {
+        if self.eat_keyword("NOT") {
+            return Ok(GqlExpr::UnaryOp(UnaryOp::Not, Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison_op(&self) -> Option<BinaryOp> {
+        match self.peek() {
+            Token::Symbol("=") => Some(BinaryOp::Eq),
+            Token::Symbol("<>" | "!=") => Some(BinaryOp::Ne),
+            Token::Symbol("<") => Some(BinaryOp::Lt),
+            Token::Symbol("<=") => Some(BinaryOp::Le),
+            Token::Symbol(">") => Some(BinaryOp::Gt),
+            Token::Symbol(">=") => Some(BinaryOp::Ge),
+            _ => None,
+        }
+    }
+
+    fn comparison(&mut self) -> Result<GqlExpr> {
+        let left = self.additive()?;
+        let Some(op) = self.comparison_op() else { return Ok(left) };
+        self.next();
+        let right = self.additive()?;
+        if self.comparison_op().is_some() {
+            return Err(syntax_error("comparisons cannot be chained; join them with AND", self.span()));
+        }
+        Ok(GqlExpr::BinaryOp(Box::new(left), op, Box::new(right)))
+    }
+
+    fn additive(&mut self) -> Result<GqlExpr> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol("+") => BinaryOp::Plus,
+                Token::Symbol("-") => BinaryOp::Minus,
+                _ => return Ok(left),
+            };
+            self.next();
+            left = GqlExpr::BinaryOp(Box::new(left), op, Box::new(self.multiplicative()?));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<GqlExpr> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol("*") => BinaryOp::Mul,
+                Token::Symbol("/") => BinaryOp::Div,
+                _ => return Ok(left),
+            };
+            self.next();
+            left = GqlExpr::BinaryOp(Box::new(left), op, Box::new(self.unary()?));
+        }
+    }
+
+    /// `-1` is read as a literal rather than a negation.
+    fn unary(&mut self) -> Result<GqlExpr> {
+        if !self.eat_symbol("-") {
+            return self.postfix();
+        }
+        Ok(match self.unary()? {
+            GqlExpr::Number(n) => GqlExpr::Number(-n),
+            operand => GqlExpr::UnaryOp(UnaryOp::Neg, Box::new(operand)),
+        })
+    }
+
+    fn postfix(&mut self) -> Result<GqlExpr> {
+        let mut expr = self.primary()?;
+        while self.eat_symbol(".") {
+            expr = GqlExpr::Property(Box::new(expr), self.name("a property name")?.0);
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<GqlExpr> {
+        let span = self.span();
+        match self.peek().clone() {
+            Token::Number(n) => {
+                self.next();
+                Ok(GqlExpr::Number(n))
+            }
+            Token::Str(s) => {
+                self.next();
+                Ok(GqlExpr::String(s))
+            }
+            Token::Symbol("(") => {
+                self.next();
+                let expr = self.expr()?;
+                self.expect_symbol(")")?;
+                Ok(expr)
+            }
+            Token::Word(word) if word.eq_ignore_ascii_case("TRUE") || word.eq_ignore_ascii_case("FALSE") => {
+                self.next();
+                Ok(GqlExpr::Bool(word.eq_ignore_ascii_case("TRUE")))
+            }
+            Token::Word(word) if !is_keyword(&word) && self.lexemes[self.pos + 1].token == Token::Symbol("(") => {
+                self.next();
+                self.call(word, span)
+            }
+            Token::Word(_) | Token::Quoted(_) => {
+                let Some((name, span)) = self.variable() else { return Err(self.unexpected("an expression")) };
+                if !self.variables.contains_key(&name) && !self.aliases.contains(&name) {
+                    return Err(syntax_error(format!("unknown variable `{}`", name), span));
+                }
+                Ok(GqlExpr::Identifier(name))
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+
+    fn call(&mut self, name: String, span: Range<usize>) -> Result<GqlExpr> {
+        let lower = name.to_lowercase();
+        let Some(&(_, arity)) = FUNCTIONS.iter().find(|(function, _)| *function == lower) else {
+            return Err(syntax_error(format!("unknown function `{}`", name), span));
+        };
+        self.expect_symbol("(")?;
+        let mut args = Vec::new();
+        if !self.at_symbol(")") {
+            args.push(self.expr()?);
+            while self.eat_symbol(",") {
+                args.push(self.expr()?);
+            }
+        }
+        let close = self.expect_symbol(")")?;
+        if args.len() != arity {
+            return Err(syntax_error(
+                format!("`{}` takes {} argument{}, found {}", lower, arity, if arity == 1 { "" } else { "s" }, args.len()),
+                span.start..close.end,
+            ));
+        }
+        Ok(GqlExpr::FunctionCall(lower, args))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(n: f64) -> GqlExpr {
+        GqlExpr::Number(n)
+    }
+
+    fn prop(variable: &str, name: &str) -> GqlExpr {
+        GqlExpr::Property(Box::new(GqlExpr::Identifier(variable.to_string())), name.to_string())
+    }
+
+    fn bin(left: GqlExpr, op: BinaryOp, right: GqlExpr) -> GqlExpr {
+        GqlExpr::BinaryOp(Box::new(left), op, Box::new(right))
+    }
+
+    fn un(op: UnaryOp, operand: GqlExpr) -> GqlExpr {
+        GqlExpr::UnaryOp(op, Box::new(operand))
+    }
+
+    /// The expression `text` returns for a node `n`.
+    fn expr(text: &str) -> GqlExpr {
+        let statements = parse_query(&format!("MATCH (n) RETURN {}", text)).unwrap();
+        match statements.last() {
+            Some(GqlStatement::Return(items)) => items[0].expr.clone(),
+            other => panic!("expected RETURN, found {:?}", other),
+        }
+    }
+
+    /// The message of the syntax error in `query` and the text its span covers.
+    fn error(query: &str) -> (String, &str) {
+        match parse_query(query) {
+            Err(Error::GqlSyntax { message, span }) => (message, &query[span]),
+            other => panic!("expected a syntax error for {:?}, found {:?}", query, other),
+        }
+    }
+
+    fn error_at(query: &str) -> &str {
+        error(query).1
+    }
+
+    #[test]
+    fn operators_bind_by_precedence_and_to_the_left() {
+        assert_eq!(expr("1 + 2 * 3"), bin(num(1.0), BinaryOp::Plus, bin(num(2.0), BinaryOp::Mul, num(3.0))));
+        assert_eq!(expr("(1 + 2) * 3"), bin(bin(num(1.0), BinaryOp::Plus, num(2.0)), BinaryOp::Mul, num(3.0)));
+        assert_eq!(expr("1 - 2 - 3"), bin(bin(num(1.0), BinaryOp::Minus, num(2.0)), BinaryOp::Minus, num(3.0)));
+        assert_eq!(expr("8 / 4 / 2"), bin(bin(num(8.0), BinaryOp::Div, num(4.0)), BinaryOp::Div, num(2.0)));
+        assert_eq!(
+            expr("n.a + 1 < n.b * 2"),
+            bin(bin(prop("n", "a"), BinaryOp::Plus, num(1.0)), BinaryOp::Lt, bin(prop("n", "b"), BinaryOp::Mul, num(2.0))),
+        );
+        assert_eq!(
+            expr("n.a OR n.b AND n.c"),
+            bin(prop("n", "a"), BinaryOp::Or, bin(prop("n", "b"), BinaryOp::And, prop("n", "c"))),
+        );
+        assert_eq!(
+            expr("n.a = 1 AND n.b <> 2 OR n.c"),
+            bin(
+                bin(bin(prop("n", "a"), BinaryOp::Eq, num(1.0)), BinaryOp::And, bin(prop("n", "b"), BinaryOp::Ne, num(2.0))),
+                BinaryOp::Or,
+                prop("n", "c"),
+            ),
+        );
+    }
+
+    #[test]
+    fn not_binds_looser_than_comparisons_and_minus_tighter_than_products() {
+        let not_eq = un(UnaryOp::Not, bin(prop("n", "a"), BinaryOp::Eq, num(1.0)));
+        assert_eq!(expr("NOT n.a = 1"), not_eq);
+        assert_eq!(expr("NOT n.a = 1 AND n.b"), bin(not_eq, BinaryOp::And, prop("n", "b")));
+        assert_eq!(expr("NOT NOT n.a"), un(UnaryOp::Not, un(UnaryOp::Not, prop("n", "a"))));
+        assert_eq!(expr("-2 * 3"), bin(num(-2.0), BinaryOp::Mul, num(3.0)));
+        assert_eq!(expr("-n.a * 2"), bin(un(UnaryOp::Neg, prop("n", "a")), BinaryOp::Mul, num(2.0)));
+        assert_eq!(expr("- -n.a"), un(UnaryOp::Neg, un(UnaryOp::Neg, prop("n", "a"))));
+        assert_eq!(expr("1 - -1"), bin(num(1.0), BinaryOp::Minus, num(-1.0)));
+        // Printed expressions parse back to themselves.
+        for text in [
+            "1 + 2 * 3", "(1 + 2) * 3", "1 - (2 - 3)", "-n.a * 2", "-(n.a + 1)", "- -n.a", "n.a OR n.b AND n.c",
+            "(n.a OR n.b) AND n.c", "NOT n.a AND n.b", "NOT (n.a AND n.b)", "(NOT n.a) = n.b", "n.a = (NOT n.b)",
+        ] {
+            assert_eq!(expr(&expr(text).to_string()), expr(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn strings_unescape_and_quoted_names_do_not() {
+        let string = |text: &str| match expr(text) {
+            GqlExpr::String(s) => s,
+            other => panic!("expected a string, found {:?}", other),
+        };
+        assert_eq!(string(r"'it\'s'"), "it's");
+        assert_eq!(string(r#""say \"hi\"""#), "say \"hi\"");
+        // The other quote needs no escape.
+        assert_eq!(string(r#"'"'"#), "\"");
+        assert_eq!(string(r#""'""#), "'");
+        assert_eq!(string(r"'a\\b'"), r"a\b");
+        assert_eq!(string(r"'line\nbreak\ttab'"), "line\nbreak\ttab");
+        assert_eq!(string("'ü→'"), "ü→");
+        assert_eq!(expr(r"n.`odd\name`"), prop("n", r"odd\name"));
+        assert_eq!(expr("`n`.`RETURN`"), prop("n", "RETURN"));
+    }
+
+    #[test]
+    fn lexical_errors_point_at_the_offending_text() {
+        assert_eq!(error("MATCH (n) RETURN 'a\\qb'"), ("unknown escape `\\q`".to_string(), "\\q"));
+        assert_eq!(error_at("MATCH (n) RETURN 'é\\é'"), "\\é");
+        assert_eq!(error("MATCH (n) RETURN 'open"), ("unterminated string".to_string(), "'open"));
+        assert_eq!(error_at("MATCH (n) RETURN 'open\\"), "'open\\");
+        assert_eq!(error("MATCH (n) RETURN n.`open"), ("unterminated quoted name".to_string(), "`open"));
+        assert_eq!(error("MATCH (n) RETURN n.a % 2"), ("unexpected character `%`".to_string(), "%"));
+        assert_eq!(error_at("MATCH (n) RETURN n.a ≠ 2"), "≠");
+    }
+
+    #[test]
+    fn clause_errors_point_at_the_offending_token() {
+        assert_eq!(error("RETURN 1"), ("expected `MATCH`, found `RETURN`".to_string(), "RETURN"));
+        assert_eq!(error(""), ("expected `MATCH`, found the end of the query".to_string(), ""));
+        assert_eq!(error("OPTIONAL (n) RETURN n"), ("expected `MATCH`, found `(`".to_string(), "("));
+        assert_eq!(error("MATCH (n)"), ("expected `RETURN`, found the end of the query".to_string(), ""));
+        assert_eq!(error_at("MATCH (n) WHERE RETURN n"), "RETURN");
+        assert_eq!(error_at("MATCH (n) RETURN n ORDER n"), "n");
+        assert_eq!(error("MATCH (n) RETURN n LIMIT 1.5"), ("expected a non-negative integer, found a number".to_string(), "1.5"));
+        assert_eq!(error_at("MATCH (n) RETURN n LIMIT -1"), "-");
+        assert_eq!(error("MATCH (n) RETURN n SKIP n"), ("expected a non-negative integer, found `n`".to_string(), "n"));
+        assert_eq!(error_at("MATCH (n) RETURN n AS"), "");
+        assert_eq!(error("MATCH (n) RETURN n.a, n.a"), ("duplicate column `n.a`".to_string(), "n.a"));
+        assert_eq!(error_at("MATCH (n) RETURN n.a AS x, n.b AS x"), "n.b AS x");
+    }
+
+    #[test]
+    fn pattern_errors_point_at_the_offending_token() {
+        assert_eq!(error("MATCH n RETURN n"), ("expected `(`, found `n`".to_string(), "n"));
+        assert_eq!(error_at("MATCH (n RETURN n"), "RETURN");
+        assert_eq!(error("MATCH (n:) RETURN n"), ("expected a label, found `)`".to_string(), ")"));
+        assert_eq!(error_at("MATCH (n:A|) RETURN n"), ")");
+        assert_eq!(error_at("MATCH (n {a 1}) RETURN n"), "1");
+        assert_eq!(error_at("MATCH (n {: 1}) RETURN n"), ":");
+        assert_eq!(error_at("MATCH (n {a: 1) RETURN n"), ")");
+        assert_eq!(error("MATCH (n {a: 1, a: 2}) RETURN n"), ("duplicate property `a`".to_string(), "a"));
+        assert_eq!(error_at("MATCH (n)-[e:R RETURN n"), "RETURN");
+        assert_eq!(error_at("MATCH (n)-[e {a: 1}]->(m) RETURN n"), "{");
+        assert_eq!(error("MATCH (n)<-[e]->(m) RETURN n"), ("an edge cannot point both ways".to_string(), "<-[e]->"));
+        assert_eq!(error_at("MATCH (n)-[e]>(m) RETURN n"), ">");
+        assert_eq!(error_at("MATCH (n)--m RETURN n"), "m");
+        assert_eq!(error("MATCH (n)-[n]->(m) RETURN n"), ("`n` is already bound to a node".to_string(), "n"));
+        assert_eq!(error_at("MATCH (n)-[e]->(m), (e) RETURN n"), "e");
+    }
+
+    #[test]
+    fn expression_errors_point_at_the_offending_token() {
+        assert_eq!(error("MATCH (n) RETURN m"), ("unknown variable `m`".to_string(), "m"));
+        assert_eq!(error("MATCH (n) RETURN n.a = 1 = 2"), ("comparisons cannot be chained; join them with AND".to_string(), "="));
+        assert_eq!(error("MATCH (n) RETURN n."), ("expected a property name, found the end of the query".to_string(), ""));
+        assert_eq!(error_at("MATCH (n) RETURN (n.a"), "");
+        assert_eq!(error_at("MATCH (n) RETURN n.a +"), "");
+        assert_eq!(error("MATCH (n) RETURN ORDER"), ("expected an expression, found `ORDER`".to_string(), "ORDER"));
+        assert_eq!(error("MATCH (n) RETURN count(n)"), ("unknown function `count`".to_string(), "count"));
+        assert_eq!(error("MATCH (n) RETURN size(n, n)"), ("`size` takes 1 argument, found 2".to_string(), "size(n, n)"));
+        assert_eq!(error_at("MATCH (n) RETURN lower()"), "lower()");
+        assert_eq!(error_at("MATCH (n) RETURN id(n"), "");
+    }
+
+    #[test]
+    fn trailing_input_is_rejected() {
+        assert_eq!(error("MATCH (n) RETURN n n"), ("expected the end of the query, found `n`".to_string(), "n"));
+        assert_eq!(error_at("MATCH (n) RETURN n LIMIT 1 LIMIT 2"), "LIMIT");
+        assert_eq!(error_at("MATCH (n) RETURN n LIMIT 1 SKIP 2"), "SKIP");
+        assert_eq!(error_at("MATCH (n) RETURN n )"), ")");
+        assert_eq!(error_at("MATCH (n) RETURN n ORDER BY n DESC ASC"), "ASC");
+        assert_eq!(error_at("MATCH (n) RETURN n 'x'"), "'x'");
+        assert!(parse_query("MATCH (n) RETURN n LIMIT 1   ").is_ok());
+    }
+}
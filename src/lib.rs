@@ -42,4 +42,8 @@ pub enum Error {
 
     #[error("Storage error: {0}")]
     Storage(String),
+
+    /// A GQL query that does not parse; `span` is the byte range of the offending text.
+    #[error("GQL syntax error at {}..{}: {message}", span.start, span.end)]
+    GqlSyntax { message: String, span: std::ops::Range<usize> },
 }
@@ -3,10 +3,10 @@
 //! Execute JSON-based graph programs using the unified IR runtime.
 
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::{Parser, Subcommand};
-use eaf_ipg_runtime::{validator::validate, Error, engidb::EngiDB, Graph, Node, ui::UiTranspiler, server::start_server, wasm_transpiler::WasmTranspiler, gql::execute_gql_query};
+use eaf_ipg_runtime::{validator::validate, Error, engidb::{EngiDB, adapter::SledAdapter, diff::diff_graphs, gc::GcOptions, index::IndexDef, merge::MergeStrategy, migrate::MigrateOptions, transact::TxOp}, Graph, Node, ui::UiTranspiler, server::start_server, wasm_transpiler::WasmTranspiler, gql::execute_gql_query};
 use kotoba_types::UiProperties;
 use std::collections::HashMap;
 use indexmap::IndexMap;
@@ -15,6 +15,10 @@ use indexmap::IndexMap;
 #[command(name = "eaf-ipg")]
 #[command(about = "Kotoba - Language Graph Database")]
 struct Cli {
+    /// Named graph of the database to work on (defaults to the default graph)
+    #[arg(long, global = true)]
+    namespace: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -80,9 +84,161 @@ enum Commands {
         #[arg(short, long)]
         message: String,
 
-        /// Export mode: export JSON without execution
+        /// Print the input JSON without importing it (use `export` to read the database back)
         #[arg(long)]
         export: bool,
+
+        /// Use the parallel bulk loader (for large graphs)
+        #[arg(long)]
+        bulk: bool,
+    },
+    /// Materialise the graph stored at a commit or branch head
+    Checkout {
+        /// Branch name or commit CID
+        rev: String,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+        /// Output JSON file (optional, prints to stdout if not specified)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Export the graph held by the database as JSON
+    Export {
+        /// Branch name, commit CID or snapshot root CID (defaults to the uncommitted state)
+        rev: Option<String>,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+        /// Output JSON file (optional, prints to stdout if not specified)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Show a commit and a summary of its graph
+    Show {
+        /// Branch name or commit CID
+        #[arg(long, default_value = "main")]
+        at: String,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+    /// Show the commit history of a branch
+    Log {
+        /// Branch name or commit CID
+        #[arg(default_value = "main")]
+        rev: String,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+        /// Maximum number of commits to show
+        #[arg(short = 'n', long)]
+        max_count: Option<usize>,
+    },
+    /// List the named graphs of a database
+    Namespaces {
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+    /// Branch management commands
+    Branch {
+        #[command(subcommand)]
+        command: BranchCommands,
+    },
+    /// CAR file export and import of commit histories
+    Car {
+        #[command(subcommand)]
+        command: CarCommands,
+    },
+    /// Property index commands
+    Index {
+        #[command(subcommand)]
+        command: IndexCommands,
+    },
+    /// Remove IPLD blocks no longer reachable from the live graph or branch history
+    Gc {
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+        /// Keep only this many of the most recent commits of each branch
+        #[arg(long)]
+        retain_commits: Option<usize>,
+    },
+    /// Check stored blocks against their CIDs and the indexes against each other
+    Fsck {
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+    /// Migrate the database to the current on-disk format
+    Migrate {
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+        /// Only list the migration steps that would run
+        #[arg(long)]
+        dry_run: bool,
+        /// Copy the database to this new directory before migrating
+        #[arg(long)]
+        backup: Option<PathBuf>,
+    },
+    /// Three-way merge a branch or commit into a branch
+    Merge {
+        /// Branch name or commit CID to merge
+        from: String,
+        /// Branch to merge into
+        #[arg(long, default_value = "main")]
+        into: String,
+        /// Conflict resolution strategy (ours, theirs, manual)
+        #[arg(long, default_value = "manual")]
+        strategy: String,
+        /// Commit a manually resolved graph file as the merge result
+        #[arg(long)]
+        resolved: Option<PathBuf>,
+        /// Write the merged graph to a JSON file
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Commit author
+        #[arg(long, default_value = "kotoba-cli")]
+        author: String,
+        /// Commit message
+        #[arg(short, long)]
+        message: Option<String>,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+    /// Structural diff between two graph JSON files or two revisions
+    Diff {
+        /// Old graph file (or revision with --db)
+        old: String,
+        /// New graph file (or revision with --db)
+        new: String,
+        /// Compare revisions in this database instead of files
+        #[arg(long)]
+        db: Option<PathBuf>,
+        /// Output format (text, json)
+        #[arg(long, default_value = "text")]
+        format: String,
+    },
+    /// Show nodes as recorded up to a transaction and valid at a point in time
+    AsOf {
+        /// Transaction id (defaults to the latest)
+        #[arg(long)]
+        tx: Option<u64>,
+        /// Valid time in seconds since the epoch (defaults to now)
+        #[arg(long)]
+        valid_at: Option<u64>,
+        /// Only show this entity
+        #[arg(long)]
+        entity: Option<String>,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
     },
     /// Validate a JSON graph file
     Validate {
@@ -138,6 +294,114 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum BranchCommands {
+    /// List all branches
+    List {
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+    /// Create a new branch
+    Create {
+        /// Branch name
+        name: String,
+        /// Branch name or commit CID to start from
+        #[arg(long, default_value = "main")]
+        from: String,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+    /// Delete a branch
+    Delete {
+        /// Branch name
+        name: String,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+    /// Point a branch at another commit
+    Reset {
+        /// Branch name
+        name: String,
+        /// Branch name or commit CID to reset to
+        rev: String,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum CarCommands {
+    /// Write a commit and every block reachable from it to a CAR file
+    Export {
+        /// Branch name or commit CID
+        #[arg(default_value = "main")]
+        rev: String,
+        /// Output CAR file
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+    /// Read the blocks of a CAR file, checking each against its CID
+    Import {
+        /// CAR file to read
+        file: PathBuf,
+        /// Create a branch pointing at the file's root commit
+        #[arg(long)]
+        branch: Option<String>,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum IndexCommands {
+    /// List declared property indexes
+    List {
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+    /// Declare a property index and index the existing nodes
+    Create {
+        /// Index name
+        name: String,
+        /// Node kind to index
+        #[arg(long)]
+        kind: String,
+        /// Indexed property; repeat for a composite index
+        #[arg(short, long = "property")]
+        properties: Vec<String>,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+    /// Remove a property index
+    Drop {
+        /// Index name
+        name: String,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+    /// Find the nodes whose leading indexed properties equal the given values
+    Lookup {
+        /// Index name
+        name: String,
+        /// Property values as JSON (plain words are taken as strings)
+        values: Vec<String>,
+        /// Database path
+        #[arg(long, default_value = "todo.db")]
+        db: PathBuf,
+    },
+}
+
 #[derive(Subcommand)]
 enum UiCommands {
     /// Generate HTML from UI-IR
@@ -178,8 +442,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 async fn async_main(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let namespace = cli.namespace.as_deref();
     match cli.command {
-        Commands::Run { file, export, db, branch, author, message } => {
+        Commands::Run { file, export, bulk, db, branch, author, message } => {
             // Load JSON file
             let json_content = fs::read_to_string(&file)?;
 
@@ -192,11 +457,19 @@ async fn async_main(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
             let graph: Graph = serde_json::from_str(&json_content)?;
 
             // Open the database
-            let engidb = EngiDB::open(&db)?;
+            let engidb = open_db(&db, namespace)?;
 
             // Import the graph
             println!("Importing graph into database...");
-            engidb.import_graph(&graph)?;
+            if bulk {
+                engidb.bulk_import_graph(&graph, |p| {
+                    if p.done == p.total {
+                        eprintln!("  {:?}: {} done", p.phase, p.total);
+                    }
+                })?;
+            } else {
+                engidb.import_graph(&graph)?;
+            }
             println!("Import complete.");
 
             // Commit the changes
@@ -217,6 +490,293 @@ async fn async_main(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
             // println!("Execution completed successfully");
         }
 
+        Commands::Checkout { rev, db, output } => {
+            let engidb = open_db(&db, namespace)?;
+            let graph = engidb.checkout(&rev)?;
+            let json = serde_json::to_string_pretty(&graph)?;
+
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, &json)?;
+                    println!("✓ Graph at '{}' written to: {}", rev, path.display());
+                }
+                None => {
+                    println!("{}", json);
+                }
+            }
+        }
+
+        Commands::Export { rev, db, output } => {
+            let engidb = open_db(&db, namespace)?;
+            let graph = match &rev {
+                Some(rev) => engidb.export_graph(&engidb.resolve_root(rev)?)?,
+                None => engidb.graph_from_snapshot(&engidb.snapshot()?)?,
+            };
+            let json = serde_json::to_string_pretty(&graph)?;
+
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, &json)?;
+                    println!("✓ Exported {} nodes and {} edges to: {}", graph.node.len(), graph.edge.len(), path.display());
+                }
+                None => {
+                    println!("{}", json);
+                }
+            }
+        }
+
+        Commands::Show { at, db } => {
+            let engidb = open_db(&db, namespace)?;
+            let commit_cid = engidb.resolve(&at)?;
+            let commit = engidb.get_commit(&commit_cid)?;
+            let transaction = engidb.get_transaction(&commit)?;
+            let graph = match transaction.root {
+                Some(_) => Some(engidb.checkout(&commit_cid.to_string())?),
+                None => None,
+            };
+
+            println!("commit {}", commit_cid);
+            for parent in &commit.parents {
+                println!("parent {}", parent);
+            }
+            match transaction.root {
+                Some(root) => println!("root   {}", root),
+                None => println!("root   (none recorded)"),
+            }
+            println!("author {}", commit.author);
+            println!("time   {}", transaction.timestamp);
+            println!();
+            println!("    {}", commit.message);
+            println!();
+
+            let Some(graph) = graph else { return Ok(()) };
+            let mut kinds: std::collections::BTreeMap<&str, usize> = std::collections::BTreeMap::new();
+            for node in &graph.node {
+                *kinds.entry(node.kind.as_str()).or_default() += 1;
+            }
+            println!("{} nodes, {} edges", graph.node.len(), graph.edge.len());
+            for (kind, count) in kinds {
+                println!("  {:15} {}", kind, count);
+            }
+        }
+
+        Commands::Log { rev, db, max_count } => {
+            let engidb = open_db(&db, namespace)?;
+            let entries = engidb.log(&rev)?;
+
+            for (commit_cid, commit) in entries.iter().take(max_count.unwrap_or(usize::MAX)) {
+                println!("commit {}", commit_cid);
+                if commit.parents.len() > 1 {
+                    let parents: Vec<String> = commit.parents.iter().map(|p| p.to_string()).collect();
+                    println!("Merge: {}", parents.join(" "));
+                }
+                println!("Author: {}", commit.author);
+                println!();
+                println!("    {}", commit.message);
+                println!();
+            }
+        }
+
+        Commands::Namespaces { db } => {
+            let engidb = EngiDB::open(&db)?;
+            for name in engidb.namespaces()? {
+                println!("{}", name);
+            }
+        }
+
+        Commands::Branch { command } => {
+            match command {
+                BranchCommands::List { db } => {
+                    let engidb = open_db(&db, namespace)?;
+                    for (name, head) in engidb.list_branches()? {
+                        println!("{:20} {}", name, head);
+                    }
+                }
+                BranchCommands::Create { name, from, db } => {
+                    let engidb = open_db(&db, namespace)?;
+                    let head = engidb.create_branch(&name, &from)?;
+                    println!("✓ Created branch '{}' at {}", name, head);
+                }
+                BranchCommands::Delete { name, db } => {
+                    let engidb = open_db(&db, namespace)?;
+                    let head = engidb.delete_branch(&name)?;
+                    println!("✓ Deleted branch '{}' (was {})", name, head);
+                }
+                BranchCommands::Reset { name, rev, db } => {
+                    let engidb = open_db(&db, namespace)?;
+                    let head = engidb.reset_branch(&name, &rev)?;
+                    println!("✓ Branch '{}' now points at {}", name, head);
+                }
+            }
+        }
+
+        Commands::Gc { db, dry_run, retain_commits } => {
+            let engidb = open_db(&db, namespace)?;
+            let report = engidb.gc(&GcOptions { dry_run, retain_commits })?;
+            let verb = if dry_run { "Would remove" } else { "Removed" };
+            let commits = if report.collected_commits == 1 { "commit" } else { "commits" };
+            println!("{} {} of {} blocks ({} bytes), including {} {}",
+                verb, report.collected, report.blocks, report.collected_bytes, report.collected_commits, commits);
+        }
+
+        Commands::Fsck { db } => {
+            let engidb = open_db(&db, namespace)?;
+            let report = engidb.verify()?;
+            println!("Checked {} blocks, {} vertices, {} edges and {} commits",
+                report.blocks, report.vertices, report.edges, report.commits);
+            if !report.is_ok() {
+                for problem in &report.problems {
+                    eprintln!("  - {}", problem);
+                }
+                eprintln!("✗ Found {} problems", report.problems.len());
+                std::process::exit(1);
+            }
+            println!("✓ IPLD content verification passed");
+        }
+
+        Commands::Migrate { db, dry_run, backup } => {
+            let report = EngiDB::migrate(&db, &MigrateOptions { dry_run, backup })?;
+            if report.steps.is_empty() {
+                println!("✓ Database is at format version {}", report.from);
+            } else {
+                let verb = if dry_run { "Would migrate" } else { "Migrated" };
+                println!("{} from format version {} to {}:", verb, report.from, report.to);
+                for step in &report.steps {
+                    println!("  - {}", step);
+                }
+                if let Some(path) = &report.backup {
+                    println!("✓ Backup written to: {}", path.display());
+                }
+            }
+        }
+
+        Commands::Car { command } => {
+            match command {
+                CarCommands::Export { rev, output, db } => {
+                    let engidb = open_db(&db, namespace)?;
+                    let commit = engidb.resolve(&rev)?;
+                    let file = std::io::BufWriter::new(fs::File::create(&output)?);
+                    let blocks = engidb.export_car(&commit, file)?;
+                    println!("✓ Exported {} blocks of {} to: {}", blocks, commit, output.display());
+                }
+                CarCommands::Import { file, branch, db } => {
+                    let engidb = open_db(&db, namespace)?;
+                    let input = std::io::BufReader::new(fs::File::open(&file)?);
+                    let report = engidb.import_car(input, branch.as_deref())?;
+                    println!("✓ Imported {} blocks, including {} commits", report.blocks, report.commits);
+                    for root in &report.roots {
+                        println!("  root {}", root);
+                    }
+                    if let Some(name) = branch {
+                        println!("✓ Created branch '{}' at {}", name, report.roots[0]);
+                    }
+                }
+            }
+        }
+
+        Commands::Index { command } => {
+            match command {
+                IndexCommands::List { db } => {
+                    let engidb = open_db(&db, namespace)?;
+                    for index in engidb.list_indexes()? {
+                        println!("{:20} {}({})", index.name, index.kind, index.properties.join(", "));
+                    }
+                }
+                IndexCommands::Create { name, kind, properties, db } => {
+                    let engidb = open_db(&db, namespace)?;
+                    engidb.create_index(IndexDef { name: name.clone(), kind, properties })?;
+                    println!("✓ Created index '{}'", name);
+                }
+                IndexCommands::Drop { name, db } => {
+                    let engidb = open_db(&db, namespace)?;
+                    engidb.drop_index(&name)?;
+                    println!("✓ Dropped index '{}'", name);
+                }
+                IndexCommands::Lookup { name, values, db } => {
+                    let engidb = open_db(&db, namespace)?;
+                    let values: Vec<serde_json::Value> = values.iter()
+                        .map(|v| serde_json::from_str(v).unwrap_or_else(|_| serde_json::Value::String(v.clone())))
+                        .collect();
+                    for node in engidb.lookup_index(&name, &values)? {
+                        println!("{}", serde_json::to_string(&node)?);
+                    }
+                }
+            }
+        }
+
+        Commands::Merge { from, into, strategy, resolved, output, author, message, db } => {
+            let engidb = open_db(&db, namespace)?;
+            let message = message.unwrap_or_else(|| format!("Merge '{}' into '{}'", from, into));
+
+            if let Some(path) = resolved {
+                let graph: Graph = serde_json::from_str(&fs::read_to_string(&path)?)?;
+                let commit_cid = engidb.commit_merge(&into, &from, &graph, author, message)?;
+                println!("✓ Committed resolved merge with CID: {}", commit_cid);
+                return Ok(());
+            }
+
+            let strategy: MergeStrategy = strategy.parse()?;
+            let outcome = engidb.merge(&into, &from, strategy, author, message)?;
+
+            for conflict in &outcome.conflicts {
+                println!("CONFLICT {}", serde_json::to_string(conflict)?);
+            }
+            if let Some(path) = output {
+                std::fs::write(&path, serde_json::to_string_pretty(&outcome.graph)?)?;
+                println!("✓ Merged graph written to: {}", path.display());
+            }
+
+            match outcome.commit {
+                Some(commit_cid) if outcome.fast_forward => println!("✓ Fast-forwarded '{}' to {}", into, commit_cid),
+                Some(commit_cid) => println!("✓ Merged with CID: {} ({} conflicts resolved)", commit_cid, outcome.conflicts.len()),
+                None if outcome.conflicts.is_empty() => println!("Already up to date."),
+                None => {
+                    eprintln!("✗ Merge has {} conflicts; resolve them and commit with --resolved", outcome.conflicts.len());
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Commands::Diff { old, new, db, format } => {
+            let diff = match db {
+                Some(db) => open_db(&db, namespace)?.diff(&old, &new)?,
+                None => {
+                    let old_graph: Graph = serde_json::from_str(&fs::read_to_string(&old)?)?;
+                    let new_graph: Graph = serde_json::from_str(&fs::read_to_string(&new)?)?;
+                    diff_graphs(&old_graph, &new_graph)
+                }
+            };
+
+            match format.as_str() {
+                "json" => {
+                    println!("{}", serde_json::to_string_pretty(&diff)?);
+                }
+                "text" => {
+                    print!("{}", diff);
+                }
+                _ => {
+                    return Err(Box::new(Error::Validation(format!("Unknown format: {}", format))));
+                }
+            }
+        }
+
+        Commands::AsOf { tx, valid_at, entity, db } => {
+            let engidb = open_db(&db, namespace)?;
+            let mut view = engidb.view();
+            if let Some(tx) = tx {
+                view = view.as_of(tx);
+            }
+            if let Some(valid_at) = valid_at {
+                view = view.valid_at(valid_at);
+            }
+
+            let nodes = match entity {
+                Some(entity) => view.entity(&entity)?.into_iter().collect(),
+                None => view.nodes()?,
+            };
+            println!("{}", serde_json::to_string_pretty(&nodes)?);
+        }
+
         Commands::Validate { file } => {
             let json_content = fs::read_to_string(&file)?;
             let graph: Graph = serde_json::from_str(&json_content)?;
@@ -246,7 +806,7 @@ async fn async_main(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
             match command {
                 TodoCommands::Add { title, description, db } => {
                     println!("Adding todo: {}", title);
-                    add_todo(&db, &title, description.as_deref())?;
+                    add_todo(&db, &title, description.as_deref(), namespace)?;
                     println!("✓ Todo added successfully!");
                 }
                 TodoCommands::List { db } => {
@@ -255,12 +815,12 @@ async fn async_main(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
                 }
                 TodoCommands::Complete { id, db } => {
                     println!("Completing todo #{}", id);
-                    complete_todo(&db, id)?;
+                    complete_todo(&db, id, namespace)?;
                     println!("✓ Todo #{} marked as completed!", id);
                 }
                 TodoCommands::Delete { id, db } => {
                     println!("Deleting todo #{}", id);
-                    delete_todo(&db, id)?;
+                    delete_todo(&db, id, namespace)?;
                     println!("✓ Todo #{} deleted!", id);
                 }
             }
@@ -269,7 +829,7 @@ async fn async_main(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
         Commands::Ui { command } => {
             match command {
                 UiCommands::Generate { view_id, db, output } => {
-                    let transpiler = UiTranspiler::new(&db)?;
+                    let transpiler = UiTranspiler::new(SledAdapter::new(open_db(&db, namespace)?));
                     let html = transpiler.transpile_to_html(&view_id)?;
 
                     match output {
@@ -290,7 +850,7 @@ async fn async_main(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
             println!("📊 Database: {}", db.display());
             println!("🚀 Port: {}", port);
 
-            start_server(db, port).await?;
+            start_server(db, port, namespace).await?;
         }
 
         Commands::Wasm { command } => {
@@ -325,8 +885,14 @@ async fn async_main(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
         Commands::Gql { query, db, format } => {
             println!("🔍 Executing GQL query: {}", query);
 
-            let engidb = EngiDB::open(&db)?;
-            let result = execute_gql_query(&engidb, &query)?;
+            let adapter = SledAdapter::new(open_db(&db, namespace)?);
+            let result = match execute_gql_query(&adapter, &query) {
+                Err(Error::GqlSyntax { message, span }) => {
+                    print_gql_syntax_error(&query, &message, span);
+                    std::process::exit(1);
+                }
+                result => result?,
+            };
 
             match format.as_str() {
                 "json" => {
@@ -345,6 +911,17 @@ async fn async_main(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Print the line of a GQL query holding a syntax error, with the error underlined
+fn print_gql_syntax_error(query: &str, message: &str, span: std::ops::Range<usize>) {
+    let line_start = query[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = query[span.start..].find('\n').map_or(query.len(), |i| span.start + i);
+    let indent = query[line_start..span.start].chars().count();
+    let width = query[span.start..span.end.min(line_end)].chars().count().max(1);
+    eprintln!("✗ GQL syntax error: {}", message);
+    eprintln!("  {}", &query[line_start..line_end]);
+    eprintln!("  {}{}", " ".repeat(indent), "^".repeat(width));
+}
+
 /// Print GQL result as a formatted table
 fn print_gql_result_as_table(result: &eaf_ipg_runtime::gql::GqlResult) {
     if result.rows.is_empty() {
@@ -466,9 +1043,18 @@ fn create_mock_todo_ui_nodes_for_wasm() -> Vec<Node> {
     ]
 }
 
+/// Opens the database at `path` on the graph selected by `--namespace`.
+fn open_db(path: &Path, namespace: Option<&str>) -> Result<EngiDB, Error> {
+    let engidb = EngiDB::open(path)?;
+    match namespace {
+        Some(name) => Ok(engidb.namespace(name)?),
+        None => Ok(engidb),
+    }
+}
+
 // Todo app functions using EngiDB
-fn add_todo(db_path: &PathBuf, title: &str, description: Option<&str>) -> Result<(), Error> {
-    let engidb = EngiDB::open(db_path)?;
+fn add_todo(db_path: &PathBuf, title: &str, description: Option<&str>, namespace: Option<&str>) -> Result<(), Error> {
+    let engidb = open_db(db_path, namespace)?;
 
     // Generate a simple ID based on current timestamp
     let id = std::time::SystemTime::now()
@@ -509,18 +1095,21 @@ fn list_todos(db_path: &PathBuf) -> Result<(), Error> {
     Ok(())
 }
 
-fn complete_todo(db_path: &PathBuf, id: u64) -> Result<(), Error> {
-    let engidb = EngiDB::open(db_path)?;
-    // TODO: Implement completion logic when full query support is available
-    println!("✅ Todo completion will be implemented with full EngiDB query capabilities");
+fn complete_todo(db_path: &PathBuf, id: u64, namespace: Option<&str>) -> Result<(), Error> {
+    let engidb = open_db(db_path, namespace)?;
+    let entity = format!("todo_{}", id);
+    let now = chrono::Utc::now().to_rfc3339();
+    engidb.transact(&[
+        TxOp::Assert { entity: entity.clone(), attribute: "completed".to_string(), value: serde_json::json!(true) },
+        TxOp::Assert { entity, attribute: "updated_at".to_string(), value: serde_json::json!(now) },
+    ])?;
     engidb.commit("main", "todo-cli".to_string(), format!("Complete todo: {}", id))?;
     Ok(())
 }
 
-fn delete_todo(db_path: &PathBuf, id: u64) -> Result<(), Error> {
-    let engidb = EngiDB::open(db_path)?;
-    // TODO: Implement deletion logic when full query support is available
-    println!("🗑️  Todo deletion will be implemented with full EngiDB query capabilities");
+fn delete_todo(db_path: &PathBuf, id: u64, namespace: Option<&str>) -> Result<(), Error> {
+    let engidb = open_db(db_path, namespace)?;
+    engidb.transact(&[TxOp::RetractEntity { entity: format!("todo_{}", id) }])?;
     engidb.commit("main", "todo-cli".to_string(), format!("Delete todo: {}", id))?;
     Ok(())
 }
@@ -1,11 +1,12 @@
 //! HTTP API Server for Kotoba
 //!
 //! Provides REST API endpoints for the Todo application,
-//! connecting HTMX frontend with EngiDB backend.
+//! connecting HTMX frontend with a `GraphAdapter` storage backend
+//! (EngiDB by default).
 //!
 //! Pure Rust implementation using Axum/Hyper.
 
-use crate::{engidb::EngiDB, Error, Result, realtime::{create_event_broadcaster, broadcast_event, RealtimeEvent}};
+use crate::{engidb::{adapter::{GraphAdapter, SledAdapter}, scan::NodeFilter, transact::TxOp}, Error, Result, realtime::{create_event_broadcaster, broadcast_event, RealtimeEvent}};
 use axum::{
     extract::{Form, Path, State},
     http::StatusCode,
@@ -23,12 +24,21 @@ use futures::stream::{self, Stream};
 use std::sync::Arc;
 
 /// Shared application state
-#[derive(Clone)]
-pub struct AppState {
-    pub engidb: Arc<EngiDB>,
+pub struct AppState<A> {
+    pub adapter: Arc<A>,
     pub event_broadcaster: crate::realtime::EventBroadcaster,
 }
 
+// Derived `Clone` would require `A: Clone`; only the `Arc` is cloned.
+impl<A> Clone for AppState<A> {
+    fn clone(&self) -> Self {
+        AppState {
+            adapter: Arc::clone(&self.adapter),
+            event_broadcaster: self.event_broadcaster.clone(),
+        }
+    }
+}
+
 /// Todo item representation for API
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TodoItem {
@@ -47,20 +57,29 @@ pub struct CreateTodoRequest {
     pub description: Option<String>,
 }
 
-/// Start the HTTP server
-pub async fn start_server(db_path: PathBuf, port: u16) -> Result<()> {
-    let engidb = EngiDB::open(&db_path)?;
+/// Start the HTTP server on the EngiDB database at `db_path`, serving the
+/// named graph `namespace` or the default graph
+pub async fn start_server(db_path: PathBuf, port: u16, namespace: Option<&str>) -> Result<()> {
+    let mut adapter = SledAdapter::open(&db_path)?;
+    if let Some(name) = namespace {
+        adapter = adapter.namespace(name)?;
+    }
+    println!("📊 Database: {}", db_path.display());
+    serve(adapter, port).await
+}
+
+/// Start the HTTP server on any storage backend
+pub async fn serve<A: GraphAdapter + Send + Sync + 'static>(adapter: A, port: u16) -> Result<()> {
     let event_broadcaster = create_event_broadcaster();
 
     let app_state = AppState {
-        engidb: Arc::new(engidb),
+        adapter: Arc::new(adapter),
         event_broadcaster: event_broadcaster.clone(),
     };
 
     let app = build_router(app_state);
 
     println!("🚀 Starting Kotoba API Server on port {}", port);
-    println!("📊 Database: {}", db_path.display());
     println!("🌐 API endpoints:");
     println!("  POST /api/todo/add     - Add new todo");
     println!("  GET  /api/todo/list    - List all todos");
@@ -80,16 +99,16 @@ pub async fn start_server(db_path: PathBuf, port: u16) -> Result<()> {
 }
 
 /// Build the application router
-fn build_router(state: AppState) -> Router {
+fn build_router<A: GraphAdapter + Send + Sync + 'static>(state: AppState<A>) -> Router {
     Router::new()
         .route("/", get(index))
         .route("/app", get(todo_app))
-        .route("/api/todo/add", post(add_todo))
-        .route("/api/todo/list", get(list_todos))
-        .route("/api/todo/:id/complete", post(complete_todo))
-        .route("/api/todo/:id", delete(delete_todo))
-        .route("/ws", get(ws_handler))
-        .route("/events", get(sse_handler))
+        .route("/api/todo/add", post(add_todo::<A>))
+        .route("/api/todo/list", get(list_todos::<A>))
+        .route("/api/todo/:id/complete", post(complete_todo::<A>))
+        .route("/api/todo/:id", delete(delete_todo::<A>))
+        .route("/ws", get(ws_handler::<A>))
+        .route("/events", get(sse_handler::<A>))
         .nest_service("/static", tower_http::services::ServeDir::new("examples"))
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
@@ -137,8 +156,8 @@ async fn todo_app() -> Html<&'static str> {
 }
 
 /// Add a new todo item
-async fn add_todo(
-    State(state): State<AppState>,
+async fn add_todo<A: GraphAdapter + Send + Sync + 'static>(
+    State(state): State<AppState<A>>,
     Form(req): Form<CreateTodoRequest>,
 ) -> impl IntoResponse {
     println!("📝 Adding todo: {}", req.title);
@@ -170,13 +189,13 @@ async fn add_todo(
         },
     };
 
-    // Store in EngiDB
-    match state.engidb.store_todo_item(&todo_node) {
+    // Store through the adapter
+    match state.adapter.add_vertex(&todo_node) {
         Ok(_) => {
-            println!("✅ Todo stored in EngiDB: {} (ID: {})", req.title, id);
+            println!("✅ Todo stored: {} (ID: {})", req.title, id);
 
             // Commit the change
-            let _ = state.engidb.commit("main", "api-server".to_string(), format!("Add todo: {}", req.title));
+            let _ = state.adapter.commit("main", "api-server".to_string(), format!("Add todo: {}", req.title));
 
             // Broadcast real-time event
             let _ = broadcast_event(&state.event_broadcaster, RealtimeEvent::TodoAdded {
@@ -210,12 +229,14 @@ async fn add_todo(
 }
 
 /// List all todo items (HTMX HTML response)
-async fn list_todos(State(state): State<AppState>) -> impl IntoResponse {
+async fn list_todos<A: GraphAdapter + Send + Sync + 'static>(State(state): State<AppState<A>>) -> impl IntoResponse {
     println!("📋 Listing todos for HTMX");
 
-    // Query todos from EngiDB
-    match state.engidb.scan_todo_items() {
-        Ok(nodes) => {
+    // Query todos from the backend
+    let filter = NodeFilter { kind: Some("TodoItem".to_string()), ..NodeFilter::default() };
+    match state.adapter.scan_nodes(&filter) {
+        Ok(page) => {
+            let nodes = page.nodes;
             println!("✅ Found {} todo nodes", nodes.len());
 
             // Convert nodes to TodoItems
@@ -305,33 +326,70 @@ fn html_escape(s: &str) -> String {
 }
 
 /// Mark a todo as completed
-async fn complete_todo(
+async fn complete_todo<A: GraphAdapter + Send + Sync + 'static>(
     Path(id): Path<u64>,
-    State(state): State<AppState>,
+    State(state): State<AppState<A>>,
 ) -> impl IntoResponse {
     println!("✅ Completing todo #{}", id);
 
-    // For HTMX, we just return success without content
-    // The checkbox state change is handled client-side
-    (StatusCode::OK, "")
+    let entity = format!("todo_{}", id);
+    let now = chrono::Utc::now().to_rfc3339();
+    let ops = [
+        TxOp::Assert { entity: entity.clone(), attribute: "completed".to_string(), value: serde_json::json!(true) },
+        TxOp::Assert { entity, attribute: "updated_at".to_string(), value: serde_json::json!(now) },
+    ];
+    match state.adapter.transact(&ops) {
+        Ok(_) => {
+            let _ = state.adapter.commit("main", "api-server".to_string(), format!("Complete todo: {}", id));
+            let _ = broadcast_event(&state.event_broadcaster, RealtimeEvent::TodoCompleted { id });
+
+            // For HTMX, we just return success without content
+            // The checkbox state change is handled client-side
+            (StatusCode::OK, String::new())
+        }
+        Err(e) => {
+            eprintln!("❌ Failed to complete todo: {}", e);
+            (todo_error_status(&e), e.to_string())
+        }
+    }
 }
 
 /// Delete a todo item
-async fn delete_todo(
+async fn delete_todo<A: GraphAdapter + Send + Sync + 'static>(
     Path(id): Path<u64>,
-    State(state): State<AppState>,
+    State(state): State<AppState<A>>,
 ) -> impl IntoResponse {
     println!("🗑️ Deleting todo #{}", id);
 
-    // For HTMX, we return empty content to remove the element
-    // The hx-target="closest div" and hx-swap="outerHTML" will remove the todo item
-    (StatusCode::OK, "")
+    match state.adapter.delete_node(&format!("todo_{}", id)) {
+        Ok(()) => {
+            let _ = state.adapter.commit("main", "api-server".to_string(), format!("Delete todo: {}", id));
+            let _ = broadcast_event(&state.event_broadcaster, RealtimeEvent::TodoDeleted { id });
+
+            // For HTMX, we return empty content to remove the element
+            // The hx-target="closest div" and hx-swap="outerHTML" will remove the todo item
+            (StatusCode::OK, String::new())
+        }
+        Err(e) => {
+            eprintln!("❌ Failed to delete todo: {}", e);
+            (todo_error_status(&e), e.to_string())
+        }
+    }
+}
+
+/// Maps a storage error of a todo update to an HTTP status.
+fn todo_error_status(error: &crate::engidb::Error) -> StatusCode {
+    match error {
+        crate::engidb::Error::NotFound(_) => StatusCode::NOT_FOUND,
+        crate::engidb::Error::Transaction(_) => StatusCode::CONFLICT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
 }
 
 /// WebSocket handler for real-time updates
-async fn ws_handler(
+async fn ws_handler<A: GraphAdapter + Send + Sync + 'static>(
     ws: axum::extract::ws::WebSocketUpgrade,
-    State(state): State<AppState>,
+    State(state): State<AppState<A>>,
 ) -> impl IntoResponse {
     ws.on_upgrade(move |socket| handle_socket(socket, state.event_broadcaster))
 }
@@ -416,8 +474,8 @@ async fn handle_socket(mut socket: axum::extract::ws::WebSocket, broadcaster: cr
 }
 
 /// Server-Sent Events handler for older browsers
-async fn sse_handler(
-    State(state): State<AppState>,
+async fn sse_handler<A: GraphAdapter + Send + Sync + 'static>(
+    State(state): State<AppState<A>>,
 ) -> Sse<impl Stream<Item = Result<axum::response::sse::Event>>> {
     let mut rx = state.event_broadcaster.subscribe();
 
@@ -2,41 +2,68 @@
 //!
 //! Converts UI-IR graphs to HTML + Tailwind CSS + HTMX
 
-use crate::{engidb::EngiDB, Error, Result};
+use crate::{engidb::adapter::{GraphAdapter, SledAdapter}, Error, Result};
 use kotoba_types::{Node, UiProperties};
 use std::path::Path;
 
 /// UI Transpiler
-pub struct UiTranspiler {
-    engidb: EngiDB,
+pub struct UiTranspiler<A> {
+    adapter: A,
 }
 
-impl UiTranspiler {
-    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
-        let engidb = EngiDB::open(db_path)?;
-        Ok(UiTranspiler { engidb })
+impl UiTranspiler<SledAdapter> {
+    /// Opens a transpiler on the EngiDB database at `db_path`.
+    pub fn open<P: AsRef<Path>>(db_path: P) -> Result<Self> {
+        Ok(UiTranspiler::new(SledAdapter::open(db_path)?))
+    }
+}
+
+impl<A: GraphAdapter> UiTranspiler<A> {
+    pub fn new(adapter: A) -> Self {
+        UiTranspiler { adapter }
     }
 
     /// Transpile UI-IR to HTML + Tailwind + HTMX
     pub fn transpile_to_html(&self, view_id: &str) -> Result<String> {
-        let ui_nodes = self.collect_ui_nodes()?;
+        let ui_nodes = self.collect_ui_nodes(view_id)?;
         let root_node = ui_nodes.iter()
             .find(|n| n.id == view_id)
             .ok_or_else(|| Error::Validation(format!("View '{}' not found", view_id)))?;
 
-        let html = self.node_to_html(root_node, &ui_nodes)?;
+        let html = self.node_to_html(root_node, &ui_nodes, &mut Vec::new())?;
         let full_html = self.wrap_with_template(&html);
 
         Ok(full_html)
     }
 
-    fn collect_ui_nodes(&self) -> Result<Vec<Node>> {
-        // For now, return mock UI nodes since full query isn't implemented
-        // In the future, this will query EngiDB for UiNodeType nodes
-        Ok(self.create_mock_todo_ui())
+    /// Loads the view and every node below it through its `children` from the
+    /// adapter. Stores without the view get the built-in todo UI.
+    fn collect_ui_nodes(&self, view_id: &str) -> Result<Vec<Node>> {
+        if self.adapter.get_node(view_id)?.is_none() {
+            return Ok(self.create_mock_todo_ui());
+        }
+        let mut nodes: Vec<Node> = Vec::new();
+        let mut pending = vec![view_id.to_string()];
+        while let Some(id) = pending.pop() {
+            if nodes.iter().any(|n| n.id == id) {
+                continue;
+            }
+            // Missing children are skipped when rendering, as in the mock UI.
+            let Some(node) = self.adapter.get_node(&id)? else { continue };
+            if let Some(children) = node.properties.get("children").and_then(|c| c.as_array()) {
+                pending.extend(children.iter().filter_map(|c| c.as_str()).map(str::to_string));
+            }
+            nodes.push(node);
+        }
+        Ok(nodes)
     }
 
-    fn node_to_html(&self, node: &Node, all_nodes: &[Node]) -> Result<String> {
+    /// Renders `node` and its children; `ancestors` holds the ids of the
+    /// nodes being rendered above it, so stored cycles fail instead of recursing.
+    fn node_to_html(&self, node: &Node, all_nodes: &[Node], ancestors: &mut Vec<String>) -> Result<String> {
+        if ancestors.contains(&node.id) {
+            return Err(Error::Validation(format!("UI node '{}' is its own descendant", node.id)));
+        }
         // Parse UI properties from node
         let ui_props: UiProperties = serde_json::from_value(
             serde_json::to_value(&node.properties)
@@ -75,8 +102,10 @@ impl UiTranspiler {
         // Children
         for child_id in &ui_props.children {
             if let Some(child_node) = all_nodes.iter().find(|n| n.id == *child_id) {
-                let child_html = self.node_to_html(child_node, all_nodes)?;
-                html.push_str(&child_html);
+                ancestors.push(node.id.clone());
+                let child_html = self.node_to_html(child_node, all_nodes, ancestors);
+                ancestors.pop();
+                html.push_str(&child_html?);
             }
         }
 
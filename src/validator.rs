@@ -136,8 +136,8 @@ fn check_phi_arity_and_preds(graph: &Graph) -> Result<(), Error> {
             continue;
         }
 
-        // Find all arg edges connected to this phi
-        let arg_edges: Vec<_> = graph.edge_incidences(&node.id).iter()
+        // Find all arg edges leading into this phi
+        let arg_edges: Vec<_> = graph.node_incidences(&node.id).iter()
             .filter_map(|inc| {
                 if inc.role == "target" {
                     graph.get_edge(&inc.edge)
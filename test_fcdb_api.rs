@@ -5,11 +5,10 @@ use fcdb_graph::GraphDB;
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Testing FCDB API...");
 
-    // Based on FCDB documentation, PackCAS likely needs a path
     println!("Testing PackCAS initialization with path...");
 
-    let cas = PackCAS::new(std::path::PathBuf::from("./test_fcdb_db")).await?;
-    println!("PackCAS created with path");
+    let cas = PackCAS::open(std::path::PathBuf::from("./test_fcdb_db")).await?;
+    println!("PackCAS opened with path");
 
     // GraphDB::new should return GraphDB directly, not Result
     println!("Testing GraphDB initialization...");